@@ -8,7 +8,6 @@ struct ServerInfo {
     service: String,
     ip: String,
     port: u64,
-    key: String,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -58,7 +57,6 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                                         service: service.to_string(),
                                         ip: ip.to_string(),
                                         port,
-                                        key: key.to_string(),
                                     };
                                     
                                     servers.insert(server_id.clone(), server_info);
@@ -112,7 +110,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     if invalid_servers > 0 {
         println!("   ❌ Invalid/unauthorized servers: {}", invalid_servers);
     }
-    println!("");
+    println!();
     
     if servers.is_empty() {
         println!("❌ No valid servers found. Make sure:");
@@ -126,7 +124,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut service_groups: HashMap<String, Vec<&ServerInfo>> = HashMap::new();
     for server in servers.values() {
         service_groups.entry(server.service.clone())
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(server);
     }
     
@@ -142,7 +140,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
     
-    println!("");
+    println!();
     println!("💡 Tips:");
     println!("   • Use any of the URLs above to connect to the servers");
     println!("   • Production servers are typically more stable");
@@ -150,7 +148,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     // Health check suggestion
     if let Some(first_server) = servers.values().next() {
-        println!("");
+        println!();
         println!("🧪 Quick health check:");
         println!("   curl http://{}:{}/health", first_server.ip, first_server.port);
     }
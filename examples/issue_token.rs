@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+use rustlm_server::auth::Authenticator;
+use rustlm_server::config::{Cli, Config};
+
+/// Issue a bearer token signed with the server's `auth.token_secret`.
+///
+/// Usage: cargo run --example issue_token -- <subject> [hours] [task,category,...]
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let subject = args
+        .first()
+        .ok_or("Usage: issue_token <subject> [hours] [task,category,...]")?;
+    let hours: u64 = args.get(1).map(|h| h.parse()).transpose()?.unwrap_or(24);
+    let tasks = args
+        .get(2)
+        .map(|list| list.split(',').map(|t| t.trim().to_string()).collect());
+
+    // Reads rustlm.toml and RUSTLM_AUTH_TOKEN_SECRET like the server does
+    let config = Config::load(&Cli::default())?;
+    if config.auth.token_secret.is_none() {
+        return Err("Set auth.token_secret or RUSTLM_AUTH_TOKEN_SECRET first".into());
+    }
+
+    let authenticator = Authenticator::from_config(&config.auth);
+    let token = authenticator.issue_token(subject, tasks, Duration::from_secs(hours * 3600))?;
+    println!("{}", token);
+    Ok(())
+}
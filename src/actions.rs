@@ -0,0 +1,350 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::auth::Principal;
+use crate::config::{ActionsConfig, ExecutorConfig};
+use crate::error::ProcessError;
+use crate::executor::{Execution, SandboxExecutor};
+use crate::platform::Platform;
+use crate::shell::ShellCommand;
+
+/// Minutes a used or expired token is remembered after it expires, so replays are reported
+const SPENT_RETENTION_MINUTES: i64 = 10;
+
+/// A suggested command that can be run by confirming its token
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ActionToken {
+    pub token: String,
+    /// The command as it would be typed into a shell
+    pub command: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A suggested command waiting for confirmation
+#[derive(Debug, Clone)]
+pub struct PendingAction {
+    pub token: String,
+    /// The `/process` request the command was suggested in
+    pub request_id: Uuid,
+    pub task: String,
+    pub input_text: String,
+    pub command: ShellCommand,
+    /// Only this client may confirm the action
+    pub client_id: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// What happened when a token was presented
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Confirmed,
+    /// The token had expired
+    Expired,
+    /// The token had already been confirmed
+    Replayed,
+    /// Another client presented the token
+    WrongClient,
+    /// The client may no longer run the task the command was suggested by; the token stays usable
+    Forbidden,
+}
+
+/// An attempt to confirm an action, kept in the audit log
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    pub token: String,
+    pub request_id: Uuid,
+    pub task: String,
+    pub command: String,
+    /// Name of the caller who presented the token
+    pub principal: String,
+    pub client_id: String,
+    pub outcome: AuditOutcome,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+struct ActionState {
+    pending: HashMap<String, PendingAction>,
+    /// Confirmed and expired actions, kept to recognize replays until they're pruned
+    spent: HashMap<String, (PendingAction, AuditOutcome)>,
+    audit: VecDeque<AuditEntry>,
+}
+
+/// Issues single-use tokens for suggested commands and checks them when they're confirmed
+pub struct ActionStore {
+    enabled: bool,
+    ttl: chrono::Duration,
+    max_audit_entries: usize,
+    /// Runs confirmed commands when set; otherwise they're only handed to callbacks
+    executor: Option<SandboxExecutor>,
+    state: Mutex<ActionState>,
+}
+
+impl ActionStore {
+    /// A store that issues no tokens
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ttl: chrono::Duration::zero(),
+            max_audit_entries: 0,
+            executor: None,
+            state: Mutex::new(ActionState::default()),
+        }
+    }
+
+    pub fn from_config(config: &ActionsConfig, executor: &ExecutorConfig) -> Result<Self> {
+        if !config.enabled {
+            return Ok(Self::disabled());
+        }
+        let executor = if config.execute { Some(SandboxExecutor::new(executor)?) } else { None };
+        Ok(Self {
+            enabled: true,
+            ttl: chrono::Duration::seconds(config.ttl_secs as i64),
+            max_audit_entries: config.max_audit_entries,
+            executor,
+            state: Mutex::new(ActionState::default()),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Issue a token for each command suggested in response to `request_id`, shown quoted for
+    /// `platform`'s shell
+    pub fn issue(
+        &self,
+        request_id: Uuid,
+        task: &str,
+        input_text: &str,
+        client_id: &str,
+        commands: &[ShellCommand],
+        platform: Option<Platform>,
+    ) -> Vec<ActionToken> {
+        if !self.enabled || commands.is_empty() {
+            return Vec::new();
+        }
+        let now = Utc::now();
+        let expires_at = now + self.ttl;
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        self.prune(&mut state, now);
+
+        commands
+            .iter()
+            .map(|command| {
+                let token = Uuid::new_v4().simple().to_string();
+                state.pending.insert(
+                    token.clone(),
+                    PendingAction {
+                        token: token.clone(),
+                        request_id,
+                        task: task.to_string(),
+                        input_text: input_text.to_string(),
+                        command: command.clone(),
+                        client_id: client_id.to_string(),
+                        expires_at,
+                    },
+                );
+                ActionToken {
+                    token,
+                    command: platform.map_or_else(|| command.render(), |platform| command.render_for(platform)),
+                    expires_at,
+                }
+            })
+            .collect()
+    }
+
+    /// Use up a token presented by `principal`, returning the action it stands for. The token is
+    /// only used up if `may_run` allows the task that suggested the command.
+    pub fn confirm(
+        &self,
+        token: &str,
+        principal: &Principal,
+        may_run: impl FnOnce(&str) -> bool,
+    ) -> Result<PendingAction, ProcessError> {
+        let now = Utc::now();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        self.prune(&mut state, now);
+
+        if let Some((action, outcome)) = state.spent.get(token).cloned() {
+            let (attempt, error) = match outcome {
+                AuditOutcome::Expired => (outcome, ProcessError::Expired(format!("Action token {} has expired", token))),
+                _ => (
+                    AuditOutcome::Replayed,
+                    ProcessError::AlreadyUsed(format!("Action token {} was already confirmed", token)),
+                ),
+            };
+            self.record(&mut state, &action, principal, attempt);
+            return Err(error);
+        }
+        let Some(action) = state.pending.get(token).cloned() else {
+            return Err(ProcessError::NotFound(format!("Unknown action token {}", token)));
+        };
+        if action.client_id != principal.client_id {
+            self.record(&mut state, &action, principal, AuditOutcome::WrongClient);
+            return Err(ProcessError::Forbidden("Action token was issued to another client".to_string()));
+        }
+        if !may_run(&action.task) {
+            self.record(&mut state, &action, principal, AuditOutcome::Forbidden);
+            return Err(ProcessError::Forbidden(format!("Not allowed to run task: {}", action.task)));
+        }
+
+        // Pruning moved expired actions to `spent`, so this one is still valid
+        state.pending.remove(token);
+        state.spent.insert(token.to_string(), (action.clone(), AuditOutcome::Confirmed));
+        self.record(&mut state, &action, principal, AuditOutcome::Confirmed);
+        info!("{} confirmed action {}: {}", principal.name, token, action.command.render());
+        Ok(action)
+    }
+
+    /// Run a confirmed command with the sandboxed executor, if one is configured
+    pub async fn execute(&self, action: &PendingAction) -> Option<Execution> {
+        let executor = self.executor.as_ref()?;
+        Some(executor.run_args(&action.command.argv()).await)
+    }
+
+    /// Confirmation attempts, oldest first
+    pub fn audit_log(&self) -> Vec<AuditEntry> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.audit.iter().cloned().collect()
+    }
+
+    fn record(&self, state: &mut ActionState, action: &PendingAction, principal: &Principal, outcome: AuditOutcome) {
+        if outcome != AuditOutcome::Confirmed {
+            warn!("Rejected action token {} from {}: {:?}", action.token, principal.name, outcome);
+        }
+        if state.audit.len() == self.max_audit_entries {
+            state.audit.pop_front();
+        }
+        if self.max_audit_entries > 0 {
+            state.audit.push_back(AuditEntry {
+                token: action.token.clone(),
+                request_id: action.request_id,
+                task: action.task.clone(),
+                command: action.command.render(),
+                principal: principal.name.clone(),
+                client_id: principal.client_id.clone(),
+                outcome,
+                at: Utc::now(),
+            });
+        }
+    }
+
+    /// Drop actions that expired a while ago; until then an expired or used token is reported as
+    /// such rather than as unknown
+    fn prune(&self, state: &mut ActionState, now: DateTime<Utc>) {
+        let cutoff = now - self.ttl.max(chrono::Duration::minutes(SPENT_RETENTION_MINUTES));
+        let expired: Vec<String> = state
+            .pending
+            .iter()
+            .filter(|(_, action)| action.expires_at <= now)
+            .map(|(token, _)| token.clone())
+            .collect();
+        for token in expired {
+            if let Some(action) = state.pending.remove(&token) {
+                state.spent.insert(token, (action, AuditOutcome::Expired));
+            }
+        }
+        state.spent.retain(|_, (action, _)| action.expires_at > cutoff);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(ttl_secs: u64) -> ActionStore {
+        let config = ActionsConfig {
+            enabled: true,
+            ttl_secs,
+            ..ActionsConfig::default()
+        };
+        ActionStore::from_config(&config, &ExecutorConfig::default()).unwrap()
+    }
+
+    fn principal(client_id: &str) -> Principal {
+        Principal {
+            name: client_id.to_string(),
+            client_id: client_id.to_string(),
+            ..Principal::anonymous()
+        }
+    }
+
+    fn allowed(_task: &str) -> bool {
+        true
+    }
+
+    fn issue(store: &ActionStore, client_id: &str) -> Vec<ActionToken> {
+        let commands = [
+            ShellCommand::new("brew").args(["install", "ripgrep"]),
+            ShellCommand::new("cargo").args(["install", "ripgrep"]),
+        ];
+        store.issue(Uuid::new_v4(), "install", "ripgrep", client_id, &commands, None)
+    }
+
+    #[test]
+    fn test_tokens_are_single_use() {
+        let store = store(60);
+        let tokens = issue(&store, "key:app");
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[1].command, "cargo install ripgrep");
+        assert_ne!(tokens[0].token, tokens[1].token);
+
+        let action = store.confirm(&tokens[0].token, &principal("key:app"), allowed).unwrap();
+        assert_eq!(action.command.render(), "brew install ripgrep");
+        let replay = store.confirm(&tokens[0].token, &principal("key:app"), allowed).unwrap_err();
+        assert_eq!(replay.code(), "already_used");
+
+        // Confirming one command leaves the other suggestions usable
+        assert!(store.confirm(&tokens[1].token, &principal("key:app"), allowed).is_ok());
+        assert_eq!(store.confirm("nonsense", &principal("key:app"), allowed).unwrap_err().code(), "not_found");
+
+        let outcomes: Vec<_> = store.audit_log().iter().map(|entry| entry.outcome).collect();
+        assert_eq!(outcomes, vec![AuditOutcome::Confirmed, AuditOutcome::Replayed, AuditOutcome::Confirmed]);
+    }
+
+    #[test]
+    fn test_tokens_are_bound_to_the_client() {
+        let store = store(60);
+        let tokens = issue(&store, "key:app");
+
+        let error = store.confirm(&tokens[0].token, &principal("key:other"), allowed).unwrap_err();
+        assert_eq!(error.code(), "forbidden");
+        assert!(store.confirm(&tokens[0].token, &principal("key:app"), allowed).is_ok());
+
+        let audit = store.audit_log();
+        assert_eq!(audit[0].outcome, AuditOutcome::WrongClient);
+        assert_eq!(audit[0].principal, "key:other");
+        assert_eq!(audit[1].principal, "key:app");
+    }
+
+    #[test]
+    fn test_tokens_survive_a_forbidden_task() {
+        let store = store(60);
+        let tokens = issue(&store, "key:app");
+
+        let error = store.confirm(&tokens[0].token, &principal("key:app"), |task| task != "install").unwrap_err();
+        assert_eq!(error.code(), "forbidden");
+        assert!(store.confirm(&tokens[0].token, &principal("key:app"), allowed).is_ok());
+
+        let outcomes: Vec<_> = store.audit_log().iter().map(|entry| entry.outcome).collect();
+        assert_eq!(outcomes, vec![AuditOutcome::Forbidden, AuditOutcome::Confirmed]);
+    }
+
+    #[test]
+    fn test_expired_tokens_are_rejected() {
+        let store = store(0);
+        let tokens = issue(&store, "key:app");
+
+        let error = store.confirm(&tokens[0].token, &principal("key:app"), allowed).unwrap_err();
+        assert_eq!(error.code(), "expired");
+        assert_eq!(store.audit_log()[0].outcome, AuditOutcome::Expired);
+        assert!(ActionStore::disabled().issue(Uuid::new_v4(), "install", "x", "anonymous", &[ShellCommand::new("ls")], None).is_empty());
+    }
+}
@@ -0,0 +1,352 @@
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::AuthConfig;
+use crate::error::ProcessError;
+use crate::tasks::Task;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Header carrying a static API key, as an alternative to `Authorization: Bearer`
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Tasks a caller may run
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskAccess {
+    All,
+    /// Task names or task categories (`nlp`, `system`, `llm`)
+    Only(Vec<String>),
+}
+
+impl TaskAccess {
+    /// Build from a configured list, where `*` (or no list) allows everything
+    pub fn from_list(list: Option<&[String]>) -> Self {
+        match list {
+            Some(list) if !list.iter().any(|entry| entry == "*") => TaskAccess::Only(list.to_vec()),
+            _ => TaskAccess::All,
+        }
+    }
+
+    pub fn allows(&self, task: &dyn Task) -> bool {
+        match self {
+            TaskAccess::All => true,
+            TaskAccess::Only(entries) => {
+                let category = task.category().as_str();
+                entries
+                    .iter()
+                    .any(|entry| entry.eq_ignore_ascii_case(task.name()) || entry.eq_ignore_ascii_case(category))
+            }
+        }
+    }
+}
+
+/// The authenticated caller of a request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Principal {
+    pub name: String,
+    pub access: TaskAccess,
+    /// Key rate limits are counted under, e.g. `key:frontend` or `ip:10.0.0.7`
+    pub client_id: String,
+    /// Whether the caller may use the `/admin` endpoints
+    pub admin: bool,
+}
+
+impl Principal {
+    /// Caller of a server without authentication; it may run every task but, since anyone can be
+    /// anonymous, not use the `/admin` endpoints
+    pub fn anonymous() -> Self {
+        Self {
+            name: "anonymous".to_string(),
+            access: TaskAccess::All,
+            client_id: "anonymous".to_string(),
+            admin: false,
+        }
+    }
+}
+
+/// Claims carried by a signed bearer token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenClaims {
+    /// Who the token was issued to
+    pub sub: String,
+    /// Expiry as a Unix timestamp in seconds
+    pub exp: i64,
+    /// Allowed task names or categories; all tasks when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tasks: Option<Vec<String>>,
+}
+
+struct ApiKey {
+    name: String,
+    key: String,
+    access: TaskAccess,
+    admin: bool,
+}
+
+/// Checks API keys and HMAC-signed bearer tokens against the auth configuration
+pub struct Authenticator {
+    enabled: bool,
+    api_keys: Vec<ApiKey>,
+    token_secret: Option<Vec<u8>>,
+}
+
+impl Authenticator {
+    /// Authenticator that lets every request through as `Principal::anonymous`
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            api_keys: Vec::new(),
+            token_secret: None,
+        }
+    }
+
+    pub fn from_config(config: &AuthConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            api_keys: config
+                .api_keys
+                .iter()
+                .map(|key| ApiKey {
+                    name: key.name.clone(),
+                    key: key.key.clone(),
+                    access: TaskAccess::from_list(key.allowed_tasks.as_deref()),
+                    admin: key.admin,
+                })
+                .collect(),
+            token_secret: config.token_secret.as_ref().map(|secret| secret.as_bytes().to_vec()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Identify the caller from the `X-API-Key` or `Authorization: Bearer` header
+    pub fn authenticate(&self, headers: &HeaderMap) -> Result<Principal, ProcessError> {
+        if !self.enabled {
+            return Ok(Principal::anonymous());
+        }
+
+        let api_key = headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok());
+        let bearer = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(str::trim);
+
+        let credential = api_key.or(bearer).ok_or_else(|| {
+            ProcessError::Unauthorized("Missing API key or bearer token".to_string())
+        })?;
+
+        if let Some(key) = self.api_keys.iter().find(|k| constant_time_eq(k.key.as_bytes(), credential.as_bytes())) {
+            return Ok(Principal {
+                name: key.name.clone(),
+                access: key.access.clone(),
+                client_id: format!("key:{}", key.name),
+                admin: key.admin,
+            });
+        }
+
+        if bearer.is_some() && self.token_secret.is_some() {
+            let claims = self
+                .verify_token(credential)
+                .map_err(|e| ProcessError::Unauthorized(e.to_string()))?;
+            return Ok(Principal {
+                client_id: format!("token:{}", claims.sub),
+                name: claims.sub,
+                access: TaskAccess::from_list(claims.tasks.as_deref()),
+                admin: false,
+            });
+        }
+
+        Err(ProcessError::Unauthorized("Invalid API key or bearer token".to_string()))
+    }
+
+    /// Issue a bearer token valid for `ttl`, optionally restricted to some tasks
+    pub fn issue_token(&self, subject: &str, tasks: Option<Vec<String>>, ttl: Duration) -> Result<String> {
+        let claims = TokenClaims {
+            sub: subject.to_string(),
+            exp: chrono::Utc::now().timestamp() + ttl.as_secs() as i64,
+            tasks,
+        };
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?);
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&payload)?.finalize().into_bytes());
+        Ok(format!("{}.{}", payload, signature))
+    }
+
+    /// Check a token's signature and expiry and return its claims
+    pub fn verify_token(&self, token: &str) -> Result<TokenClaims> {
+        let (payload, signature) = token
+            .split_once('.')
+            .ok_or_else(|| anyhow!("Malformed bearer token"))?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| anyhow!("Malformed bearer token"))?;
+        self.mac(payload)?
+            .verify_slice(&signature)
+            .map_err(|_| anyhow!("Invalid bearer token signature"))?;
+
+        let claims: TokenClaims = serde_json::from_slice(
+            &URL_SAFE_NO_PAD
+                .decode(payload)
+                .map_err(|_| anyhow!("Malformed bearer token"))?,
+        )?;
+        if claims.exp <= chrono::Utc::now().timestamp() {
+            return Err(anyhow!("Bearer token has expired"));
+        }
+        Ok(claims)
+    }
+
+    fn mac(&self, payload: &str) -> Result<HmacSha256> {
+        let secret = self
+            .token_secret
+            .as_ref()
+            .ok_or_else(|| anyhow!("No token secret configured"))?;
+        let mut mac = HmacSha256::new_from_slice(secret)?;
+        mac.update(payload.as_bytes());
+        Ok(mac)
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Middleware that authenticates the request and stores the `Principal` in its extensions
+pub async fn require_auth(
+    State(authenticator): State<Arc<Authenticator>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    match authenticator.authenticate(request.headers()) {
+        Ok(mut principal) => {
+            // Without credentials to tell callers apart, rate limit them by address
+            if !authenticator.is_enabled() {
+                if let Some(ConnectInfo(addr)) = request.extensions().get::<ConnectInfo<SocketAddr>>() {
+                    principal.client_id = format!("ip:{}", addr.ip());
+                }
+            }
+            request.extensions_mut().insert(principal);
+            next.run(request).await
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ApiKeyConfig;
+    use crate::tasks::TaskRegistry;
+
+    fn authenticator() -> Authenticator {
+        Authenticator::from_config(&AuthConfig {
+            enabled: true,
+            token_secret: Some("token-secret".to_string()),
+            api_keys: vec![
+                ApiKeyConfig {
+                    name: "admin".to_string(),
+                    key: "admin-key".to_string(),
+                    allowed_tasks: None,
+                    admin: true,
+                },
+                ApiKeyConfig {
+                    name: "frontend".to_string(),
+                    key: "frontend-key".to_string(),
+                    allowed_tasks: Some(vec!["nlp".to_string(), "google_search".to_string()]),
+                    admin: false,
+                },
+            ],
+        })
+    }
+
+    fn headers(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_api_key_access() {
+        let auth = authenticator();
+        let registry = TaskRegistry::with_builtin_tasks();
+
+        let principal = auth
+            .authenticate(&headers(header::HeaderName::from_static(API_KEY_HEADER), "frontend-key"))
+            .unwrap();
+        assert_eq!(principal.name, "frontend");
+        assert_eq!(principal.client_id, "key:frontend");
+        assert!(!principal.admin);
+        assert!(principal.access.allows(registry.get("sentiment").unwrap().as_ref()));
+        assert!(principal.access.allows(registry.get("google_search").unwrap().as_ref()));
+        assert!(!principal.access.allows(registry.get("install").unwrap().as_ref()));
+
+        let principal = auth.authenticate(&headers(header::AUTHORIZATION, "Bearer admin-key")).unwrap();
+        assert_eq!(principal.access, TaskAccess::All);
+        assert!(principal.admin);
+    }
+
+    #[test]
+    fn test_missing_or_wrong_credentials() {
+        let auth = authenticator();
+
+        let error = auth.authenticate(&HeaderMap::new()).unwrap_err();
+        assert_eq!(error.code(), "unauthorized");
+
+        let error = auth.authenticate(&headers(header::AUTHORIZATION, "Bearer nope")).unwrap_err();
+        assert_eq!(error.code(), "unauthorized");
+    }
+
+    #[test]
+    fn test_signed_token() {
+        let auth = authenticator();
+        let token = auth
+            .issue_token("ci", Some(vec!["summarize".to_string()]), Duration::from_secs(60))
+            .unwrap();
+
+        let principal = auth
+            .authenticate(&headers(header::AUTHORIZATION, &format!("Bearer {}", token)))
+            .unwrap();
+        assert_eq!(principal.name, "ci");
+        assert_eq!(principal.access, TaskAccess::Only(vec!["summarize".to_string()]));
+
+        // Flipping a character of the payload breaks the signature
+        let mut tampered = token.into_bytes();
+        tampered[0] = if tampered[0] == b'A' { b'B' } else { b'A' };
+        let tampered = String::from_utf8(tampered).unwrap();
+        assert!(auth.verify_token(&tampered).is_err());
+    }
+
+    #[test]
+    fn test_expired_token() {
+        let auth = authenticator();
+        let claims = TokenClaims {
+            sub: "old".to_string(),
+            exp: chrono::Utc::now().timestamp() - 10,
+            tasks: None,
+        };
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).unwrap());
+        let signature = URL_SAFE_NO_PAD.encode(auth.mac(&payload).unwrap().finalize().into_bytes());
+
+        let error = auth.verify_token(&format!("{}.{}", payload, signature)).unwrap_err();
+        assert!(error.to_string().contains("expired"));
+    }
+
+    #[test]
+    fn test_disabled_authenticator_allows_everything() {
+        let principal = Authenticator::disabled().authenticate(&HeaderMap::new()).unwrap();
+        assert_eq!(principal, Principal::anonymous());
+    }
+}
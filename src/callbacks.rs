@@ -1,8 +1,20 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::Sha256;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
 use tracing::{info, warn};
 
+use crate::config::{CallbacksConfig, WebhookConfig};
+use crate::executor::SandboxExecutor;
+use crate::metrics::metrics;
+
+type HmacSha256 = Hmac<Sha256>;
+
 /// Callback result structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CallbackResult {
@@ -18,40 +30,59 @@ pub struct CommandContext {
     pub command: String,
     pub task: String,
     pub input_text: String,
-    pub parsed_result: String,
+    pub parsed_result: serde_json::Value,
     pub confidence: Option<f32>,
     pub timestamp: chrono::DateTime<chrono::Utc>,
     pub session_id: Option<String>,
+    /// Result of the callback that ran just before this one, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<CallbackResult>,
 }
 
-/// Callback handler enum that can handle different types of callbacks
-#[derive(Debug)]
-pub enum CallbackHandler {
-    System(SystemCommandHandler),
-    Nlp(NlpCallbackHandler),
-}
+/// A handler run after a task completes, registered with the `CallbackManager`
+#[async_trait]
+pub trait Callback: Send + Sync {
+    /// Unique name, used to unregister, enable or disable the callback
+    fn name(&self) -> &str;
 
-impl CallbackHandler {
-    pub async fn handle(&self, context: &CommandContext) -> Result<CallbackResult> {
-        match self {
-            CallbackHandler::System(handler) => handler.handle(context).await,
-            CallbackHandler::Nlp(handler) => handler.handle(context).await,
-        }
+    /// Commands the callback runs for; `None` runs it for every command
+    fn supported_commands(&self) -> Option<Vec<String>> {
+        None
     }
 
-    pub fn get_supported_commands(&self) -> Vec<String> {
-        match self {
-            CallbackHandler::System(handler) => handler.get_supported_commands(),
-            CallbackHandler::Nlp(handler) => handler.get_supported_commands(),
-        }
+    /// Callbacks with a lower priority run first
+    fn priority(&self) -> i32 {
+        0
     }
 
-    pub fn get_handler_name(&self) -> String {
-        match self {
-            CallbackHandler::System(handler) => handler.get_handler_name(),
-            CallbackHandler::Nlp(handler) => handler.get_handler_name(),
-        }
+    /// Whether an unsuccessful result stops the callbacks after this one from running
+    fn is_guard(&self) -> bool {
+        false
+    }
+
+    /// Whether the callback runs after the response is sent instead of before; ignored for guards
+    fn is_background(&self) -> bool {
+        false
+    }
+
+    /// Time the callback may take; `None` uses the manager's default
+    fn timeout(&self) -> Option<Duration> {
+        None
     }
+
+    async fn handle(&self, context: &CommandContext) -> Result<CallbackResult>;
+}
+
+/// Description of a registered callback, as listed by `CallbackManager::list`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CallbackInfo {
+    pub name: String,
+    pub priority: i32,
+    pub enabled: bool,
+    pub guard: bool,
+    pub background: bool,
+    /// `None` when the callback runs for every command
+    pub supported_commands: Option<Vec<String>>,
 }
 
 /// Default callback handlers for system commands
@@ -59,8 +90,29 @@ impl CallbackHandler {
 pub struct SystemCommandHandler;
 
 
-impl SystemCommandHandler {
-    pub async fn handle(&self, context: &CommandContext) -> Result<CallbackResult> {
+#[async_trait]
+impl Callback for SystemCommandHandler {
+    fn name(&self) -> &str {
+        "system"
+    }
+
+    fn supported_commands(&self) -> Option<Vec<String>> {
+        Some(vec![
+            "install".to_string(),
+            "find_file".to_string(),
+            "find_content".to_string(),
+            "get_file_from".to_string(),
+            "show_tools".to_string(),
+            "open_app".to_string(),
+            "open_file".to_string(),
+            "checkout".to_string(),
+            "diff".to_string(),
+            "google_search".to_string(),
+            "ask_ai".to_string(),
+        ])
+    }
+
+    async fn handle(&self, context: &CommandContext) -> Result<CallbackResult> {
         let start_time = std::time::Instant::now();
         info!("Executing system command callback for: {}", context.command);
 
@@ -82,31 +134,14 @@ impl SystemCommandHandler {
             result
         })
     }
+}
 
-    pub fn get_supported_commands(&self) -> Vec<String> {
-        vec![
-            "install".to_string(),
-            "find_file".to_string(),
-            "find_content".to_string(),
-            "get_file_from".to_string(),
-            "show_tools".to_string(),
-            "open_app".to_string(),
-            "open_file".to_string(),
-            "checkout".to_string(),
-            "diff".to_string(),
-            "google_search".to_string(),
-            "ask_ai".to_string(),
-        ]
-    }
-
-    pub fn get_handler_name(&self) -> String {
-        "SystemCommandHandler".to_string()
-    }
+impl SystemCommandHandler {
     async fn handle_install_callback(&self, context: &CommandContext) -> Result<CallbackResult> {
         info!("Processing install callback for: {}", context.input_text);
         
         // Parse the result to get suggested commands
-        let parsed_data: serde_json::Value = serde_json::from_str(&context.parsed_result)?;
+        let parsed_data = &context.parsed_result;
         
         if let Some(commands) = parsed_data.get("suggested_commands").and_then(|c| c.as_array()) {
             // Here you could actually execute the commands or log them
@@ -142,7 +177,7 @@ impl SystemCommandHandler {
     async fn handle_find_file_callback(&self, context: &CommandContext) -> Result<CallbackResult> {
         info!("Processing find file callback for: {}", context.input_text);
         
-        let parsed_data: serde_json::Value = serde_json::from_str(&context.parsed_result)?;
+        let parsed_data = &context.parsed_result;
         
         Ok(CallbackResult {
             success: true,
@@ -160,7 +195,7 @@ impl SystemCommandHandler {
     async fn handle_find_content_callback(&self, context: &CommandContext) -> Result<CallbackResult> {
         info!("Processing find content callback for: {}", context.input_text);
         
-        let parsed_data: serde_json::Value = serde_json::from_str(&context.parsed_result)?;
+        let parsed_data = &context.parsed_result;
         
         Ok(CallbackResult {
             success: true,
@@ -178,7 +213,7 @@ impl SystemCommandHandler {
     async fn handle_get_file_from_callback(&self, context: &CommandContext) -> Result<CallbackResult> {
         info!("Processing get file from callback for: {}", context.input_text);
         
-        let parsed_data: serde_json::Value = serde_json::from_str(&context.parsed_result)?;
+        let parsed_data = &context.parsed_result;
         
         Ok(CallbackResult {
             success: true,
@@ -196,7 +231,7 @@ impl SystemCommandHandler {
     async fn handle_show_tools_callback(&self, context: &CommandContext) -> Result<CallbackResult> {
         info!("Processing show tools callback for: {}", context.input_text);
         
-        let parsed_data: serde_json::Value = serde_json::from_str(&context.parsed_result)?;
+        let parsed_data = &context.parsed_result;
         
         Ok(CallbackResult {
             success: true,
@@ -214,7 +249,7 @@ impl SystemCommandHandler {
     async fn handle_open_app_callback(&self, context: &CommandContext) -> Result<CallbackResult> {
         info!("Processing open app callback for: {}", context.input_text);
         
-        let parsed_data: serde_json::Value = serde_json::from_str(&context.parsed_result)?;
+        let parsed_data = &context.parsed_result;
         
         Ok(CallbackResult {
             success: true,
@@ -232,7 +267,7 @@ impl SystemCommandHandler {
     async fn handle_open_file_callback(&self, context: &CommandContext) -> Result<CallbackResult> {
         info!("Processing open file callback for: {}", context.input_text);
         
-        let parsed_data: serde_json::Value = serde_json::from_str(&context.parsed_result)?;
+        let parsed_data = &context.parsed_result;
         
         Ok(CallbackResult {
             success: true,
@@ -250,7 +285,7 @@ impl SystemCommandHandler {
     async fn handle_checkout_callback(&self, context: &CommandContext) -> Result<CallbackResult> {
         info!("Processing checkout callback for: {}", context.input_text);
         
-        let parsed_data: serde_json::Value = serde_json::from_str(&context.parsed_result)?;
+        let parsed_data = &context.parsed_result;
         
         Ok(CallbackResult {
             success: true,
@@ -268,7 +303,7 @@ impl SystemCommandHandler {
     async fn handle_diff_callback(&self, context: &CommandContext) -> Result<CallbackResult> {
         info!("Processing diff callback for: {}", context.input_text);
         
-        let parsed_data: serde_json::Value = serde_json::from_str(&context.parsed_result)?;
+        let parsed_data = &context.parsed_result;
         
         Ok(CallbackResult {
             success: true,
@@ -286,7 +321,7 @@ impl SystemCommandHandler {
     async fn handle_google_search_callback(&self, context: &CommandContext) -> Result<CallbackResult> {
         info!("Processing Google search callback for: {}", context.input_text);
         
-        let parsed_data: serde_json::Value = serde_json::from_str(&context.parsed_result)?;
+        let parsed_data = &context.parsed_result;
         
         Ok(CallbackResult {
             success: true,
@@ -305,7 +340,7 @@ impl SystemCommandHandler {
     async fn handle_ask_ai_callback(&self, context: &CommandContext) -> Result<CallbackResult> {
         info!("Processing Ask AI callback for: {}", context.input_text);
         
-        let parsed_data: serde_json::Value = serde_json::from_str(&context.parsed_result)?;
+        let parsed_data = &context.parsed_result;
         
         Ok(CallbackResult {
             success: true,
@@ -326,8 +361,25 @@ impl SystemCommandHandler {
 pub struct NlpCallbackHandler;
 
 
-impl NlpCallbackHandler {
-    pub async fn handle(&self, context: &CommandContext) -> Result<CallbackResult> {
+#[async_trait]
+impl Callback for NlpCallbackHandler {
+    fn name(&self) -> &str {
+        "nlp"
+    }
+
+    fn supported_commands(&self) -> Option<Vec<String>> {
+        Some(vec![
+            "sentiment".to_string(),
+            "summarize".to_string(),
+            "classify".to_string(),
+            "extract_keywords".to_string(),
+            "translate".to_string(),
+            "question_answer".to_string(),
+            "natural_language".to_string(),
+        ])
+    }
+
+    async fn handle(&self, context: &CommandContext) -> Result<CallbackResult> {
         let start_time = std::time::Instant::now();
         info!("Executing NLP callback for: {}", context.command);
 
@@ -347,27 +399,14 @@ impl NlpCallbackHandler {
             r
         })
     }
+}
 
-    pub fn get_supported_commands(&self) -> Vec<String> {
-        vec![
-            "sentiment".to_string(),
-            "summarize".to_string(),
-            "classify".to_string(),
-            "extract_keywords".to_string(),
-            "translate".to_string(),
-            "question_answer".to_string(),
-            "natural_language".to_string(),
-        ]
-    }
-
-    pub fn get_handler_name(&self) -> String {
-        "NlpCallbackHandler".to_string()
-    }
+impl NlpCallbackHandler {
 
     async fn handle_sentiment_callback(&self, context: &CommandContext) -> Result<CallbackResult> {
         info!("Processing sentiment analysis callback");
         
-        let parsed_data: serde_json::Value = serde_json::from_str(&context.parsed_result)?;
+        let parsed_data = &context.parsed_result;
         
         Ok(CallbackResult {
             success: true,
@@ -391,7 +430,7 @@ impl NlpCallbackHandler {
             data: Some(serde_json::json!({
                 "action": "text_summarized",
                 "original_text": context.input_text,
-                "summary": context.parsed_result,
+                "summary": context.parsed_result.get("summary"),
                 "confidence": context.confidence
             })),
             execution_time_ms: 0,
@@ -401,7 +440,7 @@ impl NlpCallbackHandler {
     async fn handle_classify_callback(&self, context: &CommandContext) -> Result<CallbackResult> {
         info!("Processing text classification callback");
         
-        let parsed_data: serde_json::Value = serde_json::from_str(&context.parsed_result)?;
+        let parsed_data = &context.parsed_result;
         
         Ok(CallbackResult {
             success: true,
@@ -419,7 +458,7 @@ impl NlpCallbackHandler {
     async fn handle_keywords_callback(&self, context: &CommandContext) -> Result<CallbackResult> {
         info!("Processing keyword extraction callback");
         
-        let parsed_data: serde_json::Value = serde_json::from_str(&context.parsed_result)?;
+        let parsed_data = &context.parsed_result;
         
         Ok(CallbackResult {
             success: true,
@@ -437,7 +476,7 @@ impl NlpCallbackHandler {
     async fn handle_translate_callback(&self, context: &CommandContext) -> Result<CallbackResult> {
         info!("Processing translation callback");
         
-        let parsed_data: serde_json::Value = serde_json::from_str(&context.parsed_result)?;
+        let parsed_data = &context.parsed_result;
         
         Ok(CallbackResult {
             success: true,
@@ -455,7 +494,7 @@ impl NlpCallbackHandler {
     async fn handle_qa_callback(&self, context: &CommandContext) -> Result<CallbackResult> {
         info!("Processing question answering callback");
         
-        let parsed_data: serde_json::Value = serde_json::from_str(&context.parsed_result)?;
+        let parsed_data = &context.parsed_result;
         
         Ok(CallbackResult {
             success: true,
@@ -488,96 +527,501 @@ impl NlpCallbackHandler {
     }
 }
 
+/// Header carrying the hex HMAC-SHA256 of a webhook body, as `sha256=<hex>`
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "x-rustlm-signature";
+/// Header carrying the unique id of a webhook delivery, the same for every retry
+pub const WEBHOOK_DELIVERY_HEADER: &str = "x-rustlm-delivery";
+
+/// Body posted by a `WebhookCallback`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub delivery_id: String,
+    pub context: CommandContext,
+    pub result: CallbackResult,
+}
+
+/// A delivery that failed on every attempt, as written to the dead-letter log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub webhook: String,
+    pub url: String,
+    pub attempts: u32,
+    pub error: String,
+    pub failed_at: chrono::DateTime<chrono::Utc>,
+    pub payload: WebhookPayload,
+}
+
+/// Posts task results to an HTTP endpoint, signing and retrying each delivery
+pub struct WebhookCallback {
+    config: WebhookConfig,
+    client: reqwest::Client,
+}
+
+impl WebhookCallback {
+    pub fn new(config: WebhookConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .unwrap_or_default();
+        Self { config, client }
+    }
+
+    /// The previous callback's result, or the task's output when this callback runs first
+    fn payload_result(context: &CommandContext) -> CallbackResult {
+        if let Some(previous) = &context.previous {
+            return previous.clone();
+        }
+        CallbackResult {
+            success: true,
+            message: format!("Task {} completed", context.task),
+            data: Some(context.parsed_result.clone()),
+            execution_time_ms: 0,
+        }
+    }
+
+    /// Hex HMAC-SHA256 of `body` under the configured secret
+    fn signature(&self, body: &[u8]) -> Option<String> {
+        let secret = self.config.secret.as_ref()?;
+        let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).ok()?;
+        mac.update(body);
+        Some(
+            mac.finalize()
+                .into_bytes()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+        )
+    }
+
+    /// POST the body once; `Err((error, retryable))` when the delivery failed
+    async fn attempt(&self, delivery_id: &str, body: &[u8]) -> std::result::Result<u16, (String, bool)> {
+        let mut request = self
+            .client
+            .post(&self.config.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(WEBHOOK_DELIVERY_HEADER, delivery_id);
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value);
+        }
+        if let Some(signature) = self.signature(body) {
+            request = request.header(WEBHOOK_SIGNATURE_HEADER, format!("sha256={}", signature));
+        }
+
+        match request.body(body.to_vec()).send().await {
+            Ok(response) if response.status().is_success() => Ok(response.status().as_u16()),
+            Ok(response) => {
+                let status = response.status();
+                let retryable = status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+                Err((format!("webhook returned {}", status), retryable))
+            }
+            Err(e) => Err((e.to_string(), true)),
+        }
+    }
+
+    /// Record a delivery that ultimately failed
+    async fn dead_letter(&self, letter: &DeadLetter) {
+        warn!(
+            "Webhook {} delivery {} failed after {} attempts: {}",
+            self.config.name, letter.payload.delivery_id, letter.attempts, letter.error
+        );
+        let Some(path) = &self.config.dead_letter_file else {
+            return;
+        };
+
+        // One append of the whole line, so concurrent dead letters don't interleave
+        let written = async {
+            let mut line = serde_json::to_vec(letter)?;
+            line.push(b'\n');
+            let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?;
+            file.write_all(&line).await?;
+            // tokio writes in the background; flush so the line is on disk once we return
+            file.flush().await?;
+            anyhow::Ok(())
+        }
+        .await;
+        if let Err(e) = written {
+            warn!("Failed to write dead letter to {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[async_trait]
+impl Callback for WebhookCallback {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn supported_commands(&self) -> Option<Vec<String>> {
+        self.config.commands.clone()
+    }
+
+    fn priority(&self) -> i32 {
+        self.config.priority
+    }
+
+    fn is_background(&self) -> bool {
+        self.config.background
+    }
+
+    /// Long enough for every attempt and the backoff between them
+    fn timeout(&self) -> Option<Duration> {
+        let attempts = self.config.max_attempts.max(1);
+        let backoff = self
+            .config
+            .initial_backoff_ms
+            .saturating_mul((1u64 << (attempts - 1).min(32)) - 1);
+        Some(Duration::from_secs(self.config.timeout_secs) * attempts + Duration::from_millis(backoff))
+    }
+
+    async fn handle(&self, context: &CommandContext) -> Result<CallbackResult> {
+        let payload = WebhookPayload {
+            delivery_id: uuid::Uuid::new_v4().to_string(),
+            context: CommandContext {
+                previous: None,
+                ..context.clone()
+            },
+            result: Self::payload_result(context),
+        };
+        let body = serde_json::to_vec(&payload)?;
+
+        let mut backoff = Duration::from_millis(self.config.initial_backoff_ms);
+        let mut attempts = 0;
+        let error = loop {
+            attempts += 1;
+            match self.attempt(&payload.delivery_id, &body).await {
+                Ok(status) => {
+                    info!("Webhook {} delivered {} on attempt {}", self.config.name, payload.delivery_id, attempts);
+                    return Ok(CallbackResult {
+                        success: true,
+                        message: format!("Delivered to webhook {}", self.config.name),
+                        data: Some(serde_json::json!({
+                            "action": "webhook_delivered",
+                            "delivery_id": payload.delivery_id,
+                            "status": status,
+                            "attempts": attempts
+                        })),
+                        execution_time_ms: 0,
+                    });
+                }
+                Err((error, retryable)) => {
+                    if !retryable || attempts >= self.config.max_attempts {
+                        break error;
+                    }
+                    warn!(
+                        "Webhook {} attempt {} failed, retrying in {}ms: {}",
+                        self.config.name,
+                        attempts,
+                        backoff.as_millis(),
+                        error
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+            }
+        };
+
+        let delivery_id = payload.delivery_id.clone();
+        self.dead_letter(&DeadLetter {
+            webhook: self.config.name.clone(),
+            url: self.config.url.clone(),
+            attempts,
+            error: error.clone(),
+            failed_at: chrono::Utc::now(),
+            payload,
+        })
+        .await;
+        Ok(CallbackResult {
+            success: false,
+            message: format!("Webhook {} failed after {} attempts: {}", self.config.name, attempts, error),
+            data: Some(serde_json::json!({
+                "action": "webhook_failed",
+                "delivery_id": delivery_id,
+                "attempts": attempts
+            })),
+            execution_time_ms: 0,
+        })
+    }
+}
+
+/// A registered callback and whether it currently runs
+struct Registration {
+    callback: Arc<dyn Callback>,
+    enabled: bool,
+}
+
+impl Registration {
+    fn runs_for(&self, command: &str) -> bool {
+        self.enabled
+            && self
+                .callback
+                .supported_commands()
+                .is_none_or(|commands| commands.iter().any(|c| c == command))
+    }
+}
+
+/// Run one callback once a permit is free, turning errors and timeouts into failed results; the
+/// timeout includes waiting for the permit, so busy callbacks can't stall the request
+async fn run_callback(
+    callback: &dyn Callback,
+    context: &CommandContext,
+    permits: &Semaphore,
+    default_timeout: Duration,
+) -> CallbackResult {
+    let name = callback.name();
+    let timeout = callback.timeout().unwrap_or(default_timeout);
+    info!("Executing callback {} for command: {}", name, context.command);
+
+    let start_time = std::time::Instant::now();
+    let outcome = tokio::time::timeout(timeout, async {
+        // The semaphore is never closed, so acquiring only waits
+        let _permit = permits.acquire().await.ok();
+        callback.handle(context).await
+    })
+    .await;
+    let elapsed = start_time.elapsed();
+    let execution_time_ms = elapsed.as_millis() as u64;
+
+    let result = match outcome {
+        Ok(Ok(mut result)) => {
+            info!("Callback {} completed successfully", name);
+            result.execution_time_ms = execution_time_ms;
+            result
+        }
+        Ok(Err(e)) => {
+            warn!("Callback {} failed: {}", name, e);
+            CallbackResult {
+                success: false,
+                message: format!("Callback {} failed: {}", name, e),
+                data: None,
+                execution_time_ms,
+            }
+        }
+        Err(_) => {
+            warn!("Callback {} timed out after {:?}", name, timeout);
+            CallbackResult {
+                success: false,
+                message: format!("Callback {} timed out after {} ms", name, timeout.as_millis()),
+                data: Some(serde_json::json!({
+                    "reason": "timeout",
+                    "timeout_ms": timeout.as_millis() as u64,
+                })),
+                execution_time_ms,
+            }
+        }
+    };
+    metrics().observe_callback(name, elapsed, result.success);
+    result
+}
+
 /// Main callback manager that routes commands to appropriate handlers
+///
+/// Callbacks can be registered, unregistered, enabled and disabled while the server runs.
+/// Callbacks of equal priority run concurrently, and priorities run in order from lowest to
+/// highest. Each callback sees the last result of the priority before its own in
+/// `CommandContext::previous`, and an unsuccessful guard stops the higher priorities from
+/// running. Results are returned in priority and then registration order.
 pub struct CallbackManager {
-    handlers: HashMap<String, CallbackHandler>,
+    callbacks: RwLock<Vec<Registration>>,
+    permits: Arc<Semaphore>,
+    timeout: Duration,
 }
 
 impl CallbackManager {
     /// Create a new callback manager with default handlers
     pub fn new() -> Self {
-        let mut manager = Self {
-            handlers: HashMap::new(),
-        };
-        
-        // Register default handlers
-        manager.register_handler("system", CallbackHandler::System(SystemCommandHandler));
-        manager.register_handler("nlp", CallbackHandler::Nlp(NlpCallbackHandler));
-        
+        let manager = Self::empty();
+        manager.register(SystemCommandHandler);
+        manager.register(NlpCallbackHandler);
         manager
     }
 
-    /// Register a new callback handler
-    pub fn register_handler(&mut self, name: &str, handler: CallbackHandler) {
-        info!("Registering callback handler: {}", name);
-        self.handlers.insert(name.to_string(), handler);
+    /// Create a callback manager with the default handlers, the command executor if enabled and
+    /// the configured webhooks, failing if the executor is enabled but can't start
+    pub fn from_config(config: &CallbacksConfig) -> Result<Self> {
+        let manager = Self::new().with_limits(config.max_concurrency, Duration::from_secs(config.timeout_secs));
+        if config.executor.enabled {
+            let executor = SandboxExecutor::new(&config.executor).context("Failed to start the command executor")?;
+            manager.register(executor);
+        }
+        for webhook in &config.webhooks {
+            manager.register(WebhookCallback::new(webhook.clone()));
+        }
+        
+        Ok(manager)
     }
 
-    /// Execute callbacks for a command
-    pub async fn execute_callback(&self, context: &CommandContext) -> Result<Vec<CallbackResult>> {
-        let mut results = Vec::new();
-        
-        for (name, handler) in &self.handlers {
-            if handler.get_supported_commands().contains(&context.command) {
-                info!("Executing callback {} for command: {}", name, context.command);
-                
-                match handler.handle(context).await {
-                    Ok(result) => {
-                        info!("Callback {} completed successfully", name);
-                        results.push(result);
-                    }
-                    Err(e) => {
-                        warn!("Callback {} failed: {}", name, e);
-                        results.push(CallbackResult {
-                            success: false,
-                            message: format!("Callback {} failed: {}", name, e),
-                            data: None,
-                            execution_time_ms: 0,
-                        });
-                    }
-                }
+    /// Create a callback manager without any handlers
+    pub fn empty() -> Self {
+        let defaults = CallbacksConfig::default();
+        Self {
+            callbacks: RwLock::new(Vec::new()),
+            permits: Arc::new(Semaphore::new(defaults.max_concurrency)),
+            timeout: Duration::from_secs(defaults.timeout_secs),
+        }
+    }
+
+    /// Limit how many callbacks run at once and how long each may take by default
+    pub fn with_limits(mut self, max_concurrency: usize, timeout: Duration) -> Self {
+        self.permits = Arc::new(Semaphore::new(max_concurrency.max(1)));
+        self.timeout = timeout;
+        self
+    }
+
+    /// Register a callback, replacing any callback with the same name
+    pub fn register(&self, callback: impl Callback + 'static) {
+        self.register_shared(Arc::new(callback));
+    }
+
+    /// Register a callback that is shared with the caller
+    pub fn register_shared(&self, callback: Arc<dyn Callback>) {
+        info!("Registering callback handler: {}", callback.name());
+        let mut callbacks = self.callbacks.write().unwrap_or_else(|e| e.into_inner());
+        let registration = Registration {
+            callback,
+            enabled: true,
+        };
+        match callbacks
+            .iter()
+            .position(|r| r.callback.name() == registration.callback.name())
+        {
+            Some(index) => callbacks[index] = registration,
+            None => callbacks.push(registration),
+        }
+        // Stable, so equal priorities keep their registration order
+        callbacks.sort_by_key(|r| r.callback.priority());
+    }
+
+    /// Remove a callback; returns whether it was registered
+    pub fn unregister(&self, name: &str) -> bool {
+        let mut callbacks = self.callbacks.write().unwrap_or_else(|e| e.into_inner());
+        let before = callbacks.len();
+        callbacks.retain(|r| r.callback.name() != name);
+        callbacks.len() != before
+    }
+
+    /// Enable or disable a callback without unregistering it; returns whether it was registered
+    pub fn set_enabled(&self, name: &str, enabled: bool) -> bool {
+        let mut callbacks = self.callbacks.write().unwrap_or_else(|e| e.into_inner());
+        match callbacks.iter_mut().find(|r| r.callback.name() == name) {
+            Some(registration) => {
+                registration.enabled = enabled;
+                true
             }
+            None => false,
         }
-        
-        if results.is_empty() {
+    }
+
+    /// Describe the registered callbacks in the order they run
+    pub fn list(&self) -> Vec<CallbackInfo> {
+        self.callbacks
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|r| CallbackInfo {
+                name: r.callback.name().to_string(),
+                priority: r.callback.priority(),
+                enabled: r.enabled,
+                guard: r.callback.is_guard(),
+                background: r.callback.is_background(),
+                supported_commands: r.callback.supported_commands(),
+            })
+            .collect()
+    }
+
+    /// Execute callbacks for a command
+    ///
+    /// Background callbacks are started and reported as running; the rest are awaited.
+    pub async fn execute_callback(&self, context: &CommandContext) -> Result<Vec<CallbackResult>> {
+        // Take a snapshot so callbacks can be changed while these run
+        let callbacks: Vec<Arc<dyn Callback>> = self
+            .callbacks
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|r| r.runs_for(&context.command))
+            .map(|r| Arc::clone(&r.callback))
+            .collect();
+
+        if callbacks.is_empty() {
             info!("No callbacks found for command: {}", context.command);
-            results.push(CallbackResult {
+            return Ok(vec![CallbackResult {
                 success: true,
                 message: format!("No callbacks registered for command: {}", context.command),
                 data: None,
                 execution_time_ms: 0,
-            });
+            }]);
+        }
+
+        let mut results: Vec<CallbackResult> = Vec::new();
+        let mut context = context.clone();
+        
+        // Sorted by priority, so each chunk is one priority level
+        for level in callbacks.chunk_by(|a, b| a.priority() == b.priority()) {
+            let (background, foreground): (Vec<_>, Vec<_>) = level
+                .iter()
+                .partition(|callback| callback.is_background() && !callback.is_guard());
+
+            let outcomes = futures::future::join_all(
+                foreground
+                    .iter()
+                    .map(|callback| run_callback(callback.as_ref(), &context, &self.permits, self.timeout)),
+            )
+            .await;
+
+            let guard_failed = foreground
+                .iter()
+                .zip(&outcomes)
+                .find(|(callback, result)| callback.is_guard() && !result.success);
+            if let Some((callback, _)) = guard_failed {
+                info!("Guard callback {} failed, skipping the remaining callbacks", callback.name());
+                results.extend(outcomes);
+                break;
+            }
+
+            for callback in background {
+                results.push(self.spawn_background(Arc::clone(callback), &context));
+            }
+            if let Some(last) = outcomes.last() {
+                context.previous = Some(last.clone());
+            }
+            results.extend(outcomes);
         }
         
         Ok(results)
     }
 
-    /// Get all supported commands across all handlers
-    pub fn get_all_supported_commands(&self) -> Vec<String> {
-        let mut all_commands = Vec::new();
-        
-        for handler in self.handlers.values() {
-            all_commands.extend(handler.get_supported_commands());
+    /// Run a callback without waiting for it; its outcome is only logged and counted
+    fn spawn_background(&self, callback: Arc<dyn Callback>, context: &CommandContext) -> CallbackResult {
+        let name = callback.name().to_string();
+        let context = context.clone();
+        let permits = Arc::clone(&self.permits);
+        let timeout = self.timeout;
+        tokio::spawn(async move {
+            run_callback(callback.as_ref(), &context, &permits, timeout).await;
+        });
+
+        CallbackResult {
+            success: true,
+            message: format!("Callback {} is running in the background", name),
+            data: Some(serde_json::json!({ "background": true })),
+            execution_time_ms: 0,
         }
+    }
+
+    /// Get all commands that some callback explicitly supports
+    pub fn get_all_supported_commands(&self) -> Vec<String> {
+        let mut all_commands: Vec<String> = self
+            .callbacks
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .flat_map(|r| r.callback.supported_commands().unwrap_or_default())
+            .collect();
         
         all_commands.sort();
         all_commands.dedup();
         all_commands
     }
-
-    /// Get handler information
-    pub fn get_handler_info(&self) -> Vec<serde_json::Value> {
-        self.handlers
-            .iter()
-            .map(|(name, handler)| {
-                serde_json::json!({
-                    "handler_name": name,
-                    "handler_type": handler.get_handler_name(),
-                    "supported_commands": handler.get_supported_commands()
-                })
-            })
-            .collect()
-    }
 }
 
 impl Default for CallbackManager {
@@ -600,6 +1044,16 @@ mod tests {
         assert!(commands.contains(&"install".to_string()));
     }
 
+    #[test]
+    fn test_from_config_fails_when_the_executor_cannot_start() {
+        let mut config = CallbacksConfig::default();
+        config.executor.enabled = true;
+        config.executor.working_dir = std::env::temp_dir().join(format!("rustlm-missing-{}", uuid::Uuid::new_v4()));
+
+        let error = CallbackManager::from_config(&config).err().unwrap();
+        assert!(format!("{:#}", error).contains("command executor"));
+    }
+
     #[tokio::test]
     async fn test_system_command_callback() {
         let handler = SystemCommandHandler;
@@ -607,10 +1061,11 @@ mod tests {
             command: "install".to_string(),
             task: "install".to_string(),
             input_text: "rust".to_string(),
-            parsed_result: r#"{"command": "install", "package": "rust", "suggested_commands": ["brew install rust", "cargo install rust"]}"#.to_string(),
+            parsed_result: serde_json::json!({"command": "install", "package": "rust", "suggested_commands": ["brew install rust", "cargo install rust"]}),
             confidence: Some(0.9),
             timestamp: chrono::Utc::now(),
             session_id: None,
+            previous: None,
         };
 
         let result = handler.handle(&context).await.unwrap();
@@ -625,10 +1080,11 @@ mod tests {
             command: "sentiment".to_string(),
             task: "sentiment".to_string(),
             input_text: "I love this!".to_string(),
-            parsed_result: r#"{"sentiment": "positive", "positive_score": 1, "negative_score": 0}"#.to_string(),
+            parsed_result: serde_json::json!({"sentiment": "positive", "positive_score": 1, "negative_score": 0}),
             confidence: Some(0.8),
             timestamp: chrono::Utc::now(),
             session_id: None,
+            previous: None,
         };
 
         let result = handler.handle(&context).await.unwrap();
@@ -643,14 +1099,393 @@ mod tests {
             command: "sentiment".to_string(),
             task: "sentiment".to_string(),
             input_text: "Great product!".to_string(),
-            parsed_result: r#"{"sentiment": "positive"}"#.to_string(),
+            parsed_result: serde_json::json!({"sentiment": "positive"}),
             confidence: Some(0.9),
             timestamp: chrono::Utc::now(),
             session_id: None,
+            previous: None,
         };
 
         let results = manager.execute_callback(&context).await.unwrap();
         assert!(!results.is_empty());
         assert!(results.iter().any(|r| r.success));
     }
+
+    /// Callback that reports its own name, for checking which callbacks ran
+    struct NamedCallback {
+        name: &'static str,
+        priority: i32,
+        commands: Option<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Callback for NamedCallback {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn supported_commands(&self) -> Option<Vec<String>> {
+            self.commands.clone()
+        }
+
+        fn priority(&self) -> i32 {
+            self.priority
+        }
+
+        async fn handle(&self, _context: &CommandContext) -> Result<CallbackResult> {
+            Ok(CallbackResult {
+                success: true,
+                message: self.name.to_string(),
+                data: None,
+                execution_time_ms: 0,
+            })
+        }
+    }
+
+    fn context(command: &str) -> CommandContext {
+        CommandContext {
+            command: command.to_string(),
+            task: command.to_string(),
+            input_text: "text".to_string(),
+            parsed_result: serde_json::json!({}),
+            confidence: None,
+            timestamp: chrono::Utc::now(),
+            session_id: None,
+            previous: None,
+        }
+    }
+
+    async fn ran(manager: &CallbackManager, command: &str) -> Vec<String> {
+        let results = manager.execute_callback(&context(command)).await.unwrap();
+        results.into_iter().map(|r| r.message).collect()
+    }
+
+    #[tokio::test]
+    async fn test_runtime_registration() {
+        let manager = CallbackManager::empty();
+        manager.register(NamedCallback { name: "audit", priority: 10, commands: None });
+        manager.register(NamedCallback { name: "first", priority: -5, commands: None });
+        manager.register(NamedCallback {
+            name: "installs",
+            priority: 0,
+            commands: Some(vec!["install".to_string()]),
+        });
+
+        assert_eq!(ran(&manager, "install").await, vec!["first", "installs", "audit"]);
+        assert_eq!(ran(&manager, "sentiment").await, vec!["first", "audit"]);
+
+        assert!(manager.set_enabled("first", false));
+        assert_eq!(ran(&manager, "sentiment").await, vec!["audit"]);
+
+        assert!(manager.unregister("audit"));
+        assert!(!manager.unregister("audit"));
+        let listed = manager.list();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].name, "first");
+        assert!(!listed[0].enabled);
+        assert_eq!(listed[1].supported_commands, Some(vec!["install".to_string()]));
+    }
+
+    /// Adds one to the `count` found in the previous result's data
+    struct CountCallback(&'static str, i32);
+
+    #[async_trait]
+    impl Callback for CountCallback {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn priority(&self) -> i32 {
+            self.1
+        }
+
+        async fn handle(&self, context: &CommandContext) -> Result<CallbackResult> {
+            let count = context
+                .previous
+                .as_ref()
+                .and_then(|p| p.data.as_ref())
+                .and_then(|d| d["count"].as_u64())
+                .unwrap_or(0);
+            Ok(CallbackResult {
+                success: true,
+                message: self.0.to_string(),
+                data: Some(serde_json::json!({ "count": count + 1 })),
+                execution_time_ms: 0,
+            })
+        }
+    }
+
+    /// Guard that rejects input containing "blocked"
+    struct BlocklistGuard;
+
+    #[async_trait]
+    impl Callback for BlocklistGuard {
+        fn name(&self) -> &str {
+            "blocklist"
+        }
+
+        fn is_guard(&self) -> bool {
+            true
+        }
+
+        async fn handle(&self, context: &CommandContext) -> Result<CallbackResult> {
+            let allowed = !context.input_text.contains("blocked");
+            Ok(CallbackResult {
+                success: allowed,
+                message: "blocklist".to_string(),
+                data: None,
+                execution_time_ms: 0,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_callbacks_consume_previous_result() {
+        let manager = CallbackManager::empty();
+        manager.register(CountCallback("third", 3));
+        manager.register(CountCallback("first", 1));
+        manager.register(CountCallback("second", 2));
+
+        let results = manager.execute_callback(&context("sentiment")).await.unwrap();
+        let counts: Vec<(String, u64)> = results
+            .iter()
+            .map(|r| (r.message.clone(), r.data.as_ref().unwrap()["count"].as_u64().unwrap()))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![("first".to_string(), 1), ("second".to_string(), 2), ("third".to_string(), 3)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failing_guard_stops_the_chain() {
+        let manager = CallbackManager::empty();
+        manager.register(BlocklistGuard);
+        manager.register(NamedCallback { name: "after", priority: 1, commands: None });
+
+        assert_eq!(ran(&manager, "sentiment").await, vec!["blocklist", "after"]);
+
+        let mut blocked = context("sentiment");
+        blocked.input_text = "blocked text".to_string();
+        let results = manager.execute_callback(&blocked).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(!results[0].success);
+        assert!(manager.list()[0].guard);
+    }
+
+    /// Callback that sleeps before succeeding and signals when it's done
+    struct SlowCallback {
+        name: &'static str,
+        delay: Duration,
+        background: bool,
+        done: Arc<tokio::sync::Notify>,
+    }
+
+    impl SlowCallback {
+        fn new(name: &'static str, delay_ms: u64) -> Self {
+            Self {
+                name,
+                delay: Duration::from_millis(delay_ms),
+                background: false,
+                done: Arc::new(tokio::sync::Notify::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Callback for SlowCallback {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn is_background(&self) -> bool {
+            self.background
+        }
+
+        async fn handle(&self, _context: &CommandContext) -> Result<CallbackResult> {
+            tokio::time::sleep(self.delay).await;
+            self.done.notify_one();
+            Ok(CallbackResult {
+                success: true,
+                message: self.name.to_string(),
+                data: None,
+                execution_time_ms: 0,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_equal_priorities_run_concurrently_up_to_the_limit() {
+        let manager = CallbackManager::empty();
+        manager.register(SlowCallback::new("a", 150));
+        manager.register(SlowCallback::new("b", 150));
+
+        let start = std::time::Instant::now();
+        assert_eq!(ran(&manager, "sentiment").await, vec!["a", "b"]);
+        assert!(start.elapsed() < Duration::from_millis(280));
+
+        let manager = CallbackManager::empty().with_limits(1, Duration::from_secs(5));
+        manager.register(SlowCallback::new("a", 150));
+        manager.register(SlowCallback::new("b", 150));
+
+        let start = std::time::Instant::now();
+        assert_eq!(ran(&manager, "sentiment").await, vec!["a", "b"]);
+        assert!(start.elapsed() >= Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn test_slow_callback_times_out() {
+        let manager = CallbackManager::empty().with_limits(4, Duration::from_millis(50));
+        manager.register(SlowCallback::new("slow", 5_000));
+        manager.register(SlowCallback::new("fast", 0));
+
+        let results = manager.execute_callback(&context("sentiment")).await.unwrap();
+        assert!(!results[0].success);
+        assert!(results[0].message.contains("timed out"));
+        assert_eq!(results[0].data.as_ref().unwrap()["reason"], "timeout");
+        assert!(results[1].success);
+    }
+
+    #[tokio::test]
+    async fn test_waiting_for_a_permit_counts_towards_the_timeout() {
+        let manager = CallbackManager::empty().with_limits(1, Duration::from_millis(50));
+        manager.register(SlowCallback::new("fast", 0));
+
+        // E.g. held by a slow background callback
+        let _held = manager.permits.acquire().await.unwrap();
+        let results = tokio::time::timeout(Duration::from_secs(5), manager.execute_callback(&context("sentiment")))
+            .await
+            .expect("callbacks returned while the permits were taken")
+            .unwrap();
+        assert!(!results[0].success);
+        assert_eq!(results[0].data.as_ref().unwrap()["reason"], "timeout");
+    }
+
+    #[tokio::test]
+    async fn test_background_callback_does_not_delay_results() {
+        let manager = CallbackManager::empty();
+        let callback = SlowCallback {
+            background: true,
+            ..SlowCallback::new("background", 200)
+        };
+        let done = Arc::clone(&callback.done);
+        manager.register(callback);
+
+        let start = std::time::Instant::now();
+        let results = manager.execute_callback(&context("sentiment")).await.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert_eq!(results[0].data.as_ref().unwrap()["background"], true);
+        assert!(manager.list()[0].background);
+
+        tokio::time::timeout(Duration::from_secs(5), done.notified())
+            .await
+            .expect("background callback finished");
+    }
+
+    #[tokio::test]
+    async fn test_register_replaces_callback_with_same_name() {
+        let manager = CallbackManager::new();
+        manager.register(NamedCallback { name: "nlp", priority: 0, commands: None });
+
+        assert_eq!(manager.list().len(), 2);
+        assert_eq!(ran(&manager, "sentiment").await, vec!["nlp"]);
+    }
+
+    type Received = Arc<std::sync::Mutex<Vec<(axum::http::HeaderMap, bytes::Bytes)>>>;
+    type Statuses = Arc<std::sync::Mutex<std::vec::IntoIter<u16>>>;
+
+    /// Start a webhook receiver that answers with `statuses` in turn, then 200, recording each request
+    async fn spawn_webhook_receiver(statuses: Vec<u16>) -> (String, Received) {
+        use axum::{extract::State, http::StatusCode, routing::post, Router};
+
+        let received: Received = Arc::default();
+        let statuses: Statuses = Arc::new(std::sync::Mutex::new(statuses.into_iter()));
+
+        let app = Router::new()
+            .route(
+                "/hook",
+                post(
+                    |State((received, statuses)): State<(Received, Statuses)>,
+                     headers: axum::http::HeaderMap,
+                     body: bytes::Bytes| async move {
+                        received.lock().unwrap().push((headers, body));
+                        let status = statuses.lock().unwrap().next().unwrap_or(200);
+                        StatusCode::from_u16(status).unwrap()
+                    },
+                ),
+            )
+            .with_state((Arc::clone(&received), statuses));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (format!("http://{}/hook", address), received)
+    }
+
+    fn webhook_config(url: &str) -> WebhookConfig {
+        WebhookConfig {
+            secret: Some("webhook-secret".to_string()),
+            initial_backoff_ms: 1,
+            ..WebhookConfig::new("results", url)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_webhook_retries_and_signs() {
+        let (url, received) = spawn_webhook_receiver(vec![503]).await;
+        let mut config = webhook_config(&url);
+        config.headers.insert("x-team".to_string(), "search".to_string());
+        let webhook = WebhookCallback::new(config);
+
+        let result = webhook.handle(&context("install")).await.unwrap();
+        assert!(result.success);
+        assert_eq!(result.data.as_ref().unwrap()["attempts"], 2);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        let (headers, body) = &received[1];
+        assert_eq!(headers["x-team"], "search");
+        assert_eq!(headers[WEBHOOK_DELIVERY_HEADER], received[0].0[WEBHOOK_DELIVERY_HEADER]);
+
+        let mut mac = HmacSha256::new_from_slice(b"webhook-secret").unwrap();
+        mac.update(body);
+        let expected: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(headers[WEBHOOK_SIGNATURE_HEADER], format!("sha256={}", expected).as_str());
+
+        let payload: WebhookPayload = serde_json::from_slice(body).unwrap();
+        assert_eq!(payload.context.command, "install");
+        assert!(payload.result.success);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_failures_are_dead_lettered() {
+        let dead_letters = std::env::temp_dir().join(format!("rustlm-dead-letters-{}.jsonl", uuid::Uuid::new_v4()));
+
+        // Server errors are retried up to max_attempts
+        let (url, received) = spawn_webhook_receiver(vec![500, 500, 500]).await;
+        let mut config = webhook_config(&url);
+        config.max_attempts = 2;
+        config.dead_letter_file = Some(dead_letters.clone());
+        let result = WebhookCallback::new(config).handle(&context("sentiment")).await.unwrap();
+        assert!(!result.success);
+        assert_eq!(received.lock().unwrap().len(), 2);
+
+        // Client errors are not retried
+        let (url, received) = spawn_webhook_receiver(vec![400]).await;
+        let mut config = webhook_config(&url);
+        config.dead_letter_file = Some(dead_letters.clone());
+        let result = WebhookCallback::new(config).handle(&context("sentiment")).await.unwrap();
+        assert!(!result.success);
+        assert_eq!(received.lock().unwrap().len(), 1);
+
+        let log = std::fs::read_to_string(&dead_letters).unwrap();
+        std::fs::remove_file(&dead_letters).unwrap();
+        let letters: Vec<DeadLetter> = log.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(letters.len(), 2);
+        assert_eq!(letters[0].attempts, 2);
+        assert!(letters[0].error.contains("500"));
+        assert_eq!(letters[1].attempts, 1);
+        assert_eq!(letters[1].payload.context.command, "sentiment");
+    }
 }
@@ -0,0 +1,354 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+
+use crate::platform::Platform;
+use crate::results::PackageResolution;
+use crate::shell::{self, ShellCommand};
+
+/// Catalog shipped with the server, see `data/packages.toml`
+const BUNDLED_CATALOG: &str = include_str!("../data/packages.toml");
+
+/// Package managers a catalog entry may name a package for
+const MANAGERS: &[&str] = &[
+    "apt", "dnf", "pacman", "apk", "zypper", "brew", "winget", "cargo", "npm", "pip", "gem", "go",
+];
+
+static BUNDLED: LazyLock<Arc<PackageCatalog>> = LazyLock::new(|| {
+    Arc::new(PackageCatalog::from_toml(BUNDLED_CATALOG).expect("bundled package catalog is valid"))
+});
+
+/// Where a package is usually installed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ecosystem {
+    /// The platform's package manager
+    System,
+    Cargo,
+    Npm,
+    Pip,
+    Gem,
+    Go,
+}
+
+impl Ecosystem {
+    /// The install command for a language ecosystem; `None` for `System`, which depends on the platform
+    fn install_command(&self) -> Option<ShellCommand> {
+        let command = match self {
+            Ecosystem::System => return None,
+            Ecosystem::Cargo => ShellCommand::new("cargo").arg("install"),
+            Ecosystem::Npm => ShellCommand::new("npm").arg("install"),
+            Ecosystem::Pip => ShellCommand::new("pip").arg("install"),
+            Ecosystem::Gem => ShellCommand::new("gem").arg("install"),
+            Ecosystem::Go => ShellCommand::new("go").arg("install"),
+        };
+        Some(command)
+    }
+}
+
+/// A package known to the catalog
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CatalogEntry {
+    pub name: String,
+    /// Other names the package is asked for by, e.g. `rg` for `ripgrep`
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Ecosystems the package is available from, most likely first
+    pub ecosystems: Vec<Ecosystem>,
+    /// Package name for the system package managers when it isn't `name`
+    #[serde(default)]
+    pub system: Option<String>,
+    /// Package name for a single manager, e.g. `apt = "fd-find"`
+    #[serde(default)]
+    pub managers: BTreeMap<String, String>,
+}
+
+impl CatalogEntry {
+    /// The package name `manager` knows this package by, if it has it
+    pub fn package_for(&self, manager: &str, ecosystem: Ecosystem) -> Option<&str> {
+        if let Some(package) = self.managers.get(manager) {
+            return Some(package);
+        }
+        match ecosystem {
+            // winget ids are `Publisher.Package` and can't be guessed from the name
+            Ecosystem::System if manager == "winget" => None,
+            Ecosystem::System => Some(self.system.as_deref().unwrap_or(&self.name)),
+            _ => Some(&self.name),
+        }
+    }
+
+    fn names(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.name).chain(&self.aliases)
+    }
+
+    fn validate(&self, errors: &mut Vec<String>) {
+        if self.name.trim().is_empty() {
+            errors.push("a package has an empty name".to_string());
+            return;
+        }
+        if self.ecosystems.is_empty() {
+            errors.push(format!("package '{}' lists no ecosystems", self.name));
+        }
+        for (manager, package) in &self.managers {
+            if !MANAGERS.contains(&manager.as_str()) {
+                errors.push(format!(
+                    "package '{}' names unknown manager '{}', expected one of {}",
+                    self.name,
+                    manager,
+                    MANAGERS.join(", ")
+                ));
+            }
+            if let Err(reason) = shell::validate_package_name(package) {
+                errors.push(format!("package '{}': {}", self.name, reason));
+            }
+        }
+        for name in self.names().chain(&self.system) {
+            if let Err(reason) = shell::validate_package_name(name) {
+                errors.push(format!("package '{}': {}", self.name, reason));
+            }
+        }
+    }
+}
+
+/// Layout of a catalog file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CatalogFile {
+    #[serde(default)]
+    packages: Vec<CatalogEntry>,
+}
+
+/// Offline mapping from package names and aliases to the package each manager installs
+#[derive(Debug, Default)]
+pub struct PackageCatalog {
+    entries: Vec<CatalogEntry>,
+    /// Lowercase names and aliases to their entry
+    index: HashMap<String, usize>,
+}
+
+impl PackageCatalog {
+    /// The catalog shipped with the server
+    pub fn bundled() -> Arc<Self> {
+        BUNDLED.clone()
+    }
+
+    /// The bundled catalog extended by a catalog file; its entries replace bundled ones sharing a name or alias
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read package catalog {}", path.display()))?;
+        let user = Self::from_toml(&contents)
+            .with_context(|| format!("Invalid package catalog {}", path.display()))?;
+
+        let bundled = BUNDLED
+            .entries
+            .iter()
+            .filter(|entry| entry.names().all(|name| user.lookup(name).is_none()))
+            .cloned()
+            .collect::<Vec<_>>();
+        Self::new(bundled.into_iter().chain(user.entries).collect())
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        let file: CatalogFile = toml::from_str(contents)?;
+        Self::new(file.packages)
+    }
+
+    fn new(entries: Vec<CatalogEntry>) -> Result<Self> {
+        let mut errors = Vec::new();
+        let mut index = HashMap::new();
+        for (position, entry) in entries.iter().enumerate() {
+            entry.validate(&mut errors);
+            for name in entry.names() {
+                if let Some(other) = index.insert(name.to_lowercase(), position) {
+                    if other != position {
+                        errors.push(format!(
+                            "'{}' names both package '{}' and package '{}'",
+                            name, entries[other].name, entry.name
+                        ));
+                    }
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(Self { entries, index })
+        } else {
+            bail!("Invalid package catalog:\n  - {}", errors.join("\n  - "))
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Find a package by name or alias, ignoring case
+    pub fn lookup(&self, name: &str) -> Option<&CatalogEntry> {
+        self.index.get(&name.to_lowercase()).map(|&position| &self.entries[position])
+    }
+
+    /// Install commands for `package` on `platform`, most likely first
+    pub fn suggest(&self, package: &str, platform: Platform) -> (Vec<ShellCommand>, PackageResolution) {
+        let Some(entry) = self.lookup(package) else {
+            // Unknown names are only tried with the platform's curated package repositories; public
+            // registries like npm, crates.io and PyPI would install whatever squats on the name,
+            // and winget ids can't be guessed
+            let commands: Vec<ShellCommand> = platform
+                .package_manager()
+                .filter(|manager| manager.program != "winget")
+                .map(|manager| manager.arg(package))
+                .into_iter()
+                .collect();
+            let resolution = PackageResolution {
+                known: false,
+                name: None,
+                description: None,
+                managers: commands.iter().map(|command| command.program.clone()).collect(),
+                note: Some(if commands.is_empty() {
+                    format!("'{}' is not in the package catalog and can't be looked up on {}", package, platform)
+                } else {
+                    format!(
+                        "'{}' is not in the package catalog, so the name is used as-is for the system package manager",
+                        package
+                    )
+                }),
+            };
+            return (commands, resolution);
+        };
+
+        let mut commands = Vec::new();
+        for &ecosystem in &entry.ecosystems {
+            let Some(manager) = ecosystem.install_command().or_else(|| platform.package_manager()) else {
+                continue;
+            };
+            let Some(name) = entry.package_for(&manager.program, ecosystem) else {
+                continue;
+            };
+            let command = if ecosystem == Ecosystem::Go {
+                manager.arg(format!("{}@latest", name))
+            } else {
+                manager.arg(name)
+            };
+            commands.push(command);
+        }
+
+        let note = commands.is_empty().then(|| {
+            format!("No package manager for '{}' is available on {}", entry.name, platform)
+        });
+        let resolution = PackageResolution {
+            known: true,
+            name: Some(entry.name.clone()),
+            description: entry.description.clone(),
+            managers: commands.iter().map(|command| command.program.clone()).collect(),
+            note,
+        };
+        (commands, resolution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rendered(catalog: &PackageCatalog, package: &str, platform: Platform) -> Vec<String> {
+        catalog.suggest(package, platform).0.iter().map(ShellCommand::render).collect()
+    }
+
+    #[test]
+    fn test_bundled_catalog_resolves_aliases_per_manager() {
+        let catalog = PackageCatalog::bundled();
+        assert!(!catalog.is_empty());
+
+        assert_eq!(rendered(&catalog, "ripgrep", Platform::Debian), vec!["apt install ripgrep", "cargo install ripgrep"]);
+        assert_eq!(rendered(&catalog, "RG", Platform::MacOs), vec!["brew install ripgrep", "cargo install ripgrep"]);
+        assert_eq!(rendered(&catalog, "fd", Platform::Debian), vec!["apt install fd-find", "cargo install fd-find"]);
+        assert_eq!(rendered(&catalog, "gh", Platform::Arch), vec!["pacman -S github-cli"]);
+        assert_eq!(rendered(&catalog, "node", Platform::MacOs), vec!["brew install node"]);
+        assert_eq!(rendered(&catalog, "fzf", Platform::Linux), vec!["go install github.com/junegunn/fzf@latest"]);
+
+        // Language packages rank their own ecosystem first
+        let (_, resolution) = catalog.suggest("black", Platform::Fedora);
+        assert_eq!(resolution.managers, vec!["pip", "dnf"]);
+        assert_eq!(resolution.name.as_deref(), Some("black"));
+    }
+
+    #[test]
+    fn test_unknown_and_unavailable_packages() {
+        let catalog = PackageCatalog::bundled();
+
+        let (commands, resolution) = catalog.suggest("left-pad", Platform::Debian);
+        assert!(!resolution.known);
+        assert!(resolution.note.unwrap().contains("not in the package catalog"));
+        assert_eq!(commands[0].render(), "apt install left-pad");
+        assert_eq!(resolution.managers, vec!["apt"]);
+
+        let (commands, resolution) = catalog.suggest("left-pad", Platform::Windows);
+        assert!(!resolution.known && commands.is_empty());
+        assert!(resolution.note.unwrap().contains("not in the package catalog"));
+
+        // htop has no winget id, so nothing is suggested on Windows
+        let (commands, resolution) = catalog.suggest("htop", Platform::Windows);
+        assert!(resolution.known && commands.is_empty());
+        assert!(resolution.note.unwrap().contains("windows"));
+    }
+
+    #[test]
+    fn test_catalog_file_overrides_bundled_entries() {
+        let path = std::env::temp_dir().join(format!("rustlm-catalog-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"
+            [[packages]]
+            name = "ripgrep"
+            ecosystems = ["cargo"]
+
+            [[packages]]
+            name = "internal-cli"
+            aliases = ["icli"]
+            ecosystems = ["npm"]
+            managers = { npm = "@acme/internal-cli" }
+            "#,
+        )
+        .unwrap();
+        let catalog = PackageCatalog::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(rendered(&catalog, "ripgrep", Platform::MacOs), vec!["cargo install ripgrep"]);
+        assert_eq!(rendered(&catalog, "icli", Platform::MacOs), vec!["npm install @acme/internal-cli"]);
+        assert!(catalog.lookup("jq").is_some());
+    }
+
+    #[test]
+    fn test_invalid_catalog_reports_every_problem() {
+        let error = PackageCatalog::from_toml(
+            r#"
+            [[packages]]
+            name = "one"
+            aliases = ["shared"]
+            ecosystems = ["system"]
+            managers = { yum = "one" }
+
+            [[packages]]
+            name = "two"
+            aliases = ["shared"]
+            ecosystems = []
+            system = "two; rm -rf /"
+            "#,
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(error.contains("unknown manager 'yum'"));
+        assert!(error.contains("'shared' names both package 'one' and package 'two'"));
+        assert!(error.contains("package 'two' lists no ecosystems"));
+        assert!(error.contains("may only contain letters"));
+    }
+}
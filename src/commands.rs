@@ -0,0 +1,1110 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::catalog::PackageCatalog;
+use crate::download::{Checksum, Downloader};
+use crate::git::GitRepository;
+use crate::llm::{self, AzureOpenAiConfig, ChatMessage, LlmProvider};
+use crate::results::{
+    AskAiResult, CommandSubject, CommandSuggestion, InputCheck, SearchSuggestion, ToolListing,
+    RefKind, RepositoryInfo, ToolsResult, UsageError, WorkspaceResults,
+};
+use crate::search::WorkspaceSearch;
+use crate::shell::{self, ShellCommand};
+use crate::tasks::{
+    text_input_schema, Task, TaskCategory, TaskContext, TaskOutput, TaskRegistry, TaskStream,
+    TaskStreamEvent,
+};
+
+/// Register the built-in system command tasks
+pub(crate) fn register_builtin_tasks(registry: &mut TaskRegistry) {
+    registry.register(InstallTask::default());
+    registry.register(FindFileTask::default());
+    registry.register(FindContentTask::default());
+    registry.register(GetFileFromTask::default());
+    registry.register(ShowToolsTask);
+    registry.register(OpenAppTask);
+    registry.register(OpenFileTask);
+    registry.register(CheckoutTask::default());
+    registry.register(DiffTask::default());
+    registry.register(GoogleSearchTask);
+    registry.register(AskAiTask::default());
+}
+
+/// Suggests package manager commands to install a package
+#[derive(Debug, Clone)]
+pub struct InstallTask {
+    catalog: Arc<PackageCatalog>,
+}
+
+impl InstallTask {
+    /// Resolve package names through `catalog` instead of the bundled one
+    pub fn with_catalog(catalog: Arc<PackageCatalog>) -> Self {
+        Self { catalog }
+    }
+}
+
+impl Default for InstallTask {
+    fn default() -> Self {
+        Self::with_catalog(PackageCatalog::bundled())
+    }
+}
+
+#[async_trait]
+impl Task for InstallTask {
+    fn name(&self) -> &str {
+        "install"
+    }
+
+    fn description(&self) -> &str {
+        "Suggest commands to install a package"
+    }
+
+    fn category(&self) -> TaskCategory {
+        TaskCategory::System
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        text_input_schema("Name of the package to install")
+    }
+
+    async fn run(&self, text: &str, context: &TaskContext<'_>) -> Result<TaskOutput> {
+        info!("Processing install command: {}", text);
+
+        let mut input = InputCheck::default();
+        let package = shell::strip_control_characters(text.trim(), &mut input);
+        if package.is_empty() {
+            return Ok(TaskOutput::new(UsageError::new("install", "Package name required", "install <package_name>"), Some(0.9)));
+        }
+        if let Err(reason) = shell::validate_package_name(&package) {
+            input.reject(reason);
+        }
+
+        let platform = context.platform();
+        if input.is_rejected() {
+            let result = CommandSuggestion::new("install", CommandSubject::Package(package), Vec::new(), input)
+                .for_platform(platform);
+            return Ok(TaskOutput::new(result, Some(0.9)));
+        }
+
+        let (commands, resolution) = self.catalog.suggest(&package, platform);
+        let result = CommandSuggestion::new("install", CommandSubject::Package(package), commands, input)
+            .for_platform(platform)
+            .with_resolution(resolution);
+
+        Ok(TaskOutput::new(result, Some(0.9)))
+    }
+}
+
+/// Suggests commands to find a file by name
+#[derive(Debug, Clone, Default)]
+pub struct FindFileTask {
+    search: Option<Arc<WorkspaceSearch>>,
+}
+
+impl FindFileTask {
+    /// Also search the workspace roots on the server
+    pub fn with_search(search: Arc<WorkspaceSearch>) -> Self {
+        Self { search: Some(search) }
+    }
+}
+
+#[async_trait]
+impl Task for FindFileTask {
+    fn name(&self) -> &str {
+        "find_file"
+    }
+
+    fn description(&self) -> &str {
+        "Suggest commands to find a file by name"
+    }
+
+    fn category(&self) -> TaskCategory {
+        TaskCategory::System
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        text_input_schema("File name or pattern to search for")
+    }
+
+    async fn run(&self, text: &str, _context: &TaskContext<'_>) -> Result<TaskOutput> {
+        info!("Processing find file command: {}", text);
+
+        let mut input = InputCheck::default();
+        let filename = shell::strip_control_characters(text.trim(), &mut input);
+        if filename.is_empty() {
+            return Ok(TaskOutput::new(UsageError::new("find_file", "Filename required", "find_file <filename>"), Some(0.9)));
+        }
+
+        let commands = vec![
+            ShellCommand::new("find").args([".", "-name"]).arg(&filename),
+            ShellCommand::new("find").args([".", "-iname"]).arg(&filename),
+            ShellCommand::new("locate").operand(&filename),
+            ShellCommand::new("fd").operand(&filename),
+        ];
+        let workspace = match &self.search {
+            Some(search) => Some(search_workspace(search, &filename, WorkspaceSearch::find_files).await?),
+            None => None,
+        };
+        let mut result = CommandSuggestion::new("find_file", CommandSubject::Filename(filename), commands, input);
+        result.workspace = workspace;
+
+        Ok(TaskOutput::new(result, Some(0.9)))
+    }
+}
+
+/// Suggests commands to search file contents
+#[derive(Debug, Clone, Default)]
+pub struct FindContentTask {
+    search: Option<Arc<WorkspaceSearch>>,
+}
+
+impl FindContentTask {
+    /// Also search the workspace roots on the server
+    pub fn with_search(search: Arc<WorkspaceSearch>) -> Self {
+        Self { search: Some(search) }
+    }
+}
+
+#[async_trait]
+impl Task for FindContentTask {
+    fn name(&self) -> &str {
+        "find_content"
+    }
+
+    fn description(&self) -> &str {
+        "Suggest commands to search for text inside files"
+    }
+
+    fn category(&self) -> TaskCategory {
+        TaskCategory::System
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        text_input_schema("Text to search for")
+    }
+
+    async fn run(&self, text: &str, _context: &TaskContext<'_>) -> Result<TaskOutput> {
+        info!("Processing find content command: {}", text);
+
+        let mut input = InputCheck::default();
+        let search_term = shell::strip_control_characters(text.trim(), &mut input);
+        if search_term.is_empty() {
+            return Ok(TaskOutput::new(UsageError::new("find_content", "Search term required", "find_content <search_term>"), Some(0.9)));
+        }
+
+        let commands = vec![
+            ShellCommand::new("grep").args(["-r", "-e"]).arg(&search_term).arg("."),
+            ShellCommand::new("rg").arg("-e").arg(&search_term),
+            ShellCommand::new("ag").operand(&search_term),
+            ShellCommand::new("find")
+                .args([".", "-type", "f", "-exec", "grep", "-l", "-e"])
+                .arg(&search_term)
+                .args(["{}", ";"]),
+        ];
+        let workspace = match &self.search {
+            Some(search) => Some(search_workspace(search, &search_term, WorkspaceSearch::find_content).await?),
+            None => None,
+        };
+        let mut result = CommandSuggestion::new("find_content", CommandSubject::SearchTerm(search_term), commands, input);
+        result.workspace = workspace;
+
+        Ok(TaskOutput::new(result, Some(0.9)))
+    }
+}
+
+/// Run a workspace search on the blocking pool, since it reads the file system
+async fn search_workspace(
+    search: &Arc<WorkspaceSearch>,
+    text: &str,
+    find: fn(&WorkspaceSearch, &str) -> WorkspaceResults,
+) -> Result<WorkspaceResults> {
+    let search = search.clone();
+    let text = text.to_string();
+    Ok(tokio::task::spawn_blocking(move || find(&search, &text)).await?)
+}
+
+/// Suggests commands to download or copy a file
+#[derive(Clone, Default)]
+pub struct GetFileFromTask {
+    downloader: Option<Arc<Downloader>>,
+}
+
+impl GetFileFromTask {
+    /// Also download HTTP(S) sources into the server's download directory
+    pub fn with_downloader(downloader: Arc<Downloader>) -> Self {
+        Self {
+            downloader: Some(downloader),
+        }
+    }
+}
+
+#[async_trait]
+impl Task for GetFileFromTask {
+    fn name(&self) -> &str {
+        "get_file_from"
+    }
+
+    fn description(&self) -> &str {
+        "Suggest commands to download or copy a file from a URL or path"
+    }
+
+    fn category(&self) -> TaskCategory {
+        TaskCategory::System
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        text_input_schema("URL or path of the file to fetch, optionally followed by sha256:<hex> or sha512:<hex>")
+    }
+
+    async fn run(&self, text: &str, context: &TaskContext<'_>) -> Result<TaskOutput> {
+        info!("Processing get file from command: {}", text);
+
+        let mut input = InputCheck::default();
+        let mut source = shell::strip_control_characters(text.trim(), &mut input);
+        if source.is_empty() {
+            return Ok(TaskOutput::new(UsageError::new("get_file_from", "Source required", "get_file_from <url_or_path> [sha256:<hex>]"), Some(0.9)));
+        }
+        // A trailing `sha256:<hex>` is the checksum the file is expected to have
+        let mut checksum = None;
+        if let Some((rest, last)) = source.rsplit_once(char::is_whitespace) {
+            if last.to_ascii_lowercase().starts_with("sha") && last.contains(':') {
+                match Checksum::parse(last) {
+                    Ok(parsed) => checksum = Some(parsed),
+                    Err(reason) => input.reject(reason),
+                }
+                source = rest.trim_end().to_string();
+            }
+        }
+
+        let mut download = None;
+        let commands = if source.starts_with("http") {
+            match shell::validate_url(&source) {
+                Ok(url) => {
+                    // Suggesting commands never fetches anything; the caller has to ask for the download
+                    let downloader = self.downloader.as_ref().filter(|_| context.download_requested() && !input.is_rejected());
+                    if let Some(downloader) = downloader {
+                        download = Some(downloader.start(&url, checksum, context.client_id()));
+                    }
+                    let filename = url
+                        .path_segments()
+                        .and_then(|mut segments| segments.next_back())
+                        .filter(|name| !name.is_empty())
+                        .unwrap_or("download")
+                        .to_string();
+                    let filename = shell::option_safe_path(&filename, &mut input);
+                    vec![
+                        ShellCommand::new("curl").arg("-O").arg(url.as_str()),
+                        ShellCommand::new("wget").arg(url.as_str()),
+                        ShellCommand::new("curl").arg("-L").arg(url.as_str()).arg("-o").arg(filename),
+                    ]
+                }
+                Err(reason) => {
+                    input.reject(reason);
+                    Vec::new()
+                }
+            }
+        } else {
+            let path = shell::option_safe_path(&source, &mut input);
+            vec![
+                ShellCommand::new("cp").arg(&path).arg("."),
+                ShellCommand::new("rsync").arg("-av").arg(&path).arg("."),
+                ShellCommand::new("scp").arg(&path).arg("."),
+            ]
+        };
+
+        let mut result = CommandSuggestion::new("get_file_from", CommandSubject::Source(source), commands, input);
+        result.download = download;
+
+        Ok(TaskOutput::new(result, Some(0.9)))
+    }
+}
+
+/// Lists common tools, optionally filtered by category
+pub struct ShowToolsTask;
+
+#[async_trait]
+impl Task for ShowToolsTask {
+    fn name(&self) -> &str {
+        "show_tools"
+    }
+
+    fn description(&self) -> &str {
+        "List common command-line tools by category"
+    }
+
+    fn category(&self) -> TaskCategory {
+        TaskCategory::System
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        text_input_schema("Tool category, or \"all\"")
+    }
+
+    async fn run(&self, text: &str, _context: &TaskContext<'_>) -> Result<TaskOutput> {
+        info!("Processing show tools command: {}", text);
+
+        let category = text.trim().to_lowercase();
+
+        let tool_categories: [(&str, &[&str]); 5] = [
+            ("development", &["git", "cargo", "npm", "yarn", "docker", "kubectl"]),
+            ("system", &["brew", "apt", "yum", "systemctl", "ps", "top", "htop"]),
+            ("file_management", &["ls", "find", "grep", "sed", "awk", "rsync", "tar"]),
+            ("network", &["curl", "wget", "ssh", "scp", "ping", "netstat"]),
+            ("text_editors", &["vim", "nano", "code", "emacs", "sublime"]),
+        ];
+        let to_strings = |tools: &[&str]| tools.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+
+        let tools = if category.is_empty() || category == "all" {
+            ToolListing::All(
+                tool_categories
+                    .iter()
+                    .map(|(name, tools)| (name.to_string(), to_strings(tools)))
+                    .collect::<BTreeMap<_, _>>(),
+            )
+        } else {
+            let key = match category.as_str() {
+                "dev" | "development" => Some("development"),
+                "system" => Some("system"),
+                "file" | "files" => Some("file_management"),
+                "network" => Some("network"),
+                "editor" | "editors" => Some("text_editors"),
+                _ => None,
+            };
+            match tool_categories.iter().find(|(name, _)| Some(*name) == key) {
+                Some((_, tools)) => ToolListing::Category(to_strings(tools)),
+                None => ToolListing::UnknownCategory {
+                    error: "Unknown category".to_string(),
+                    available_categories: tool_categories.iter().map(|(name, _)| name.to_string()).collect(),
+                },
+            }
+        };
+
+        let result = ToolsResult {
+            command: "show_tools".to_string(),
+            category: if category.is_empty() { "all".to_string() } else { category },
+            tools,
+        };
+
+        Ok(TaskOutput::new(result, Some(0.9)))
+    }
+}
+
+/// Suggests commands to launch an application
+pub struct OpenAppTask;
+
+#[async_trait]
+impl Task for OpenAppTask {
+    fn name(&self) -> &str {
+        "open_app"
+    }
+
+    fn description(&self) -> &str {
+        "Suggest commands to launch an application"
+    }
+
+    fn category(&self) -> TaskCategory {
+        TaskCategory::System
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        text_input_schema("Name of the application to open")
+    }
+
+    async fn run(&self, text: &str, context: &TaskContext<'_>) -> Result<TaskOutput> {
+        info!("Processing open app command: {}", text);
+
+        let mut input = InputCheck::default();
+        let app_name = shell::strip_control_characters(text.trim(), &mut input);
+        if app_name.is_empty() {
+            return Ok(TaskOutput::new(UsageError::new("open_app", "App name required", "open_app <app_name>"), Some(0.9)));
+        }
+        if let Err(reason) = shell::validate_app_name(&app_name) {
+            input.reject(reason);
+        }
+
+        let platform = context.platform();
+        let commands = platform.app_launchers(&app_name);
+        let result = CommandSuggestion::new("open_app", CommandSubject::AppName(app_name), commands, input)
+            .for_platform(platform);
+
+        Ok(TaskOutput::new(result, Some(0.9)))
+    }
+}
+
+/// Suggests commands to open a file
+pub struct OpenFileTask;
+
+#[async_trait]
+impl Task for OpenFileTask {
+    fn name(&self) -> &str {
+        "open_file"
+    }
+
+    fn description(&self) -> &str {
+        "Suggest commands to open or view a file"
+    }
+
+    fn category(&self) -> TaskCategory {
+        TaskCategory::System
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        text_input_schema("Path of the file to open")
+    }
+
+    async fn run(&self, text: &str, context: &TaskContext<'_>) -> Result<TaskOutput> {
+        info!("Processing open file command: {}", text);
+
+        let mut input = InputCheck::default();
+        let file_path = shell::strip_control_characters(text.trim(), &mut input);
+        if file_path.is_empty() {
+            return Ok(TaskOutput::new(UsageError::new("open_file", "File path required", "open_file <file_path>"), Some(0.9)));
+        }
+
+        let platform = context.platform();
+        let path = shell::option_safe_path(&file_path, &mut input);
+        let commands = platform.file_openers(&path);
+        let result = CommandSuggestion::new("open_file", CommandSubject::FilePath(file_path), commands, input)
+            .for_platform(platform);
+
+        Ok(TaskOutput::new(result, Some(0.9)))
+    }
+}
+
+/// Suggests Git commands to check out a branch or commit
+#[derive(Debug, Clone, Default)]
+pub struct CheckoutTask {
+    repository: Option<Arc<GitRepository>>,
+}
+
+impl CheckoutTask {
+    /// Look targets up in `repository` and suggest commands for what they turn out to be
+    pub fn with_repository(repository: Arc<GitRepository>) -> Self {
+        Self {
+            repository: Some(repository),
+        }
+    }
+}
+
+#[async_trait]
+impl Task for CheckoutTask {
+    fn name(&self) -> &str {
+        "checkout"
+    }
+
+    fn description(&self) -> &str {
+        "Suggest Git commands to check out a branch or commit"
+    }
+
+    fn category(&self) -> TaskCategory {
+        TaskCategory::System
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        text_input_schema("Branch name or commit to check out")
+    }
+
+    async fn run(&self, text: &str, _context: &TaskContext<'_>) -> Result<TaskOutput> {
+        info!("Processing checkout command: {}", text);
+
+        let mut input = InputCheck::default();
+        let branch_or_commit = shell::strip_control_characters(text.trim(), &mut input);
+        if branch_or_commit.is_empty() {
+            return Ok(TaskOutput::new(UsageError::new("checkout", "Branch or commit required", "checkout <branch_or_commit>"), Some(0.9)));
+        }
+        if let Err(reason) = shell::validate_git_ref(&branch_or_commit) {
+            input.reject(reason);
+        }
+
+        let git = |args: &[&str]| ShellCommand::new("git").args(args.iter().copied()).arg(&branch_or_commit);
+        let mut repository = None;
+        let commands = match &self.repository {
+            Some(repo) if !input.is_rejected() => {
+                let mut info = RepositoryInfo {
+                    path: repo.path().display().to_string(),
+                    ..RepositoryInfo::default()
+                };
+                let commands = match repo.resolve(&branch_or_commit).await {
+                    Ok(target) => {
+                        let commands = match (target.kind, &target.remote) {
+                            (RefKind::Branch, _) => vec![git(&["switch"]), git(&["checkout"])],
+                            (RefKind::RemoteBranch, Some(remote)) => vec![
+                                git(&["switch"]),
+                                ShellCommand::new("git").args(["checkout", "--track"]).arg(remote),
+                            ],
+                            (RefKind::RemoteBranch, None) => vec![git(&["checkout", "--track"])],
+                            (RefKind::Tag | RefKind::Commit, _) => vec![git(&["switch", "--detach"]), git(&["checkout"])],
+                            (RefKind::Unknown, _) => vec![git(&["switch", "-c"]), git(&["checkout", "-b"])],
+                        };
+                        info.refs.push(target);
+                        commands
+                    }
+                    Err(e) => {
+                        info.error = Some(format!("{:#}", e));
+                        vec![git(&["checkout"]), git(&["switch", "-c"])]
+                    }
+                };
+                repository = Some(info);
+                commands
+            }
+            _ => vec![
+                git(&["checkout"]),
+                git(&["checkout", "-b"]),
+                git(&["switch"]),
+                git(&["switch", "-c"]),
+            ],
+        };
+        let mut result = CommandSuggestion::new("checkout", CommandSubject::Target(branch_or_commit), commands, input);
+        result.repository = repository;
+
+        Ok(TaskOutput::new(result, Some(0.9)))
+    }
+}
+
+/// Suggests commands to compare files or commits
+#[derive(Debug, Clone, Default)]
+pub struct DiffTask {
+    repository: Option<Arc<GitRepository>>,
+}
+
+impl DiffTask {
+    /// Also report diff stats from `repository`
+    pub fn with_repository(repository: Arc<GitRepository>) -> Self {
+        Self {
+            repository: Some(repository),
+        }
+    }
+}
+
+#[async_trait]
+impl Task for DiffTask {
+    fn name(&self) -> &str {
+        "diff"
+    }
+
+    fn description(&self) -> &str {
+        "Suggest commands to compare files, commits or the working tree"
+    }
+
+    fn category(&self) -> TaskCategory {
+        TaskCategory::System
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        text_input_schema("Files or commits to compare; may be empty for the working tree")
+    }
+
+    async fn run(&self, text: &str, _context: &TaskContext<'_>) -> Result<TaskOutput> {
+        info!("Processing diff command: {}", text);
+
+        let mut input = InputCheck::default();
+        let files_or_commits = shell::strip_control_characters(text.trim(), &mut input);
+        let parts: Vec<&str> = files_or_commits.split_whitespace().collect();
+        if let Some(option) = parts.iter().find(|part| part.starts_with('-')) {
+            input.reject(format!("'{}' looks like an option, not a file or commit", option));
+        }
+
+        let git = |args: &[&str]| ShellCommand::new("git").args(args.iter().copied());
+        let commands = match parts.as_slice() {
+            [] => vec![
+                git(&["diff"]),
+                git(&["diff", "--staged"]),
+                git(&["diff", "HEAD~1"]),
+                git(&["status"]),
+            ],
+            // Likely a single file or commit
+            [target] => vec![
+                git(&["diff", target]),
+                git(&["diff", "HEAD", target]),
+                git(&["show", target]),
+            ],
+            // Likely two files or commits
+            [from, to] => vec![
+                ShellCommand::new("diff").args([*from, *to]),
+                git(&["diff", from, to]),
+                ShellCommand::new("code").args(["--diff", from, to]),
+            ],
+            // Two commits, then the paths to limit the diff to
+            [from, to, paths @ ..] => vec![git(&["diff", from, to, "--"]).args(paths.iter().copied())],
+        };
+
+        let repository = match &self.repository {
+            Some(repo) if !input.is_rejected() => {
+                let (commits, paths) = repo.classify_diff_targets(&parts).await;
+                let mut info = RepositoryInfo {
+                    path: repo.path().display().to_string(),
+                    ..RepositoryInfo::default()
+                };
+                match repo.diff_stats(&commits, &paths).await {
+                    Ok(stats) => info.diff = Some(stats),
+                    Err(e) => info.error = Some(format!("{:#}", e)),
+                }
+                Some(info)
+            }
+            _ => None,
+        };
+
+        let mut result = CommandSuggestion::new("diff", CommandSubject::Target(files_or_commits.clone()), commands, input);
+        result.repository = repository;
+
+        Ok(TaskOutput::new(result, Some(0.9)))
+    }
+}
+
+/// Builds a Google search URL and commands to open it
+pub struct GoogleSearchTask;
+
+#[async_trait]
+impl Task for GoogleSearchTask {
+    fn name(&self) -> &str {
+        "google_search"
+    }
+
+    fn description(&self) -> &str {
+        "Build a Google search URL and commands to open it"
+    }
+
+    fn category(&self) -> TaskCategory {
+        TaskCategory::System
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        text_input_schema("Search query")
+    }
+
+    async fn run(&self, text: &str, context: &TaskContext<'_>) -> Result<TaskOutput> {
+        info!("Processing Google search command: {}", text);
+
+        let mut input = InputCheck::default();
+        let query = shell::strip_control_characters(text.trim(), &mut input);
+        if query.is_empty() {
+            return Ok(TaskOutput::new(UsageError::new("google_search", "Search query required", "google_search <search_query>"), Some(0.9)));
+        }
+
+        // Percent-encoded, so the URL has no quotes or spaces
+        let google_url = reqwest::Url::parse_with_params("https://www.google.com/search", [("q", &query)])?.to_string();
+
+        let platform = context.platform();
+        let commands = platform.url_openers(&google_url);
+        let result = SearchSuggestion::new(query, google_url, commands, input).for_platform(platform);
+
+        Ok(TaskOutput::new(result, Some(0.9)))
+    }
+}
+
+/// Sends a question to the configured LLM provider
+#[derive(Debug, Clone, Default)]
+pub struct AskAiTask {
+    provider: Option<Arc<dyn LlmProvider>>,
+    /// Sent before questions whose request and session don't set their own
+    system_prompt: Option<String>,
+}
+
+impl AskAiTask {
+    /// Ask `provider` instead of reading `AZURE_OPENAI_*` on each request
+    pub fn with_provider(provider: Arc<dyn LlmProvider>) -> Self {
+        Self {
+            provider: Some(provider),
+            system_prompt: None,
+        }
+    }
+
+    pub fn with_system_prompt(mut self, prompt: &str) -> Self {
+        self.system_prompt = Some(prompt.to_string());
+        self
+    }
+
+    /// The system prompt, the session's earlier turns that fit its token budget, then `question`
+    fn messages(&self, question: &str, context: &TaskContext<'_>) -> Vec<ChatMessage> {
+        let default_prompt = self.system_prompt.as_deref();
+        match context.session() {
+            Some(session) => session.messages(question, context.system_prompt(), default_prompt),
+            None => context
+                .system_prompt()
+                .or(default_prompt)
+                .map(ChatMessage::system)
+                .into_iter()
+                .chain([ChatMessage::user(question)])
+                .collect(),
+        }
+    }
+
+    fn provider(&self) -> Arc<dyn LlmProvider> {
+        self.provider.clone().unwrap_or_else(|| Arc::new(AzureOpenAiConfig::from_env()))
+    }
+
+    fn setup_required(question: &str, messages: &[ChatMessage], provider: &dyn LlmProvider) -> AskAiResult {
+        let auth_header = provider
+            .auth_header()
+            .map(|(name, value)| format!(" -H '{}: {}'", name, value.replace(llm::PLACEHOLDER_API_KEY, "YOUR_API_KEY")))
+            .unwrap_or_default();
+
+        let mut result = AskAiResult::new(question, "setup_required");
+        result.message = Some(provider.setup_instructions());
+        result.curl_example = Some(format!(
+            "curl -X POST '{}' -H 'Content-Type: application/json'{} -d '{}'",
+            provider.chat_completions_url(),
+            auth_header,
+            llm::request_body(provider.model(), messages, false)
+        ));
+        result
+    }
+
+    fn completed(question: &str, answer: String, source: &str) -> TaskOutput {
+        let mut result = AskAiResult::new(question, "completed");
+        result.answer = Some(answer);
+        result.source = Some(source.to_string());
+        TaskOutput::new(result, Some(0.95))
+    }
+
+}
+
+#[async_trait]
+impl Task for AskAiTask {
+    fn name(&self) -> &str {
+        "ask_ai"
+    }
+
+    fn description(&self) -> &str {
+        "Ask a question to the configured LLM provider"
+    }
+
+    fn category(&self) -> TaskCategory {
+        TaskCategory::Llm
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        text_input_schema("Question to ask")
+    }
+
+    async fn run(&self, text: &str, context: &TaskContext<'_>) -> Result<TaskOutput> {
+        info!("Processing Ask AI command: {}", text);
+
+        let question = text.trim();
+        if question.is_empty() {
+            return Ok(TaskOutput::new(UsageError::new("ask_ai", "Question required", "ask_ai <your_question>"), Some(0.9)));
+        }
+
+        // If using default values, provide setup instructions
+        let provider = self.provider();
+        let messages = self.messages(question, context);
+        if !provider.is_configured() {
+            return Ok(TaskOutput::new(Self::setup_required(question, &messages, provider.as_ref()), Some(0.8)));
+        }
+
+        // Failures are reported as upstream errors rather than as a result
+        let response = provider.chat_completion(&messages).await?;
+        if let Some(session) = context.session() {
+            session.record(context.system_prompt(), question, &response);
+        }
+        Ok(Self::completed(question, response, provider.name()))
+    }
+
+    async fn run_stream(&self, text: &str, context: &TaskContext<'_>) -> Result<Option<TaskStream>> {
+        let question = text.trim().to_string();
+        let provider = self.provider();
+        if question.is_empty() || !provider.is_configured() {
+            return Ok(None);
+        }
+
+        info!("Streaming Ask AI command: {}", question);
+
+        let deltas = provider.stream_chat_completion(&self.messages(&question, context)).await?;
+        let source = provider.name().to_string();
+        let session = context.session().cloned();
+        let system_prompt = context.system_prompt().map(str::to_string);
+
+        // Forward each delta while collecting the full answer for the final event
+        let events = stream::unfold(
+            (deltas, String::new(), false),
+            move |(mut deltas, mut answer, finished)| {
+                let question = question.clone();
+                let source = source.clone();
+                let session = session.clone();
+                let system_prompt = system_prompt.clone();
+                async move {
+                    if finished {
+                        return None;
+                    }
+                    match deltas.next().await {
+                        Some(Ok(delta)) => {
+                            answer.push_str(&delta);
+                            Some((Ok(TaskStreamEvent::Delta(delta)), (deltas, answer, false)))
+                        }
+                        Some(Err(e)) => Some((Err(e), (deltas, answer, true))),
+                        None => {
+                            if let Some(session) = &session {
+                                session.record(system_prompt.as_deref(), &question, &answer);
+                            }
+                            let output = Self::completed(&question, answer, &source);
+                            Some((Ok(TaskStreamEvent::Done(output)), (deltas, String::new(), true)))
+                        }
+                    }
+                }
+            },
+        );
+
+        Ok(Some(events.boxed()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Platform;
+    use crate::tasks::ProcessOptions;
+
+    /// Run a task for macOS in a context backed by the built-in registry and serialize its result
+    async fn run_task(task: &dyn Task, text: &str) -> (String, Option<f32>) {
+        let registry = TaskRegistry::with_builtin_tasks();
+        let options = ProcessOptions {
+            platform: Some(Platform::MacOs),
+            ..ProcessOptions::default()
+        };
+        let context = TaskContext::with_options(&registry, &options);
+        let output = task.run(text, &context).await.unwrap();
+        (serde_json::to_string(&output.result).unwrap(), output.confidence)
+    }
+
+    /// Run a task for `platform` and return its rendered suggestions
+    async fn suggestions_for(task: &dyn Task, text: &str, platform: Platform) -> Vec<String> {
+        let registry = TaskRegistry::with_builtin_tasks();
+        let options = ProcessOptions {
+            platform: Some(platform),
+            ..ProcessOptions::default()
+        };
+        let output = task.run(text, &TaskContext::with_options(&registry, &options)).await.unwrap();
+        let result = output.result.to_value();
+        assert_eq!(result["platform"], platform.as_str());
+        serde_json::from_value(result["suggested_commands"].clone()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_install_command() {
+        let (result, confidence) = run_task(&InstallTask::default(), "rust").await;
+
+        assert!(result.contains("install"));
+        assert!(result.contains("rust"));
+        assert!(result.contains("brew install rust"));
+        assert!(confidence.is_some());
+        assert_eq!(confidence.unwrap(), 0.9);
+    }
+
+    #[tokio::test]
+    async fn test_install_resolves_catalog_aliases() {
+        let registry = TaskRegistry::with_builtin_tasks();
+        let options = ProcessOptions {
+            platform: Some(Platform::Debian),
+            ..ProcessOptions::default()
+        };
+        let context = TaskContext::with_options(&registry, &options);
+        let result = InstallTask::default().run("rg", &context).await.unwrap().result.to_value();
+
+        assert_eq!(result["package"], "rg");
+        assert_eq!(result["resolution"]["known"], true);
+        assert_eq!(result["resolution"]["name"], "ripgrep");
+        assert_eq!(result["resolution"]["managers"], serde_json::json!(["apt", "cargo"]));
+        assert_eq!(result["suggested_commands"][0], "apt install ripgrep");
+
+        let result = InstallTask::default().run("rust; rm -rf /", &context).await.unwrap().result.to_value();
+        assert!(result.get("resolution").is_none());
+        assert_eq!(result["suggested_commands"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_find_file_command() {
+        let (result, confidence) = run_task(&FindFileTask::default(), "main.rs").await;
+
+        assert!(result.contains("find_file"));
+        assert!(result.contains("main.rs"));
+        assert!(result.contains("find . -name"));
+        assert!(confidence.is_some());
+        assert_eq!(confidence.unwrap(), 0.9);
+    }
+
+    #[tokio::test]
+    async fn test_find_content_command() {
+        let (result, confidence) = run_task(&FindContentTask::default(), "TODO").await;
+
+        assert!(result.contains("find_content"));
+        assert!(result.contains("TODO"));
+        assert!(result.contains("grep -r"));
+        assert!(confidence.is_some());
+        assert_eq!(confidence.unwrap(), 0.9);
+    }
+
+    #[tokio::test]
+    async fn test_repository_aware_checkout_and_diff() {
+        let path = crate::git::tests::repository();
+        let repository = Arc::new(
+            GitRepository::new(&crate::config::GitConfig {
+                enabled: true,
+                repository: path.clone(),
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+        let registry = TaskRegistry::with_builtin_tasks();
+        let context = TaskContext::new(&registry);
+        let checkout = CheckoutTask::with_repository(repository.clone());
+
+        let result = checkout.run("v1.0", &context).await.unwrap().result.to_value();
+        assert_eq!(result["repository"]["refs"][0]["kind"], "tag");
+        assert_eq!(result["suggested_commands"][0], "git switch --detach v1.0");
+
+        let result = checkout.run("release", &context).await.unwrap().result.to_value();
+        assert_eq!(result["suggested_commands"][1], "git checkout --track origin/release");
+
+        let result = checkout.run("feature/logn", &context).await.unwrap().result.to_value();
+        assert_eq!(result["repository"]["refs"][0]["kind"], "unknown");
+        assert_eq!(result["repository"]["refs"][0]["close_matches"], serde_json::json!(["feature/login"]));
+        assert_eq!(result["suggested_commands"][0], "git switch -c feature/logn");
+
+        let result = DiffTask::with_repository(repository).run("v1.0 HEAD README.md", &context).await.unwrap().result.to_value();
+        std::fs::remove_dir_all(&path).unwrap();
+        let diff = &result["repository"]["diff"];
+        assert_eq!(diff["files_changed"], 1);
+        assert_eq!(diff["insertions"], 2);
+        assert_eq!(diff["deletions"], 1);
+        assert_eq!(diff["files"][0]["path"], "README.md");
+    }
+
+    #[tokio::test]
+    async fn test_platform_specific_suggestions() {
+        let install = suggestions_for(&InstallTask::default(), "ripgrep", Platform::Debian).await;
+        assert_eq!(install[0], "apt install ripgrep");
+        assert!(!install.iter().any(|c| c.starts_with("brew")));
+
+        // Without a known system package manager the catalog's next ecosystem comes first
+        let install = suggestions_for(&InstallTask::default(), "ripgrep", Platform::Linux).await;
+        assert_eq!(install, vec!["cargo install ripgrep"]);
+
+        // Unknown packages are never guessed from a public registry
+        let install = suggestions_for(&InstallTask::default(), "left-pad", Platform::Linux).await;
+        assert!(install.is_empty());
+
+        let open = suggestions_for(&OpenAppTask, "Firefox", Platform::Fedora).await;
+        assert_eq!(open, vec!["gtk-launch firefox", "firefox"]);
+
+        let open = suggestions_for(&OpenFileTask, "notes.txt", Platform::Windows).await;
+        assert_eq!(open[0], "explorer notes.txt");
+
+        // Windows suggestions are quoted for cmd.exe, where single quotes mean nothing
+        let open = suggestions_for(&OpenFileTask, "a&calc.txt", Platform::Windows).await;
+        assert_eq!(open[0], r#"explorer "a&calc.txt""#);
+        let open = suggestions_for(&OpenAppTask, "Visual Studio Code", Platform::Windows).await;
+        assert_eq!(open, vec![r#"cmd /C start "" "Visual Studio Code""#]);
+
+        let search = suggestions_for(&GoogleSearchTask, "rust & cargo", Platform::Windows).await;
+        assert_eq!(search[0], r#"cmd /C start "" "https://www.google.com/search?q=rust+%26+cargo""#);
+        let search = suggestions_for(&GoogleSearchTask, "rust", Platform::Fedora).await;
+        assert_eq!(search[0], "xdg-open 'https://www.google.com/search?q=rust'");
+        assert!(!search.iter().any(|c| c.starts_with("open") || c.starts_with("osascript")));
+    }
+
+    #[tokio::test]
+    async fn test_quotes_in_input_stay_inside_one_argument() {
+        let registry = TaskRegistry::with_builtin_tasks();
+        let context = TaskContext::new(&registry);
+        let output = FindContentTask::default().run("it's'; rm -rf ~; echo '", &context).await.unwrap();
+        let result = output.result.to_value();
+
+        assert_eq!(result["commands"][0]["args"][2], "it's'; rm -rf ~; echo '");
+        assert_eq!(
+            result["suggested_commands"][0],
+            r#"grep -r -e 'it'\''s'\''; rm -rf ~; echo '\''' ."#
+        );
+        assert_eq!(result["input"]["status"], "accepted");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_input_is_rejected_or_sanitized() {
+        let registry = TaskRegistry::with_builtin_tasks();
+        let context = TaskContext::new(&registry);
+
+        let output = CheckoutTask::default().run("main; rm -rf /", &context).await.unwrap();
+        let result = output.result.to_value();
+        assert_eq!(result["input"]["status"], "rejected");
+        assert!(result["suggested_commands"].as_array().unwrap().is_empty());
+
+        let output = OpenFileTask.run("-rf\n", &context).await.unwrap();
+        let result = output.result.to_value();
+        assert_eq!(result["input"]["status"], "sanitized");
+        assert_eq!(result["suggested_commands"][3], "cat ./-rf");
+        assert_eq!(result["input"]["issues"].as_array().unwrap().len(), 1);
+
+        let output = GetFileFromTask::default().run("http://", &context).await.unwrap();
+        assert_eq!(output.result.to_value()["input"]["status"], "rejected");
+
+        let output = GetFileFromTask::default().run("https://example.com/tool.tar.gz sha256:abc", &context).await.unwrap();
+        let result = output.result.to_value();
+        assert_eq!(result["input"]["status"], "rejected");
+        assert_eq!(result["source"], "https://example.com/tool.tar.gz");
+    }
+
+    #[tokio::test]
+    async fn test_show_tools_command() {
+        let (result, confidence) = run_task(&ShowToolsTask, "development").await;
+
+        assert!(result.contains("show_tools"));
+        assert!(result.contains("git"));
+        assert!(result.contains("cargo"));
+        assert!(confidence.is_some());
+        assert_eq!(confidence.unwrap(), 0.9);
+    }
+
+    #[tokio::test]
+    async fn test_checkout_command() {
+        let (result, confidence) = run_task(&CheckoutTask::default(), "main").await;
+
+        assert!(result.contains("checkout"));
+        assert!(result.contains("main"));
+        assert!(result.contains("git checkout"));
+        assert!(confidence.is_some());
+        assert_eq!(confidence.unwrap(), 0.9);
+    }
+
+    #[tokio::test]
+    async fn test_diff_command() {
+        let (result, confidence) = run_task(&DiffTask::default(), "").await;
+
+        assert!(result.contains("diff"));
+        assert!(result.contains("git diff"));
+        assert!(confidence.is_some());
+        assert_eq!(confidence.unwrap(), 0.9);
+
+        // Targets after two commits are paths, not dropped
+        let (result, _) = run_task(&DiffTask::default(), "main feature src/lib.rs README.md").await;
+        let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(result["suggested_commands"], serde_json::json!(["git diff main feature -- src/lib.rs README.md"]));
+    }
+
+    #[tokio::test]
+    async fn test_google_search_command() {
+        let (result, confidence) = run_task(&GoogleSearchTask, "rust programming language").await;
+
+        assert!(result.contains("google_search"));
+        assert!(result.contains("rust programming language"));
+        assert!(result.contains("https://www.google.com/search?q=rust+programming+language"));
+        assert!(result.contains("open"));
+        assert!(confidence.is_some());
+        assert_eq!(confidence.unwrap(), 0.9);
+    }
+
+    #[tokio::test]
+    async fn test_ask_ai_command() {
+        let (result, confidence) = run_task(&AskAiTask::default(), "What is the meaning of life?").await;
+
+        assert!(result.contains("ask_ai"));
+        assert!(result.contains("What is the meaning of life?"));
+        // Should contain setup instructions since Azure keys aren't configured
+        assert!(result.contains("setup_required") || result.contains("AZURE_OPENAI"));
+        assert!(confidence.is_some());
+        assert!(confidence.unwrap() >= 0.7);
+    }
+}
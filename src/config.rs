@@ -0,0 +1,1406 @@
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Parser, ValueEnum};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::catalog::PackageCatalog;
+use crate::commands::{
+    AskAiTask, CheckoutTask, DiffTask, FindContentTask, FindFileTask, GetFileFromTask, InstallTask,
+};
+use crate::download::Downloader;
+use crate::git::GitRepository;
+use crate::llm::{
+    AzureOpenAiConfig, LlmClient, LlmClientOptions, LlmProvider, LocalLlmConfig, OpenAiConfig,
+    DEFAULT_REQUEST_TIMEOUT, LOCAL_LLM_ENDPOINT, OPENAI_ENDPOINT, PLACEHOLDER_API_KEY,
+};
+use crate::search::WorkspaceSearch;
+use crate::tasks::TaskRegistry;
+use crate::udp_broadcast::{AnnouncementMode, DEFAULT_DISCOVERY_PORT};
+
+/// Config file read when `--config` isn't given, if it exists
+pub const DEFAULT_CONFIG_FILE: &str = "rustlm.toml";
+
+/// Server configuration, loaded from a TOML file and overridden by environment variables and flags
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub discovery: DiscoveryConfig,
+    pub llm: LlmConfig,
+    pub tasks: TasksConfig,
+    pub auth: AuthConfig,
+    pub rate_limit: RateLimitConfig,
+    pub callbacks: CallbacksConfig,
+    pub actions: ActionsConfig,
+    pub sessions: SessionsConfig,
+}
+
+/// HTTP listener settings
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: SocketAddr,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([0, 0, 0, 0], 3000)),
+        }
+    }
+}
+
+/// How the server announces itself over UDP
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum DiscoveryMode {
+    /// Announce every `interval_secs` seconds
+    Periodic,
+    /// Only respond to discovery requests
+    OnRequest,
+    /// Announce `count` times, every `interval_secs` seconds
+    Limited,
+}
+
+impl FromStr for DiscoveryMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        <Self as ValueEnum>::from_str(s, true).map_err(|_| {
+            anyhow!("unknown discovery mode '{}', expected periodic, on_request or limited", s)
+        })
+    }
+}
+
+/// UDP discovery settings
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiscoveryConfig {
+    pub enabled: bool,
+    pub service_name: String,
+    pub shared_key: String,
+    pub mode: DiscoveryMode,
+    pub interval_secs: u64,
+    pub count: u32,
+    pub port: u16,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            service_name: "rustlm-service".to_string(),
+            shared_key: "SECRETKEY123".to_string(),
+            mode: DiscoveryMode::OnRequest,
+            interval_secs: 30,
+            count: 5,
+            port: DEFAULT_DISCOVERY_PORT,
+        }
+    }
+}
+
+impl DiscoveryConfig {
+    pub fn announcement_mode(&self) -> AnnouncementMode {
+        match self.mode {
+            DiscoveryMode::Periodic => AnnouncementMode::Periodic(self.interval_secs),
+            DiscoveryMode::OnRequest => AnnouncementMode::OnRequest,
+            DiscoveryMode::Limited => AnnouncementMode::Limited(self.interval_secs, self.count),
+        }
+    }
+}
+
+/// LLM provider used by `ask_ai`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum LlmProviderKind {
+    AzureOpenai,
+    /// The OpenAI chat API, or a hosted service implementing it
+    Openai,
+    /// A local server implementing the OpenAI chat API, such as llama.cpp or Ollama
+    Local,
+}
+
+impl FromStr for LlmProviderKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        <Self as ValueEnum>::from_str(s, true).map_err(|_| {
+            anyhow!("unknown LLM provider '{}', expected azure_openai, openai or local", s)
+        })
+    }
+}
+
+/// LLM provider settings
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LlmConfig {
+    pub provider: LlmProviderKind,
+    /// Base URL of the provider's API; the provider's usual one when unset
+    pub endpoint: Option<String>,
+    /// Not needed by the `local` provider
+    pub api_key: String,
+    /// Azure OpenAI deployment
+    pub deployment: String,
+    /// Model for the `openai` and `local` providers
+    pub model: Option<String>,
+    /// Time to wait for an answer, or when streaming for each part of it
+    pub timeout_secs: u64,
+    pub connect_timeout_secs: u64,
+    /// Retries of a request that got a 429 or 5xx response, timed out or couldn't connect
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled for each further one; the provider's `Retry-After` wins
+    pub retry_backoff_ms: u64,
+    /// Longest wait before a retry; when asked to wait longer the request fails instead
+    pub max_retry_delay_secs: u64,
+    /// Consecutive failed requests after which the provider isn't called for `circuit_reset_secs`
+    pub circuit_failure_threshold: u32,
+    pub circuit_reset_secs: u64,
+    /// Sent before every `ask_ai` question unless the request or session sets its own
+    pub system_prompt: Option<String>,
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        let azure = AzureOpenAiConfig::placeholder();
+        Self {
+            provider: LlmProviderKind::AzureOpenai,
+            endpoint: None,
+            api_key: azure.api_key,
+            deployment: azure.deployment,
+            model: None,
+            timeout_secs: DEFAULT_REQUEST_TIMEOUT.as_secs(),
+            connect_timeout_secs: 10,
+            max_retries: 2,
+            retry_backoff_ms: 500,
+            max_retry_delay_secs: 30,
+            circuit_failure_threshold: 5,
+            circuit_reset_secs: 30,
+            system_prompt: None,
+        }
+    }
+}
+
+impl LlmConfig {
+    /// The configured endpoint, or the provider's usual one
+    pub fn endpoint(&self) -> String {
+        match (&self.endpoint, self.provider) {
+            (Some(endpoint), _) => endpoint.clone(),
+            (None, LlmProviderKind::AzureOpenai) => AzureOpenAiConfig::placeholder().endpoint,
+            (None, LlmProviderKind::Openai) => OPENAI_ENDPOINT.to_string(),
+            (None, LlmProviderKind::Local) => LOCAL_LLM_ENDPOINT.to_string(),
+        }
+    }
+
+    pub fn azure_config(&self) -> AzureOpenAiConfig {
+        AzureOpenAiConfig::new(&self.endpoint(), &self.api_key, &self.deployment)
+            .with_timeout(Duration::from_secs(self.timeout_secs))
+    }
+
+    pub fn client_options(&self) -> LlmClientOptions {
+        LlmClientOptions {
+            connect_timeout: Duration::from_secs(self.connect_timeout_secs),
+            max_retries: self.max_retries,
+            retry_backoff: Duration::from_millis(self.retry_backoff_ms),
+            max_retry_delay: Duration::from_secs(self.max_retry_delay_secs),
+            circuit_failure_threshold: self.circuit_failure_threshold,
+            circuit_reset: Duration::from_secs(self.circuit_reset_secs),
+        }
+    }
+
+    /// The provider `ask_ai` sends questions to, with its own client and circuit breaker
+    pub fn provider(&self) -> Arc<dyn LlmProvider> {
+        let timeout = Duration::from_secs(self.timeout_secs);
+        let client = Arc::new(LlmClient::new(self.client_options()));
+        match self.provider {
+            LlmProviderKind::AzureOpenai => Arc::new(self.azure_config().with_client(client)),
+            LlmProviderKind::Openai => Arc::new(
+                OpenAiConfig::new(&self.endpoint(), &self.api_key, self.model.as_deref().unwrap_or_default())
+                    .with_timeout(timeout)
+                    .with_client(client),
+            ),
+            LlmProviderKind::Local => {
+                let mut local = LocalLlmConfig::new(&self.endpoint()).with_timeout(timeout).with_client(client);
+                if let Some(model) = &self.model {
+                    local = local.with_model(model);
+                }
+                // The placeholder means no key was set
+                if !self.api_key.is_empty() && self.api_key != PLACEHOLDER_API_KEY {
+                    local = local.with_api_key(&self.api_key);
+                }
+                Arc::new(local)
+            }
+        }
+    }
+}
+
+/// Which registered tasks are served
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TasksConfig {
+    /// Only serve these tasks; all tasks are served when unset
+    pub enabled: Option<Vec<String>>,
+    /// Never serve these tasks, even if listed in `enabled`
+    pub disabled: Vec<String>,
+    /// Package catalog for `install`, extending the bundled one
+    pub package_catalog: Option<PathBuf>,
+    pub search: WorkspaceSearchConfig,
+    pub git: GitConfig,
+    pub download: DownloadConfig,
+}
+
+/// Server-side downloads for `get_file_from`; disabled by default
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DownloadConfig {
+    pub enabled: bool,
+    /// Directory files are downloaded into; nothing is written outside it
+    pub directory: PathBuf,
+    /// Largest file that is downloaded, in bytes
+    pub max_size: u64,
+    /// Content types that may be downloaded, e.g. `application/zip` or `image/*`; any when empty
+    pub allowed_content_types: Vec<String>,
+    /// Time a download may take
+    pub timeout_secs: u64,
+    /// Downloads whose status is kept once they finish
+    pub max_finished: usize,
+    /// Hosts files may be downloaded from, e.g. `github.com` or `*.example.com`; any public host
+    /// when empty. Hosts that resolve to loopback, private or link-local addresses are always refused.
+    pub allowed_hosts: Vec<String>,
+    /// Downloads that run at once; more wait in the queue
+    pub max_concurrent: usize,
+    /// Largest total size of the files in the download directory, partial downloads included
+    pub max_total_size: u64,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: PathBuf::from("downloads"),
+            max_size: 100 * 1024 * 1024,
+            allowed_content_types: Vec::new(),
+            timeout_secs: 300,
+            max_finished: 100,
+            allowed_hosts: Vec::new(),
+            max_concurrent: 4,
+            max_total_size: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// Repository-aware `checkout` and `diff`; disabled by default
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GitConfig {
+    pub enabled: bool,
+    /// Local repository that targets are looked up in
+    pub repository: PathBuf,
+    /// Time a `git` command may run
+    pub timeout_secs: u64,
+    /// Similar branch and tag names listed for an unknown target
+    pub max_close_matches: usize,
+}
+
+impl Default for GitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            repository: PathBuf::from("."),
+            timeout_secs: 10,
+            max_close_matches: 5,
+        }
+    }
+}
+
+/// Server-side search for `find_file` and `find_content`; disabled by default
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkspaceSearchConfig {
+    pub enabled: bool,
+    /// Directories that are searched; nothing outside them is read
+    pub roots: Vec<PathBuf>,
+    /// Directory levels below a root that are searched
+    pub max_depth: usize,
+    /// Files larger than this many bytes aren't searched for content
+    pub max_file_size: u64,
+    /// Matches returned before the search stops
+    pub max_results: usize,
+    /// Lines shown before and after a matching line
+    pub context_lines: usize,
+    /// Skip files matched by `.gitignore` files
+    pub respect_gitignore: bool,
+}
+
+impl Default for WorkspaceSearchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            roots: Vec::new(),
+            max_depth: 16,
+            max_file_size: 1024 * 1024,
+            max_results: 100,
+            context_lines: 2,
+            respect_gitignore: true,
+        }
+    }
+}
+
+impl TasksConfig {
+    pub fn is_enabled(&self, task: &str) -> bool {
+        let listed = |names: &[String]| names.iter().any(|n| n.eq_ignore_ascii_case(task));
+        self.enabled.as_deref().is_none_or(listed) && !listed(&self.disabled)
+    }
+
+    /// Remove every task that isn't enabled from the registry
+    pub fn apply(&self, registry: &mut TaskRegistry) {
+        for name in registry.names() {
+            if !self.is_enabled(&name) {
+                registry.unregister(&name);
+            }
+        }
+    }
+}
+
+/// HTTP API authentication; when enabled every request except `/health` needs credentials
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub enabled: bool,
+    /// Secret used to sign and verify bearer tokens; tokens are rejected when unset
+    pub token_secret: Option<String>,
+    pub api_keys: Vec<ApiKeyConfig>,
+}
+
+/// A static API key, sent as `X-API-Key` or `Authorization: Bearer`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeyConfig {
+    /// Name used in logs to identify the caller
+    pub name: String,
+    pub key: String,
+    /// Task names or categories (`nlp`, `system`, `llm`) the key may run; all tasks when unset
+    #[serde(default)]
+    pub allowed_tasks: Option<Vec<String>>,
+    /// Whether the key may use the `/admin` endpoints
+    #[serde(default)]
+    pub admin: bool,
+}
+
+/// Per-client rate limits, keyed by API key, token subject or client IP
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// Limits for tasks that run locally
+    pub local: ClassLimitConfig,
+    /// Limits for tasks that call the LLM provider, such as `ask_ai`
+    pub llm: ClassLimitConfig,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            local: ClassLimitConfig {
+                requests_per_minute: 600,
+                burst: 60,
+                daily_quota: None,
+            },
+            llm: ClassLimitConfig {
+                requests_per_minute: 20,
+                burst: 5,
+                daily_quota: Some(500),
+            },
+        }
+    }
+}
+
+/// Token bucket and daily quota for one class of tasks
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClassLimitConfig {
+    /// Rate the bucket refills at
+    pub requests_per_minute: u32,
+    /// Requests that can be made at once with a full bucket
+    pub burst: u32,
+    /// Requests allowed per UTC day; unlimited when unset
+    #[serde(default)]
+    pub daily_quota: Option<u64>,
+}
+
+/// Callbacks run after each task, in addition to the built-in ones
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CallbacksConfig {
+    /// Callbacks running at once across all requests
+    pub max_concurrency: usize,
+    /// Time a callback may take before it's reported as failed, unless the callback sets its own
+    pub timeout_secs: u64,
+    pub executor: ExecutorConfig,
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+impl Default for CallbacksConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrency: 8,
+            timeout_secs: 30,
+            executor: ExecutorConfig::default(),
+            webhooks: Vec::new(),
+        }
+    }
+}
+
+/// Sandbox that runs allow-listed suggested commands; disabled by default
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExecutorConfig {
+    pub enabled: bool,
+    /// Directory commands run in; path arguments must stay inside it
+    pub working_dir: PathBuf,
+    /// Commands that may run, as a program optionally followed by a subcommand, e.g. `git diff`
+    pub allowed_commands: Vec<String>,
+    /// Tasks whose suggested commands are run
+    pub commands: Vec<String>,
+    /// Time a command may run before it's killed
+    pub timeout_secs: u64,
+    /// Bytes of stdout and of stderr kept; the rest is discarded
+    pub max_output_bytes: usize,
+}
+
+impl Default for ExecutorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            working_dir: PathBuf::from("."),
+            allowed_commands: vec!["find".to_string(), "rg".to_string(), "git diff".to_string()],
+            commands: vec!["find_file".to_string(), "find_content".to_string(), "diff".to_string()],
+            timeout_secs: 10,
+            max_output_bytes: 64 * 1024,
+        }
+    }
+}
+
+/// Single-use tokens for suggested commands, confirmed on `/actions/{token}/confirm`; disabled by default
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActionsConfig {
+    pub enabled: bool,
+    /// Time a token can be confirmed in
+    pub ttl_secs: u64,
+    /// Run confirmed commands with the sandbox set up in `callbacks.executor`; otherwise they're
+    /// only handed to the `confirm_action` callbacks
+    pub execute: bool,
+    /// Confirmation attempts kept for `/admin/actions`
+    pub max_audit_entries: usize,
+}
+
+impl Default for ActionsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: 300,
+            execute: false,
+            max_audit_entries: 1000,
+        }
+    }
+}
+
+/// Conversation history for `ask_ai` requests that give a `session_id`, kept in memory
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionsConfig {
+    pub enabled: bool,
+    /// Estimated tokens of system prompt, history and question sent with each question; the
+    /// oldest messages are dropped to stay within it
+    pub max_history_tokens: usize,
+    /// Time after its last question that a session is forgotten
+    pub idle_timeout_secs: u64,
+    /// Sessions kept at once; the least recently used is dropped to start a new one
+    pub max_sessions: usize,
+}
+
+impl Default for SessionsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_history_tokens: 4000,
+            idle_timeout_secs: 3600,
+            max_sessions: 1000,
+        }
+    }
+}
+
+/// A webhook that task results are posted to
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// Callback name, used in logs, metrics and `/admin/callbacks`
+    pub name: String,
+    pub url: String,
+    /// Commands to post results for; every command when unset
+    pub commands: Option<Vec<String>>,
+    /// Extra headers sent with every delivery
+    pub headers: BTreeMap<String, String>,
+    /// Secret used to sign deliveries with HMAC-SHA256; unsigned when unset
+    pub secret: Option<String>,
+    /// Callbacks with a lower priority run first
+    pub priority: i32,
+    /// Attempts before a delivery is dead-lettered, including the first one
+    pub max_attempts: u32,
+    /// Wait before the first retry; doubled for every further retry
+    pub initial_backoff_ms: u64,
+    /// Timeout of each attempt
+    pub timeout_secs: u64,
+    /// File that failed deliveries are appended to as JSON lines; only logged when unset
+    pub dead_letter_file: Option<PathBuf>,
+    /// Deliver in the background instead of holding the response until the webhook answers
+    pub background: bool,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            url: String::new(),
+            commands: None,
+            headers: BTreeMap::new(),
+            secret: None,
+            priority: 100,
+            max_attempts: 3,
+            initial_backoff_ms: 500,
+            timeout_secs: 10,
+            dead_letter_file: None,
+            background: false,
+        }
+    }
+}
+
+impl WebhookConfig {
+    pub fn new(name: &str, url: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            url: url.to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Command-line flags; each one overrides the config file and environment
+#[derive(Debug, Default, Parser)]
+#[command(name = "rustlm-server", version, about = "REST API server with NLP and command tasks")]
+pub struct Cli {
+    /// Path to the TOML config file [default: ./rustlm.toml if it exists]
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// Address to listen on, e.g. 127.0.0.1:8080
+    #[arg(long)]
+    pub bind: Option<SocketAddr>,
+
+    /// Disable UDP discovery
+    #[arg(long)]
+    pub no_discovery: bool,
+
+    /// UDP discovery mode
+    #[arg(long, value_enum)]
+    pub discovery_mode: Option<DiscoveryMode>,
+
+    /// Seconds between announcements in periodic and limited modes
+    #[arg(long)]
+    pub discovery_interval: Option<u64>,
+
+    /// UDP port used for discovery
+    #[arg(long)]
+    pub discovery_port: Option<u16>,
+
+    /// Service name sent in discovery responses
+    #[arg(long)]
+    pub service_name: Option<String>,
+
+    /// LLM provider used by ask_ai
+    #[arg(long, value_enum)]
+    pub llm_provider: Option<LlmProviderKind>,
+
+    /// LLM endpoint URL
+    #[arg(long)]
+    pub llm_endpoint: Option<String>,
+
+    /// Azure OpenAI deployment name
+    #[arg(long)]
+    pub llm_deployment: Option<String>,
+
+    /// Model name for the openai and local providers
+    #[arg(long)]
+    pub llm_model: Option<String>,
+
+    /// Seconds to wait for the LLM before failing with a timeout
+    #[arg(long)]
+    pub llm_timeout: Option<u64>,
+
+    /// Only serve the given task; may be repeated
+    #[arg(long = "enable-task", value_name = "TASK")]
+    pub enable_tasks: Vec<String>,
+
+    /// Don't serve the given task; may be repeated
+    #[arg(long = "disable-task", value_name = "TASK")]
+    pub disable_tasks: Vec<String>,
+}
+
+impl Cli {
+    fn apply(&self, config: &mut Config) {
+        if let Some(bind) = self.bind {
+            config.server.bind = bind;
+        }
+        if self.no_discovery {
+            config.discovery.enabled = false;
+        }
+        if let Some(mode) = self.discovery_mode {
+            config.discovery.mode = mode;
+        }
+        if let Some(interval) = self.discovery_interval {
+            config.discovery.interval_secs = interval;
+        }
+        if let Some(port) = self.discovery_port {
+            config.discovery.port = port;
+        }
+        if let Some(service_name) = &self.service_name {
+            config.discovery.service_name = service_name.clone();
+        }
+        if let Some(provider) = self.llm_provider {
+            config.llm.provider = provider;
+        }
+        if let Some(endpoint) = &self.llm_endpoint {
+            config.llm.endpoint = Some(endpoint.clone());
+        }
+        if let Some(deployment) = &self.llm_deployment {
+            config.llm.deployment = deployment.clone();
+        }
+        if let Some(model) = &self.llm_model {
+            config.llm.model = Some(model.clone());
+        }
+        if let Some(timeout) = self.llm_timeout {
+            config.llm.timeout_secs = timeout;
+        }
+        if !self.enable_tasks.is_empty() {
+            config.tasks.enabled = Some(self.enable_tasks.clone());
+        }
+        config.tasks.disabled.extend(self.disable_tasks.iter().cloned());
+    }
+}
+
+impl Config {
+    /// Load the config file, then apply environment variables and flags, then validate
+    pub fn load(cli: &Cli) -> Result<Self> {
+        Self::resolve(cli, |name| std::env::var(name).ok())
+    }
+
+    /// Same as `load`, reading environment variables through `env`
+    pub fn resolve(cli: &Cli, env: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?,
+            None => Self::default(),
+        };
+
+        config.apply_env(env)?;
+        cli.apply(&mut config);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        Self::from_toml(&contents).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    /// Override settings from `RUSTLM_*` and `AZURE_OPENAI_*` environment variables
+    fn apply_env(&mut self, env: impl Fn(&str) -> Option<String>) -> Result<()> {
+        fn parse<T: FromStr>(name: &str, value: String) -> Result<T>
+        where
+            T::Err: std::fmt::Display,
+        {
+            value
+                .trim()
+                .parse()
+                .map_err(|e| anyhow!("Invalid value '{}' for {}: {}", value, name, e))
+        }
+        fn list(value: String) -> Vec<String> {
+            value
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect()
+        }
+
+        if let Some(value) = env("RUSTLM_BIND") {
+            self.server.bind = parse("RUSTLM_BIND", value)?;
+        }
+        if let Some(value) = env("RUSTLM_DISCOVERY_ENABLED") {
+            self.discovery.enabled = parse("RUSTLM_DISCOVERY_ENABLED", value)?;
+        }
+        if let Some(value) = env("RUSTLM_DISCOVERY_MODE") {
+            self.discovery.mode = parse("RUSTLM_DISCOVERY_MODE", value)?;
+        }
+        if let Some(value) = env("RUSTLM_DISCOVERY_INTERVAL") {
+            self.discovery.interval_secs = parse("RUSTLM_DISCOVERY_INTERVAL", value)?;
+        }
+        if let Some(value) = env("RUSTLM_DISCOVERY_PORT") {
+            self.discovery.port = parse("RUSTLM_DISCOVERY_PORT", value)?;
+        }
+        if let Some(value) = env("RUSTLM_SERVICE_NAME") {
+            self.discovery.service_name = value;
+        }
+        if let Some(value) = env("RUSTLM_SHARED_KEY") {
+            self.discovery.shared_key = value;
+        }
+        if let Some(value) = env("RUSTLM_LLM_PROVIDER") {
+            self.llm.provider = parse("RUSTLM_LLM_PROVIDER", value)?;
+        }
+        if let Some(value) = env("AZURE_OPENAI_ENDPOINT") {
+            self.llm.endpoint = Some(value);
+        }
+        if let Some(value) = env("RUSTLM_LLM_ENDPOINT") {
+            self.llm.endpoint = Some(value);
+        }
+        if let Some(value) = env("AZURE_OPENAI_API_KEY") {
+            self.llm.api_key = value;
+        }
+        if let Some(value) = env("RUSTLM_LLM_API_KEY") {
+            self.llm.api_key = value;
+        }
+        if let Some(value) = env("AZURE_OPENAI_DEPLOYMENT") {
+            self.llm.deployment = value;
+        }
+        if let Some(value) = env("RUSTLM_LLM_MODEL") {
+            self.llm.model = Some(value);
+        }
+        if let Some(value) = env("RUSTLM_LLM_TIMEOUT_SECS") {
+            self.llm.timeout_secs = parse("RUSTLM_LLM_TIMEOUT_SECS", value)?;
+        }
+        if let Some(value) = env("RUSTLM_LLM_MAX_RETRIES") {
+            self.llm.max_retries = parse("RUSTLM_LLM_MAX_RETRIES", value)?;
+        }
+        if let Some(value) = env("RUSTLM_LLM_SYSTEM_PROMPT") {
+            self.llm.system_prompt = Some(value);
+        }
+        if let Some(value) = env("RUSTLM_ENABLED_TASKS") {
+            self.tasks.enabled = Some(list(value));
+        }
+        if let Some(value) = env("RUSTLM_DISABLED_TASKS") {
+            self.tasks.disabled = list(value);
+        }
+        if let Some(value) = env("RUSTLM_AUTH_ENABLED") {
+            self.auth.enabled = parse("RUSTLM_AUTH_ENABLED", value)?;
+        }
+        if let Some(value) = env("RUSTLM_AUTH_TOKEN_SECRET") {
+            self.auth.token_secret = Some(value);
+        }
+        if let Some(value) = env("RUSTLM_RATE_LIMIT_ENABLED") {
+            self.rate_limit.enabled = parse("RUSTLM_RATE_LIMIT_ENABLED", value)?;
+        }
+        if let Some(value) = env("RUSTLM_LLM_REQUESTS_PER_MINUTE") {
+            self.rate_limit.llm.requests_per_minute = parse("RUSTLM_LLM_REQUESTS_PER_MINUTE", value)?;
+        }
+        if let Some(value) = env("RUSTLM_LLM_DAILY_QUOTA") {
+            self.rate_limit.llm.daily_quota = Some(parse("RUSTLM_LLM_DAILY_QUOTA", value)?);
+        }
+        Ok(())
+    }
+
+    /// Check the settings, reporting every problem at once
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = Vec::new();
+
+        if self.discovery.enabled {
+            if self.discovery.service_name.trim().is_empty() {
+                errors.push("discovery.service_name must not be empty".to_string());
+            }
+            if self.discovery.shared_key.is_empty() {
+                errors.push("discovery.shared_key must not be empty".to_string());
+            }
+            if self.discovery.port == 0 {
+                errors.push("discovery.port must not be 0".to_string());
+            }
+            if self.discovery.mode != DiscoveryMode::OnRequest && self.discovery.interval_secs == 0 {
+                errors.push("discovery.interval_secs must be at least 1".to_string());
+            }
+            if self.discovery.mode == DiscoveryMode::Limited && self.discovery.count == 0 {
+                errors.push("discovery.count must be at least 1 in limited mode".to_string());
+            }
+        }
+
+        let endpoint = self.llm.endpoint();
+        if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+            errors.push(format!("llm.endpoint must be an http(s) URL, got '{}'", endpoint));
+        }
+        if self.llm.provider == LlmProviderKind::Openai && self.llm.model.as_deref().is_none_or(str::is_empty) {
+            errors.push("llm.model must be set for the openai provider".to_string());
+        }
+        if self.llm.timeout_secs == 0 {
+            errors.push("llm.timeout_secs must be at least 1".to_string());
+        }
+        if self.llm.connect_timeout_secs == 0 {
+            errors.push("llm.connect_timeout_secs must be at least 1".to_string());
+        }
+        if self.llm.circuit_failure_threshold == 0 {
+            errors.push("llm.circuit_failure_threshold must be at least 1".to_string());
+        }
+        if self.llm.circuit_reset_secs == 0 {
+            errors.push("llm.circuit_reset_secs must be at least 1".to_string());
+        }
+
+        let known = TaskRegistry::with_builtin_tasks();
+        let listed = self.tasks.enabled.iter().flatten().chain(&self.tasks.disabled);
+        for name in listed {
+            if !known.contains(name) {
+                errors.push(format!("unknown task '{}' in tasks configuration", name));
+            }
+        }
+        if !known.names().iter().any(|name| self.tasks.is_enabled(name)) {
+            errors.push("tasks configuration leaves no task enabled".to_string());
+        }
+        if let Some(path) = &self.tasks.package_catalog {
+            if let Err(e) = PackageCatalog::load(path) {
+                errors.push(format!("tasks.package_catalog: {:#}", e));
+            }
+        }
+        let search = &self.tasks.search;
+        if search.enabled {
+            if search.roots.is_empty() {
+                errors.push("tasks.search.roots must list at least one directory".to_string());
+            }
+            for root in search.roots.iter().filter(|root| !root.is_dir()) {
+                errors.push(format!("tasks.search.roots entry {} is not a directory", root.display()));
+            }
+            if search.max_results == 0 {
+                errors.push("tasks.search.max_results must be at least 1".to_string());
+            }
+        }
+        if self.tasks.git.enabled {
+            if let Err(e) = GitRepository::new(&self.tasks.git) {
+                errors.push(format!("tasks.git.repository: {:#}", e));
+            }
+            if self.tasks.git.timeout_secs == 0 {
+                errors.push("tasks.git.timeout_secs must be at least 1".to_string());
+            }
+        }
+        let download = &self.tasks.download;
+        if download.enabled {
+            if download.directory.exists() && !download.directory.is_dir() {
+                errors.push(format!("tasks.download.directory {} is not a directory", download.directory.display()));
+            }
+            if download.max_size == 0 {
+                errors.push("tasks.download.max_size must be at least 1".to_string());
+            }
+            if download.timeout_secs == 0 {
+                errors.push("tasks.download.timeout_secs must be at least 1".to_string());
+            }
+            if download.max_concurrent == 0 {
+                errors.push("tasks.download.max_concurrent must be at least 1".to_string());
+            }
+            if download.max_total_size < download.max_size {
+                errors.push("tasks.download.max_total_size must be at least max_size".to_string());
+            }
+            for content_type in download.allowed_content_types.iter().filter(|t| !t.contains('/')) {
+                errors.push(format!(
+                    "tasks.download.allowed_content_types entry '{}' is not a content type such as application/zip",
+                    content_type
+                ));
+            }
+            for host in &download.allowed_hosts {
+                let name = host.strip_prefix("*.").unwrap_or(host);
+                if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || ".-".contains(c)) {
+                    errors.push(format!(
+                        "tasks.download.allowed_hosts entry '{}' is not a host name such as github.com or *.example.com",
+                        host
+                    ));
+                }
+            }
+        }
+
+        if self.auth.enabled && self.auth.api_keys.is_empty() && self.auth.token_secret.is_none() {
+            errors.push("auth is enabled but neither api_keys nor token_secret is set".to_string());
+        }
+        if self.auth.token_secret.as_deref().is_some_and(|secret| secret.len() < 16) {
+            errors.push("auth.token_secret must be at least 16 characters".to_string());
+        }
+        for (index, key) in self.auth.api_keys.iter().enumerate() {
+            if key.key.is_empty() {
+                errors.push(format!("auth.api_keys '{}' has an empty key", key.name));
+            }
+            if self.auth.api_keys[..index].iter().any(|other| other.key == key.key) {
+                errors.push(format!("auth.api_keys '{}' reuses another key", key.name));
+            }
+            for entry in key.allowed_tasks.iter().flatten() {
+                let is_category = ["*", "nlp", "system", "llm"].contains(&entry.to_lowercase().as_str());
+                if !is_category && !known.contains(entry) {
+                    errors.push(format!("unknown task '{}' in auth.api_keys '{}'", entry, key.name));
+                }
+            }
+        }
+
+        if self.rate_limit.enabled {
+            for (class, limits) in [("local", &self.rate_limit.local), ("llm", &self.rate_limit.llm)] {
+                if limits.requests_per_minute == 0 {
+                    errors.push(format!("rate_limit.{}.requests_per_minute must be at least 1", class));
+                }
+                if limits.burst == 0 {
+                    errors.push(format!("rate_limit.{}.burst must be at least 1", class));
+                }
+            }
+        }
+
+        if self.callbacks.max_concurrency == 0 {
+            errors.push("callbacks.max_concurrency must be at least 1".to_string());
+        }
+        if self.callbacks.timeout_secs == 0 {
+            errors.push("callbacks.timeout_secs must be at least 1".to_string());
+        }
+        let executor = &self.callbacks.executor;
+        if executor.enabled || self.actions.enabled && self.actions.execute {
+            if !executor.working_dir.is_dir() {
+                errors.push(format!(
+                    "callbacks.executor.working_dir {} is not a directory",
+                    executor.working_dir.display()
+                ));
+            }
+            if executor.allowed_commands.iter().any(|c| c.trim().is_empty()) {
+                errors.push("callbacks.executor.allowed_commands must not contain empty entries".to_string());
+            }
+            if executor.timeout_secs == 0 {
+                errors.push("callbacks.executor.timeout_secs must be at least 1".to_string());
+            }
+        }
+        for (index, webhook) in self.callbacks.webhooks.iter().enumerate() {
+            if webhook.name.trim().is_empty() {
+                errors.push(format!("callbacks.webhooks[{}] needs a name", index));
+            } else if ["system", "nlp", "executor"].contains(&webhook.name.as_str())
+                || self.callbacks.webhooks[..index].iter().any(|other| other.name == webhook.name)
+            {
+                errors.push(format!("callbacks.webhooks name '{}' is already in use", webhook.name));
+            }
+            if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") {
+                errors.push(format!("callbacks.webhooks '{}' url must be an http(s) URL", webhook.name));
+            }
+            if webhook.max_attempts == 0 {
+                errors.push(format!("callbacks.webhooks '{}' max_attempts must be at least 1", webhook.name));
+            }
+            if webhook.timeout_secs == 0 {
+                errors.push(format!("callbacks.webhooks '{}' timeout_secs must be at least 1", webhook.name));
+            }
+        }
+
+        if self.actions.enabled && self.actions.ttl_secs == 0 {
+            errors.push("actions.ttl_secs must be at least 1".to_string());
+        }
+        if self.actions.enabled && executor.enabled {
+            errors.push(
+                "callbacks.executor.enabled runs suggestions without a confirmation and can't be combined with actions.enabled"
+                    .to_string(),
+            );
+        }
+
+        if self.sessions.enabled {
+            if self.sessions.max_history_tokens == 0 {
+                errors.push("sessions.max_history_tokens must be at least 1".to_string());
+            }
+            if self.sessions.idle_timeout_secs == 0 {
+                errors.push("sessions.idle_timeout_secs must be at least 1".to_string());
+            }
+            if self.sessions.max_sessions == 0 {
+                errors.push("sessions.max_sessions must be at least 1".to_string());
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            bail!("Invalid configuration:\n  - {}", errors.join("\n  - "))
+        }
+    }
+
+    /// Built-in tasks configured from the settings, with disabled tasks removed
+    ///
+    /// `get_file_from` downloads through `downloader` when given; it's shared with the server so
+    /// the status of downloads can be looked up.
+    pub fn task_registry(&self, downloader: Option<Arc<Downloader>>) -> TaskRegistry {
+        let mut registry = TaskRegistry::with_builtin_tasks();
+        let mut ask_ai = AskAiTask::with_provider(self.llm.provider());
+        if let Some(prompt) = &self.llm.system_prompt {
+            ask_ai = ask_ai.with_system_prompt(prompt);
+        }
+        registry.register(ask_ai);
+        if let Some(path) = &self.tasks.package_catalog {
+            match PackageCatalog::load(path) {
+                Ok(catalog) => registry.register(InstallTask::with_catalog(Arc::new(catalog))),
+                Err(e) => tracing::warn!("Using the bundled package catalog: {:#}", e),
+            }
+        }
+        if self.tasks.search.enabled {
+            match WorkspaceSearch::new(&self.tasks.search) {
+                Ok(search) => {
+                    let search = Arc::new(search);
+                    registry.register(FindFileTask::with_search(search.clone()));
+                    registry.register(FindContentTask::with_search(search));
+                }
+                Err(e) => tracing::warn!("Workspace search is disabled: {:#}", e),
+            }
+        }
+        if self.tasks.git.enabled {
+            match GitRepository::new(&self.tasks.git) {
+                Ok(repository) => {
+                    let repository = Arc::new(repository);
+                    registry.register(CheckoutTask::with_repository(repository.clone()));
+                    registry.register(DiffTask::with_repository(repository));
+                }
+                Err(e) => tracing::warn!("Git integration is disabled: {:#}", e),
+            }
+        }
+        if let Some(downloader) = downloader {
+            registry.register(GetFileFromTask::with_downloader(downloader));
+        }
+        self.tasks.apply(&mut registry);
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_parse_config_file() {
+        let config = Config::from_toml(
+            r#"
+            [server]
+            bind = "127.0.0.1:8080"
+
+            [discovery]
+            mode = "limited"
+            interval_secs = 10
+            count = 3
+            port = 9999
+
+            [llm]
+            endpoint = "https://example.openai.azure.com"
+            deployment = "gpt-4o"
+
+            [tasks]
+            disabled = ["ask_ai"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.server.bind, "127.0.0.1:8080".parse().unwrap());
+        assert_eq!(config.discovery.announcement_mode(), AnnouncementMode::Limited(10, 3));
+        assert_eq!(config.discovery.port, 9999);
+        assert_eq!(config.discovery.service_name, "rustlm-service");
+        assert_eq!(config.llm.deployment, "gpt-4o");
+        assert!(!config.tasks.is_enabled("ask_ai"));
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        assert!(Config::from_toml("[server]\nport = 3000\n").is_err());
+    }
+
+    #[test]
+    fn test_flags_override_environment() {
+        let cli = Cli::try_parse_from([
+            "rustlm-server",
+            "--bind",
+            "127.0.0.1:4000",
+            "--discovery-mode",
+            "periodic",
+        ])
+        .unwrap();
+        let config = Config::resolve(
+            &cli,
+            env(&[
+                ("RUSTLM_BIND", "127.0.0.1:5000"),
+                ("RUSTLM_DISCOVERY_PORT", "7777"),
+                ("AZURE_OPENAI_API_KEY", "secret"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.server.bind, "127.0.0.1:4000".parse().unwrap());
+        assert_eq!(config.discovery.mode, DiscoveryMode::Periodic);
+        assert_eq!(config.discovery.port, 7777);
+        assert!(config.llm.azure_config().is_configured());
+    }
+
+    #[test]
+    fn test_llm_provider_config() {
+        let config = Config::from_toml("[llm]\nprovider = \"local\"\nmodel = \"llama3.2\"\n").unwrap();
+        assert!(config.validate().is_ok());
+        let provider = config.llm.provider();
+        assert_eq!(provider.name(), "local");
+        assert!(provider.is_configured());
+        assert_eq!(provider.chat_completions_url(), "http://localhost:11434/v1/chat/completions");
+        assert_eq!(provider.model(), Some("llama3.2"));
+
+        let cli = Cli::try_parse_from(["rustlm-server", "--llm-provider", "openai"]).unwrap();
+        let error = Config::resolve(&cli, env(&[("RUSTLM_LLM_API_KEY", "sk-test")])).unwrap_err();
+        assert!(error.to_string().contains("llm.model must be set for the openai provider"));
+
+        let config = Config::resolve(&cli, env(&[("RUSTLM_LLM_API_KEY", "sk-test"), ("RUSTLM_LLM_MODEL", "gpt-4o-mini")])).unwrap();
+        let provider = config.llm.provider();
+        assert_eq!(provider.name(), "openai");
+        assert!(provider.is_configured());
+        assert_eq!(provider.auth_header(), Some(("Authorization", "Bearer sk-test".to_string())));
+    }
+
+    #[test]
+    fn test_llm_client_config() {
+        let config = Config::resolve(&Cli::default(), env(&[("RUSTLM_LLM_MAX_RETRIES", "4")])).unwrap();
+        let options = config.llm.client_options();
+        assert_eq!(options.max_retries, 4);
+        assert_eq!(options.retry_backoff, Duration::from_millis(500));
+
+        let config = Config::from_toml("[llm]\nconnect_timeout_secs = 0\ncircuit_failure_threshold = 0\n").unwrap();
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("llm.connect_timeout_secs must be at least 1"));
+        assert!(error.contains("llm.circuit_failure_threshold must be at least 1"));
+    }
+
+    #[test]
+    fn test_invalid_environment_value() {
+        let error = Config::resolve(&Cli::default(), env(&[("RUSTLM_DISCOVERY_PORT", "abc")])).unwrap_err();
+        assert!(error.to_string().contains("RUSTLM_DISCOVERY_PORT"));
+    }
+
+    #[test]
+    fn test_validation_reports_all_errors() {
+        let mut config = Config::default();
+        config.discovery.mode = DiscoveryMode::Periodic;
+        config.discovery.interval_secs = 0;
+        config.llm.endpoint = Some("example.com".to_string());
+        config.tasks.disabled = vec!["no_such_task".to_string()];
+
+        let message = config.validate().unwrap_err().to_string();
+        assert!(message.contains("discovery.interval_secs"));
+        assert!(message.contains("llm.endpoint"));
+        assert!(message.contains("no_such_task"));
+    }
+
+    #[test]
+    fn test_auth_config() {
+        let config = Config::from_toml(
+            r#"
+            [auth]
+            enabled = true
+            token_secret = "a-long-enough-secret"
+
+            [[auth.api_keys]]
+            name = "frontend"
+            key = "frontend-key"
+            allowed_tasks = ["nlp", "google_search"]
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.auth.api_keys[0].allowed_tasks.as_ref().unwrap().len(), 2);
+
+        let mut config = Config::default();
+        config.auth.enabled = true;
+        assert!(config.validate().unwrap_err().to_string().contains("neither api_keys nor token_secret"));
+    }
+
+    #[test]
+    fn test_rate_limit_config() {
+        let config = Config::from_toml(
+            r#"
+            [rate_limit]
+            enabled = true
+
+            [rate_limit.llm]
+            requests_per_minute = 10
+            burst = 2
+            daily_quota = 100
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.rate_limit.llm.daily_quota, Some(100));
+        assert_eq!(config.rate_limit.local, RateLimitConfig::default().local);
+
+        let mut config = Config::default();
+        config.rate_limit.enabled = true;
+        config.rate_limit.local.burst = 0;
+        assert!(config.validate().unwrap_err().to_string().contains("rate_limit.local.burst"));
+    }
+
+    #[test]
+    fn test_executor_config() {
+        let config = Config::default();
+        assert!(!config.callbacks.executor.enabled);
+        assert_eq!(config.callbacks.executor.allowed_commands, vec!["find", "rg", "git diff"]);
+
+        let mut config = Config::from_toml(
+            r#"
+            [callbacks.executor]
+            enabled = true
+            allowed_commands = ["find"]
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        config.actions.enabled = true;
+        assert!(config.validate().unwrap_err().to_string().contains("can't be combined with actions.enabled"));
+        config.actions.enabled = false;
+
+        config.callbacks.executor.working_dir = PathBuf::from("/does/not/exist");
+        assert!(config.validate().unwrap_err().to_string().contains("not a directory"));
+    }
+
+    #[test]
+    fn test_package_catalog_config() {
+        let mut config = Config::from_toml("[tasks]\npackage_catalog = \"/does/not/exist.toml\"\n").unwrap();
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("tasks.package_catalog: Failed to read package catalog"));
+
+        let path = std::env::temp_dir().join(format!("rustlm-catalog-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "[[packages]]\nname = \"rg\"\necosystems = [\"cargo\"]\nmanagers = { yum = \"rg\" }\n").unwrap();
+        config.tasks.package_catalog = Some(path.clone());
+        let error = config.validate().unwrap_err().to_string();
+        std::fs::remove_file(&path).unwrap();
+        assert!(error.contains("unknown manager 'yum'"));
+    }
+
+    #[test]
+    fn test_webhook_config() {
+        let config = Config::from_toml(
+            r#"
+            [callbacks]
+            max_concurrency = 2
+
+            [[callbacks.webhooks]]
+            name = "results"
+            url = "https://hooks.example.com/rustlm"
+            commands = ["install"]
+            secret = "shared-secret"
+            headers = { "x-team" = "search" }
+            background = true
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.callbacks.max_concurrency, 2);
+        assert_eq!(config.callbacks.timeout_secs, 30);
+        let webhook = &config.callbacks.webhooks[0];
+        assert_eq!(webhook.max_attempts, 3);
+        assert_eq!(webhook.headers["x-team"], "search");
+        assert!(webhook.background);
+
+        let mut config = Config::default();
+        config.callbacks.max_concurrency = 0;
+        config.callbacks.webhooks = vec![WebhookConfig::new("nlp", "ftp://example.com")];
+        let message = config.validate().unwrap_err().to_string();
+        assert!(message.contains("callbacks.max_concurrency"));
+        assert!(message.contains("'nlp' is already in use"));
+        assert!(message.contains("url must be an http(s) URL"));
+    }
+
+    #[test]
+    fn test_task_registry_only_has_enabled_tasks() {
+        let cli = Cli::try_parse_from([
+            "rustlm-server",
+            "--enable-task",
+            "sentiment",
+            "--enable-task",
+            "ask_ai",
+            "--disable-task",
+            "ask_ai",
+        ])
+        .unwrap();
+        let config = Config::resolve(&cli, env(&[])).unwrap();
+
+        assert_eq!(config.task_registry(None).names(), vec!["sentiment".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_workspace_search_config() {
+        let mut config = Config::from_toml("[tasks.search]\nenabled = true\nroots = [\"/does/not/exist\"]\n").unwrap();
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("tasks.search.roots entry /does/not/exist is not a directory"));
+
+        let root = std::env::temp_dir().join(format!("rustlm-search-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("notes.txt"), "first\nneedle here\n").unwrap();
+        config.tasks.search.roots = vec![root.clone()];
+        assert!(config.validate().is_ok());
+
+        let registry = config.task_registry(None);
+        let task = registry.get("find_content").unwrap();
+        let output = task.run("needle", &crate::tasks::TaskContext::new(&registry)).await.unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let workspace = &output.result.to_value()["workspace"];
+        assert_eq!(workspace["matches"][0]["path"], "notes.txt");
+        assert_eq!(workspace["matches"][0]["line_number"], 2);
+        assert_eq!(workspace["matches"][0]["before"], serde_json::json!(["first"]));
+    }
+
+    #[test]
+    fn test_download_config() {
+        let config = Config::from_toml(
+            "[tasks.download]\nenabled = true\nmax_size = 0\nallowed_content_types = [\"zip\", \"image/*\"]\nallowed_hosts = [\"*.github.com\", \"https://example.com\"]\n",
+        )
+        .unwrap();
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("tasks.download.max_size must be at least 1"));
+        assert!(!error.contains("max_total_size"));
+        assert!(error.contains("allowed_content_types entry 'zip' is not a content type"));
+        assert!(!error.contains("image/*"));
+        assert!(error.contains("allowed_hosts entry 'https://example.com' is not a host name"));
+        assert!(!error.contains("*.github.com"));
+    }
+
+    #[test]
+    fn test_sessions_config() {
+        let config = Config::from_toml("[llm]\nsystem_prompt = \"Answer briefly.\"\n\n[sessions]\nmax_history_tokens = 0\n").unwrap();
+        assert_eq!(config.llm.system_prompt.as_deref(), Some("Answer briefly."));
+        assert!(config.sessions.enabled);
+        assert!(config.validate().unwrap_err().to_string().contains("sessions.max_history_tokens must be at least 1"));
+
+        let config = Config::from_toml("[sessions]\nenabled = false\nmax_history_tokens = 0\n").unwrap();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_git_config() {
+        let mut config = Config::from_toml("[tasks.git]\nenabled = true\nrepository = \"/does/not/exist\"\ntimeout_secs = 0\n").unwrap();
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("tasks.git.repository: Invalid git repository /does/not/exist"));
+        assert!(error.contains("tasks.git.timeout_secs must be at least 1"));
+
+        config.tasks.git.repository = std::env::temp_dir();
+        config.tasks.git.timeout_secs = 10;
+        assert!(config.validate().unwrap_err().to_string().contains("is not a git repository"));
+    }
+}
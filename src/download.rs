@@ -0,0 +1,893 @@
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use reqwest::header::{HeaderMap, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE, LAST_MODIFIED, LOCATION, RANGE};
+use reqwest::redirect::Policy;
+use reqwest::{StatusCode, Url};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::config::DownloadConfig;
+use crate::results::{DownloadState, DownloadStatus};
+use crate::shell;
+
+/// Appended to a file's name and a hash of its URL while it's downloaded; a later download of the
+/// same URL resumes from it
+const PARTIAL_SUFFIX: &str = ".part";
+
+/// Appended to a partial file's name for the `ETag` or `Last-Modified` it was downloaded with
+const VALIDATOR_SUFFIX: &str = ".validator";
+
+/// Redirects followed before a download fails
+const MAX_REDIRECTS: usize = 10;
+
+/// Hash algorithms a checksum may use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Sha256,
+    Sha512,
+}
+
+impl ChecksumAlgorithm {
+    fn name(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "sha256",
+            ChecksumAlgorithm::Sha512 => "sha512",
+        }
+    }
+
+    fn hex_len(&self) -> usize {
+        match self {
+            ChecksumAlgorithm::Sha256 => 64,
+            ChecksumAlgorithm::Sha512 => 128,
+        }
+    }
+}
+
+/// Expected hash of a downloaded file, written `sha256:<hex>` or `sha512:<hex>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: ChecksumAlgorithm,
+    /// Lowercase hex digest
+    pub hex: String,
+}
+
+impl Checksum {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let Some((algorithm, hex)) = text.split_once(':') else {
+            return Err(format!("'{}' is not a checksum, use sha256:<hex> or sha512:<hex>", text));
+        };
+        let algorithm = match algorithm.to_ascii_lowercase().as_str() {
+            "sha256" => ChecksumAlgorithm::Sha256,
+            "sha512" => ChecksumAlgorithm::Sha512,
+            other => return Err(format!("Checksum algorithm '{}' is not supported, use sha256 or sha512", other)),
+        };
+        if hex.len() != algorithm.hex_len() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("A {} checksum must be {} hex digits", algorithm.name(), algorithm.hex_len()));
+        }
+        Ok(Self {
+            algorithm,
+            hex: hex.to_ascii_lowercase(),
+        })
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm.name(), self.hex)
+    }
+}
+
+/// Running hash of a download
+enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            ChecksumAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize_hex(self) -> String {
+        let digest = match self {
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha512(hasher) => hasher.finalize().to_vec(),
+        };
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// Downloads `get_file_from` URLs into a sandbox directory in the background
+pub struct Downloader {
+    directory: PathBuf,
+    max_size: u64,
+    /// Lowercase content types, possibly ending in `/*`
+    allowed_content_types: Vec<String>,
+    max_finished: usize,
+    /// Lowercase host names, possibly starting with `*.`; any public host when empty
+    allowed_hosts: Vec<String>,
+    /// Lets tests download from a server on the loopback interface
+    allow_private_addresses: bool,
+    timeout: Duration,
+    /// Downloads that may run at once; the rest wait as `queued`
+    permits: Semaphore,
+    max_total_size: u64,
+    /// Bytes in the download directory, counted when a download starts plus what's written since
+    usage: Mutex<u64>,
+    jobs: Mutex<HashMap<Uuid, DownloadStatus>>,
+}
+
+impl Downloader {
+    /// Set up downloads into `config.directory`, creating it if needed
+    pub fn new(config: &DownloadConfig) -> Result<Self> {
+        std::fs::create_dir_all(&config.directory)
+            .with_context(|| format!("Failed to create download directory {}", config.directory.display()))?;
+        let directory = config
+            .directory
+            .canonicalize()
+            .with_context(|| format!("Invalid download directory {}", config.directory.display()))?;
+
+        Ok(Self {
+            directory,
+            max_size: config.max_size,
+            allowed_content_types: config.allowed_content_types.iter().map(|t| t.to_ascii_lowercase()).collect(),
+            max_finished: config.max_finished,
+            allowed_hosts: config.allowed_hosts.iter().map(|h| h.to_ascii_lowercase()).collect(),
+            allow_private_addresses: false,
+            timeout: Duration::from_secs(config.timeout_secs),
+            permits: Semaphore::new(config.max_concurrent.max(1)),
+            max_total_size: config.max_total_size,
+            usage: Mutex::new(0),
+            jobs: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Start downloading `url` in the background for `client_id`; a download of the same URL that
+    /// the client is still running is returned instead of starting another
+    pub fn start(self: &Arc<Self>, url: &Url, checksum: Option<Checksum>, client_id: Option<&str>) -> DownloadStatus {
+        let file = file_name(url);
+        let mut status = DownloadStatus {
+            id: Uuid::new_v4(),
+            url: url.to_string(),
+            file: file.clone(),
+            state: DownloadState::Queued,
+            bytes_downloaded: 0,
+            total_bytes: None,
+            resumed_from: 0,
+            content_type: None,
+            checksum: checksum.as_ref().map(ToString::to_string),
+            error: None,
+            started_at: Utc::now(),
+            finished_at: None,
+            client_id: client_id.map(str::to_string),
+        };
+
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(running) = jobs.values().find(|job| job.file == file && !job.is_finished()) {
+            if running.url == status.url && running.client_id == status.client_id {
+                return running.clone();
+            }
+            status.state = DownloadState::Failed;
+            status.error = Some(if running.client_id == status.client_id {
+                format!("{} is already being downloaded from {}", file, running.url)
+            } else {
+                format!("{} is already being downloaded by another client", file)
+            });
+            status.finished_at = Some(status.started_at);
+        }
+        jobs.insert(status.id, status.clone());
+        self.prune(&mut jobs);
+        drop(jobs);
+
+        if status.state == DownloadState::Queued {
+            info!("Downloading {} to {}", url, file);
+            let downloader = self.clone();
+            let (id, url) = (status.id, url.clone());
+            tokio::spawn(async move { downloader.run(id, url, file, checksum).await });
+        }
+        status
+    }
+
+    pub fn status(&self, id: Uuid) -> Option<DownloadStatus> {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.get(&id).cloned()
+    }
+
+    /// Running downloads and the most recent finished ones, oldest first
+    pub fn list(&self) -> Vec<DownloadStatus> {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        let mut list: Vec<DownloadStatus> = jobs.values().cloned().collect();
+        list.sort_by_key(|job| job.started_at);
+        list
+    }
+
+    async fn run(&self, id: Uuid, url: Url, file: String, checksum: Option<Checksum>) {
+        // The semaphore is never closed
+        let _permit = self.permits.acquire().await.ok();
+        self.update(id, |status| status.state = DownloadState::Running);
+        let result = self.fetch(id, &url, &file, checksum.as_ref()).await;
+        if let Err(e) = &result {
+            warn!("Failed to download {}: {:#}", url, e);
+        }
+        self.update(id, |status| {
+            status.finished_at = Some(Utc::now());
+            match result {
+                Ok(()) => status.state = DownloadState::Completed,
+                Err(e) => {
+                    status.state = DownloadState::Failed;
+                    status.error = Some(format!("{:#}", e));
+                }
+            }
+        });
+    }
+
+    /// Download into the partial file, then move it into place once it's complete and verified
+    ///
+    /// The partial file is kept when the transfer is interrupted, so it can be resumed, and removed
+    /// when the file isn't acceptable. An existing file is never replaced.
+    async fn fetch(&self, id: Uuid, url: &Url, file: &str, checksum: Option<&Checksum>) -> Result<()> {
+        let path = self.directory.join(file);
+        let partial = PartialFile::new(&self.directory, file, url);
+        for candidate in [&path, &partial.data, &partial.validator] {
+            // A link could point outside the download directory
+            if tokio::fs::symlink_metadata(candidate).await.is_ok_and(|m| m.file_type().is_symlink()) {
+                bail!("{} in the download directory is a symbolic link", candidate.display());
+            }
+        }
+        if tokio::fs::symlink_metadata(&path).await.is_ok() {
+            bail!("{} already exists in the download directory", file);
+        }
+
+        let used = self.measure_usage().await?;
+
+        // Only a partial file with the validator it was downloaded under can be continued safely
+        let stored = partial.stored_validator().await;
+        let mut offset = match &stored {
+            Some(_) => tokio::fs::metadata(&partial.data).await.map(|m| m.len()).unwrap_or(0),
+            None => 0,
+        };
+        let mut response = self.request(url, offset, stored.as_deref()).await?;
+        let mut total = None;
+        if offset > 0 {
+            match response.status() {
+                StatusCode::PARTIAL_CONTENT => match content_range(&response) {
+                    Some((start, size)) if start == offset => total = size,
+                    _ => {
+                        offset = 0;
+                        response = self.request(url, 0, None).await?;
+                    }
+                },
+                // The partial file can't be continued, e.g. because the file changed
+                StatusCode::RANGE_NOT_SATISFIABLE => {
+                    offset = 0;
+                    response = self.request(url, 0, None).await?;
+                }
+                // The server sent the whole file, e.g. because `If-Range` didn't match
+                _ => offset = 0,
+            }
+        }
+        let mut response = response.error_for_status().context("Download failed")?;
+        let total = total.or_else(|| response.content_length().map(|length| offset + length));
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_ascii_lowercase())
+            .filter(|value| !value.is_empty());
+        self.update(id, |status| {
+            status.total_bytes = total;
+            status.resumed_from = offset;
+            status.bytes_downloaded = offset;
+            status.content_type = content_type.clone();
+        });
+        let check = self.check_response(content_type.as_deref(), total).and_then(|()| match total {
+            Some(total) if used + (total - offset) > self.max_total_size => bail!(
+                "File needs {} more bytes, which would put the download directory over its quota of {} bytes",
+                total - offset,
+                self.max_total_size
+            ),
+            _ => Ok(()),
+        });
+        if let Err(e) = check {
+            partial.discard().await;
+            return Err(e);
+        }
+
+        let mut hasher = checksum.map(|checksum| Hasher::new(checksum.algorithm));
+        let mut output = if offset > 0 {
+            if let Some(hasher) = hasher.as_mut() {
+                hash_file(&partial.data, hasher).await.context("Failed to read the partial download")?;
+            }
+            tokio::fs::OpenOptions::new().append(true).open(&partial.data).await
+        } else {
+            partial.store_validator(response.headers()).await?;
+            tokio::fs::File::create(&partial.data).await
+        }
+        .with_context(|| format!("Failed to write {}", partial.data.display()))?;
+
+        let mut downloaded = offset;
+        while let Some(chunk) = response.chunk().await.context("Download interrupted")? {
+            downloaded += chunk.len() as u64;
+            if downloaded > self.max_size {
+                drop(output);
+                partial.discard().await;
+                bail!("File is larger than the limit of {} bytes", self.max_size);
+            }
+            if !self.reserve(chunk.len() as u64) {
+                drop(output);
+                partial.discard().await;
+                bail!("The download directory is over its quota of {} bytes", self.max_total_size);
+            }
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(&chunk);
+            }
+            output.write_all(&chunk).await.context("Failed to write the download")?;
+            self.update(id, |status| status.bytes_downloaded = downloaded);
+        }
+        output.flush().await.context("Failed to write the download")?;
+        drop(output);
+
+        if let (Some(hasher), Some(expected)) = (hasher, checksum) {
+            let actual = hasher.finalize_hex();
+            if actual != expected.hex {
+                partial.discard().await;
+                bail!("{} checksum mismatch: expected {}, got {}", expected.algorithm.name(), expected.hex, actual);
+            }
+        }
+        // Linking fails if another download finished the same file meanwhile, where renaming wouldn't
+        tokio::fs::hard_link(&partial.data, &path)
+            .await
+            .with_context(|| format!("Failed to move the download to {}", path.display()))?;
+        partial.discard().await;
+        info!("Downloaded {} to {} ({} bytes)", url, path.display(), downloaded);
+        Ok(())
+    }
+
+    /// Request `url` from `offset`, if the file still has `validator`, following redirects and
+    /// checking every host on the way
+    async fn request(&self, url: &Url, offset: u64, validator: Option<&str>) -> Result<reqwest::Response> {
+        let mut url = url.clone();
+        for _ in 0..=MAX_REDIRECTS {
+            let mut request = self.client_for(&url).await?.get(url.clone());
+            if offset > 0 {
+                request = request.header(RANGE, format!("bytes={}-", offset));
+                if let Some(validator) = validator {
+                    request = request.header(IF_RANGE, validator);
+                }
+            }
+            let response = request.send().await.context("Download failed")?;
+            let location = response.headers().get(LOCATION).and_then(|value| value.to_str().ok());
+            match location {
+                Some(location) if response.status().is_redirection() => {
+                    url = url.join(location).with_context(|| format!("Invalid redirect to {}", location))?;
+                }
+                _ => return Ok(response),
+            }
+        }
+        bail!("Too many redirects, stopped after {}", MAX_REDIRECTS)
+    }
+
+    /// A client that only connects to `url`'s host at the addresses checked here, so a second DNS
+    /// lookup can't point it somewhere else
+    async fn client_for(&self, url: &Url) -> Result<reqwest::Client> {
+        let url = shell::validate_url(url.as_str()).map_err(anyhow::Error::msg)?;
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        if !self.allowed_hosts.is_empty() && !self.allowed_hosts.iter().any(|allowed| host_matches(allowed, &host)) {
+            bail!("Host {} is not an allowed download host", host);
+        }
+
+        let port = url.port_or_known_default().unwrap_or(80);
+        let literal = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().ok();
+        let addresses: Vec<SocketAddr> = match literal {
+            Some(ip) => vec![SocketAddr::new(ip, port)],
+            None => tokio::net::lookup_host((host.as_str(), port))
+                .await
+                .with_context(|| format!("Failed to resolve {}", host))?
+                .collect(),
+        };
+        if addresses.is_empty() {
+            bail!("{} has no addresses", host);
+        }
+        if let Some(address) = addresses.iter().find(|address| !self.allow_private_addresses && !is_public(address.ip())) {
+            match literal {
+                Some(ip) => bail!("{} is not a public address", ip),
+                None => bail!("{} resolves to {}, which is not a public address", host, address.ip()),
+            }
+        }
+
+        let mut client = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .redirect(Policy::none())
+            .no_proxy();
+        if literal.is_none() {
+            client = client.resolve_to_addrs(&host, &addresses);
+        }
+        Ok(client.build()?)
+    }
+
+    /// Count the bytes of the files in the download directory, which later writes are added to
+    async fn measure_usage(&self) -> Result<u64> {
+        let mut used = 0;
+        let mut entries = tokio::fs::read_dir(&self.directory).await.context("Failed to read the download directory")?;
+        while let Some(entry) = entries.next_entry().await.context("Failed to read the download directory")? {
+            match entry.metadata().await {
+                Ok(metadata) if metadata.is_file() => used += metadata.len(),
+                _ => {}
+            }
+        }
+        *self.usage.lock().unwrap_or_else(|e| e.into_inner()) = used;
+        Ok(used)
+    }
+
+    /// Count `bytes` about to be written against the quota, unless they'd go over it
+    fn reserve(&self, bytes: u64) -> bool {
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        if *usage + bytes > self.max_total_size {
+            return false;
+        }
+        *usage += bytes;
+        true
+    }
+
+    fn check_response(&self, content_type: Option<&str>, total: Option<u64>) -> Result<()> {
+        if let Some(total) = total.filter(|&total| total > self.max_size) {
+            bail!("File is {} bytes, over the limit of {} bytes", total, self.max_size);
+        }
+        if !self.allowed_content_types.is_empty() {
+            let content_type = content_type.unwrap_or("application/octet-stream");
+            if !self.allowed_content_types.iter().any(|allowed| content_type_matches(allowed, content_type)) {
+                bail!("Content type {} is not allowed", content_type);
+            }
+        }
+        Ok(())
+    }
+
+    fn update(&self, id: Uuid, change: impl FnOnce(&mut DownloadStatus)) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(status) = jobs.get_mut(&id) {
+            change(status);
+        }
+    }
+
+    /// Forget the oldest finished downloads beyond `max_finished`
+    fn prune(&self, jobs: &mut HashMap<Uuid, DownloadStatus>) {
+        let mut finished: Vec<_> = jobs
+            .values()
+            .filter(|job| job.is_finished())
+            .map(|job| (job.finished_at, job.id))
+            .collect();
+        if finished.len() <= self.max_finished {
+            return;
+        }
+        finished.sort();
+        for (_, id) in &finished[..finished.len() - self.max_finished] {
+            jobs.remove(id);
+        }
+    }
+}
+
+/// The partial file a URL is downloaded into, and the validator the server sent for it
+struct PartialFile {
+    data: PathBuf,
+    validator: PathBuf,
+}
+
+impl PartialFile {
+    /// Partial files are named after the URL as well as the file, so a download only ever resumes
+    /// what the same URL wrote
+    fn new(directory: &Path, file: &str, url: &Url) -> Self {
+        let key: String = Sha256::digest(url.as_str()).iter().take(8).map(|b| format!("{:02x}", b)).collect();
+        let data = directory.join(format!("{}.{}{}", file, key, PARTIAL_SUFFIX));
+        let validator = directory.join(format!("{}.{}{}{}", file, key, PARTIAL_SUFFIX, VALIDATOR_SUFFIX));
+        Self { data, validator }
+    }
+
+    async fn stored_validator(&self) -> Option<String> {
+        let validator = tokio::fs::read_to_string(&self.validator).await.ok()?;
+        Some(validator.trim().to_string()).filter(|validator| !validator.is_empty())
+    }
+
+    /// Keep the response's strong `ETag`, or else its `Last-Modified`, to send as `If-Range` on resume
+    async fn store_validator(&self, headers: &HeaderMap) -> Result<()> {
+        let validator = headers
+            .get(ETAG)
+            .and_then(|value| value.to_str().ok())
+            .filter(|etag| !etag.starts_with("W/"))
+            .or_else(|| headers.get(LAST_MODIFIED).and_then(|value| value.to_str().ok()));
+        match validator {
+            Some(validator) => tokio::fs::write(&self.validator, validator)
+                .await
+                .with_context(|| format!("Failed to write {}", self.validator.display())),
+            None => {
+                discard(&self.validator).await;
+                Ok(())
+            }
+        }
+    }
+
+    async fn discard(&self) {
+        discard(&self.data).await;
+        discard(&self.validator).await;
+    }
+}
+
+/// Feed a file to `hasher` a block at a time
+async fn hash_file(path: &Path, hasher: &mut Hasher) -> std::io::Result<()> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+/// A name for the downloaded file from the last segment of the URL's path, safe to use in the
+/// download directory
+pub fn file_name(url: &Url) -> String {
+    let segment = url.path_segments().and_then(|mut segments| segments.next_back()).unwrap_or("");
+    let name: String = segment
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "._-".contains(c) { c } else { '_' })
+        .take(200)
+        .collect();
+    match name.trim_start_matches('.') {
+        "" => "download".to_string(),
+        name => name.to_string(),
+    }
+}
+
+/// Whether `host` is `allowed`, or a subdomain of it when `allowed` is e.g. `*.example.com`
+fn host_matches(allowed: &str, host: &str) -> bool {
+    match allowed.strip_prefix("*.") {
+        Some(domain) => host.strip_suffix(domain).is_some_and(|sub| sub.ends_with('.')),
+        None => allowed == host,
+    }
+}
+
+/// Whether an address is on the public internet, rather than e.g. loopback, a private network,
+/// link-local (including cloud metadata at 169.254.169.254) or reserved
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // Shared address space for carrier-grade NAT
+                || (a == 100 && (64..128).contains(&b))
+                // IETF protocol assignments
+                || (a == 192 && b == 0 && c == 0)
+                // Benchmarking
+                || (a == 198 && (b == 18 || b == 19))
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(ip.into()),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_unspecified()
+                    || ip.is_loopback()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+                    // Documentation
+                    || (first == 0x2001 && ip.segments()[1] == 0x0db8)
+                    // NAT64, which reaches IPv4 addresses that aren't checked here
+                    || (first == 0x64 && ip.segments()[1] == 0xff9b))
+            }
+        },
+    }
+}
+
+/// Whether `content_type` is `allowed`, or has its type when `allowed` is e.g. `image/*`
+fn content_type_matches(allowed: &str, content_type: &str) -> bool {
+    match allowed.strip_suffix("/*") {
+        Some(kind) => content_type.split('/').next() == Some(kind),
+        None => allowed == content_type,
+    }
+}
+
+/// Start and total size from `Content-Range: bytes <start>-<end>/<total>`
+fn content_range(response: &reqwest::Response) -> Option<(u64, Option<u64>)> {
+    let value = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let start = range.split_once('-')?.0.parse().ok()?;
+    Some((start, total.parse().ok()))
+}
+
+async fn discard(path: &Path) {
+    if let Err(e) = tokio::fs::remove_file(path).await {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("Failed to remove {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{header, HeaderMap, StatusCode};
+    use axum::response::{Html, IntoResponse, Response};
+    use axum::routing::get;
+    use axum::Router;
+
+    fn body() -> Vec<u8> {
+        (0..10_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn sha256(data: &[u8]) -> String {
+        Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Serve `body()` at `/file.bin` with the ETag `"v1"`, honoring `Range: bytes=<start>-` and
+    /// `If-Range`, and HTML at `/page.html`
+    async fn serve() -> String {
+        async fn file(headers: HeaderMap) -> Response {
+            let body = body();
+            let unchanged = headers.get(header::IF_RANGE).is_none_or(|validator| validator == "\"v1\"");
+            let start = headers
+                .get(header::RANGE)
+                .filter(|_| unchanged)
+                .and_then(|value| value.to_str().ok()?.strip_prefix("bytes=")?.strip_suffix('-')?.parse::<usize>().ok());
+            match start {
+                Some(start) if start >= body.len() => StatusCode::RANGE_NOT_SATISFIABLE.into_response(),
+                Some(start) => (
+                    StatusCode::PARTIAL_CONTENT,
+                    [
+                        (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                        (header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, body.len() - 1, body.len())),
+                    ],
+                    body[start..].to_vec(),
+                )
+                    .into_response(),
+                None => ([(header::CONTENT_TYPE, "application/octet-stream"), (header::ETAG, "\"v1\"")], body).into_response(),
+            }
+        }
+
+        async fn redirect(axum::extract::Query(query): axum::extract::Query<HashMap<String, String>>) -> Response {
+            (StatusCode::FOUND, [(header::LOCATION, query["to"].clone())]).into_response()
+        }
+
+        let app = Router::new()
+            .route("/file.bin", get(file))
+            .route("/redirect", get(redirect))
+            .route(
+                "/slow.bin",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_millis(300)).await;
+                    body()
+                }),
+            )
+            .route("/page.html", get(|| async { Html("<p>Not a file</p>") }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", address)
+    }
+
+    fn downloader(configure: impl FnOnce(&mut DownloadConfig)) -> Arc<Downloader> {
+        let mut config = DownloadConfig {
+            enabled: true,
+            directory: std::env::temp_dir().join(format!("rustlm-downloads-{}", Uuid::new_v4())),
+            ..DownloadConfig::default()
+        };
+        configure(&mut config);
+        let mut downloader = Downloader::new(&config).unwrap();
+        downloader.allow_private_addresses = true;
+        Arc::new(downloader)
+    }
+
+    async fn finished(downloader: &Downloader, id: Uuid) -> DownloadStatus {
+        for _ in 0..500 {
+            if let Some(status) = downloader.status(id).filter(DownloadStatus::is_finished) {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("download {} didn't finish", id);
+    }
+
+    #[tokio::test]
+    async fn test_download_verifies_checksum() {
+        let server = serve().await;
+        let downloader = downloader(|_| {});
+        let url = Url::parse(&format!("{}/file.bin", server)).unwrap();
+
+        let checksum = Checksum::parse(&format!("sha256:{}", sha256(&body()).to_uppercase())).unwrap();
+        let started = downloader.start(&url, Some(checksum), None);
+        let status = finished(&downloader, started.id).await;
+        assert_eq!(status.state, DownloadState::Completed, "{:?}", status.error);
+        assert_eq!(status.bytes_downloaded, 10_000);
+        assert_eq!(status.total_bytes, Some(10_000));
+        assert_eq!(status.content_type.as_deref(), Some("application/octet-stream"));
+        assert_eq!(std::fs::read(downloader.directory().join("file.bin")).unwrap(), body());
+
+        // A finished file is never replaced
+        let status = finished(&downloader, downloader.start(&url, None, None).id).await;
+        assert!(status.error.unwrap().contains("file.bin already exists"));
+
+        std::fs::remove_file(downloader.directory().join("file.bin")).unwrap();
+        let checksum = Checksum::parse(&format!("sha256:{}", "0".repeat(64))).unwrap();
+        let status = finished(&downloader, downloader.start(&url, Some(checksum), None).id).await;
+        assert_eq!(status.state, DownloadState::Failed);
+        assert!(status.error.unwrap().contains("sha256 checksum mismatch"));
+        assert!(std::fs::read_dir(downloader.directory()).unwrap().next().is_none());
+        assert_eq!(downloader.list().len(), 3);
+        std::fs::remove_dir_all(downloader.directory()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_resumes_partial_file() {
+        let server = serve().await;
+        let downloader = downloader(|_| {});
+        let url = Url::parse(&format!("{}/file.bin", server)).unwrap();
+        let partial = PartialFile::new(downloader.directory(), "file.bin", &url);
+        let resume = |data: &[u8], validator: Option<&str>| {
+            let _ = std::fs::remove_file(downloader.directory().join("file.bin"));
+            std::fs::write(&partial.data, data).unwrap();
+            match validator {
+                Some(validator) => std::fs::write(&partial.validator, validator).unwrap(),
+                None => drop(std::fs::remove_file(&partial.validator)),
+            }
+            let checksum = Checksum::parse(&format!("sha256:{}", sha256(&body()))).unwrap();
+            downloader.start(&url, Some(checksum), None).id
+        };
+
+        let status = finished(&downloader, resume(&body()[..4_000], Some("\"v1\""))).await;
+        assert_eq!(status.state, DownloadState::Completed, "{:?}", status.error);
+        assert_eq!(status.resumed_from, 4_000);
+        assert_eq!(status.total_bytes, Some(10_000));
+        assert_eq!(std::fs::read(downloader.directory().join("file.bin")).unwrap(), body());
+        assert!(!partial.data.exists() && !partial.validator.exists());
+
+        // The file changed since the partial file was written, so the server sends all of it
+        let status = finished(&downloader, resume(&[0; 4_000], Some("\"v0\""))).await;
+        assert_eq!(status.state, DownloadState::Completed, "{:?}", status.error);
+        assert_eq!(status.resumed_from, 0);
+
+        // Without a validator there's no telling what the partial file holds
+        let status = finished(&downloader, resume(&[0; 4_000], None)).await;
+        assert_eq!(status.state, DownloadState::Completed, "{:?}", status.error);
+        assert_eq!(status.resumed_from, 0);
+
+        // A partial file as large as the whole one can't be continued, so the download starts over
+        let status = finished(&downloader, resume(&body(), Some("\"v1\""))).await;
+        assert_eq!(status.state, DownloadState::Completed, "{:?}", status.error);
+        assert_eq!(status.resumed_from, 0);
+        assert_eq!(std::fs::read(downloader.directory().join("file.bin")).unwrap(), body());
+
+        // Another URL for the same file name never continues this one's partial file
+        let other = PartialFile::new(downloader.directory(), "file.bin", &Url::parse("https://example.com/file.bin").unwrap());
+        assert_ne!(other.data, partial.data);
+        std::fs::remove_dir_all(downloader.directory()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_limits() {
+        let server = serve().await;
+        let url = |path: &str| Url::parse(&format!("{}{}", server, path)).unwrap();
+
+        let small = downloader(|config| config.max_size = 1_000);
+        let status = finished(&small, small.start(&url("/file.bin"), None, None).id).await;
+        assert_eq!(status.state, DownloadState::Failed);
+        assert!(status.error.unwrap().contains("over the limit of 1000 bytes"));
+        assert!(std::fs::read_dir(small.directory()).unwrap().next().is_none());
+
+        let binary_only = downloader(|config| config.allowed_content_types = vec!["application/*".to_string()]);
+        let status = finished(&binary_only, binary_only.start(&url("/page.html"), None, None).id).await;
+        assert!(status.error.unwrap().contains("Content type text/html is not allowed"));
+        let status = finished(&binary_only, binary_only.start(&url("/file.bin"), None, None).id).await;
+        assert_eq!(status.state, DownloadState::Completed);
+
+        // Files already in the directory count towards its quota
+        let full = downloader(|config| config.max_total_size = 15_000);
+        std::fs::write(full.directory().join("other.dat"), [0; 6_000]).unwrap();
+        let status = finished(&full, full.start(&url("/file.bin"), None, None).id).await;
+        assert!(status.error.unwrap().contains("over its quota of 15000 bytes"));
+        assert!(!full.directory().join("file.bin").exists());
+        std::fs::remove_dir_all(full.directory()).unwrap();
+
+        // Downloads beyond max_concurrent wait their turn
+        let serial = downloader(|config| config.max_concurrent = 1);
+        let slow = serial.start(&url("/slow.bin"), None, None).id;
+        let waiting = serial.start(&url("/file.bin"), None, None).id;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(serial.status(slow).unwrap().state, DownloadState::Running);
+        assert_eq!(serial.status(waiting).unwrap().state, DownloadState::Queued);
+        assert_eq!(finished(&serial, waiting).await.state, DownloadState::Completed);
+        assert_eq!(finished(&serial, slow).await.state, DownloadState::Completed);
+        std::fs::remove_dir_all(serial.directory()).unwrap();
+
+        std::fs::remove_dir_all(small.directory()).unwrap();
+        std::fs::remove_dir_all(binary_only.directory()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_checks_every_host() {
+        let server = serve().await;
+        let port = Url::parse(&server).unwrap().port().unwrap();
+        let url = |path: &str| Url::parse(&format!("{}{}", server, path)).unwrap();
+
+        let public_only = downloader(|_| {});
+        let public_only = Arc::new(Downloader {
+            allow_private_addresses: false,
+            ..Arc::into_inner(public_only).unwrap()
+        });
+        let status = finished(&public_only, public_only.start(&url("/file.bin"), None, None).id).await;
+        assert!(status.error.unwrap().contains("127.0.0.1 is not a public address"));
+
+        // Redirects are followed, and each one is checked like the first request
+        let listed = downloader(|config| config.allowed_hosts = vec!["127.0.0.1".to_string()]);
+        let status = finished(&listed, listed.start(&url("/redirect?to=/file.bin"), None, None).id).await;
+        assert_eq!(status.state, DownloadState::Completed, "{:?}", status.error);
+        std::fs::remove_file(listed.directory().join("redirect")).unwrap();
+        let elsewhere = format!("/redirect?to=http://localhost:{}/file.bin", port);
+        let status = finished(&listed, listed.start(&url(&elsewhere), None, None).id).await;
+        assert!(status.error.unwrap().contains("Host localhost is not an allowed download host"));
+
+        std::fs::remove_dir_all(public_only.directory()).unwrap();
+        std::fs::remove_dir_all(listed.directory()).unwrap();
+    }
+
+    #[test]
+    fn test_public_addresses_and_hosts() {
+        let public = |ip: &str| is_public(ip.parse().unwrap());
+        assert!(public("93.184.216.34") && public("2606:2800:220:1::1"));
+        for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0"] {
+            assert!(!public(ip), "{}", ip);
+        }
+        for ip in ["::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1", "64:ff9b::a9fe:a9fe"] {
+            assert!(!public(ip), "{}", ip);
+        }
+
+        assert!(host_matches("github.com", "github.com"));
+        assert!(!host_matches("github.com", "api.github.com"));
+        assert!(host_matches("*.github.com", "objects.github.com"));
+        assert!(!host_matches("*.github.com", "github.com") && !host_matches("*.github.com", "evilgithub.com"));
+    }
+
+    #[test]
+    fn test_checksum_and_file_name() {
+        assert!(Checksum::parse("md5:d41d8cd98f00b204e9800998ecf8427e").unwrap_err().contains("not supported"));
+        assert!(Checksum::parse("sha256:abc").unwrap_err().contains("64 hex digits"));
+        assert_eq!(Checksum::parse(&format!("SHA512:{}", "A".repeat(128))).unwrap().to_string(), format!("sha512:{}", "a".repeat(128)));
+
+        let name = |url: &str| file_name(&Url::parse(url).unwrap());
+        assert_eq!(name("https://example.com/releases/tool-1.0.tar.gz"), "tool-1.0.tar.gz");
+        assert_eq!(name("https://example.com/a%20b;rm"), "a_20b_rm");
+        assert_eq!(name("https://example.com/..%2F..%2Fetc"), "_2F.._2Fetc");
+        assert_eq!(name("https://example.com/"), "download");
+    }
+}
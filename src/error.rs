@@ -0,0 +1,157 @@
+use axum::http::StatusCode;
+use std::fmt;
+use std::time::Duration;
+
+/// Error returned when a request can't be processed
+#[derive(Debug)]
+pub enum ProcessError {
+    /// The request body is malformed or missing a required field
+    InvalidRequest(String),
+    /// No valid API key or bearer token was supplied
+    Unauthorized(String),
+    /// The caller isn't allowed to run the requested task
+    Forbidden(String),
+    /// The request is well-formed but its input is not acceptable, e.g. empty text
+    Validation(String),
+    /// No task is registered under the requested name
+    UnknownTask(String),
+    /// The requested resource, such as an action token, doesn't exist
+    NotFound(String),
+    /// The requested resource existed but has expired
+    Expired(String),
+    /// A single-use resource, such as an action token, was already used
+    AlreadyUsed(String),
+    /// The input is larger than the server accepts
+    PayloadTooLarge(String),
+    /// The caller is sending requests faster than its rate limit allows
+    RateLimited { message: String, retry_after: Duration },
+    /// The caller has used up its daily quota
+    QuotaExceeded { message: String, retry_after: Duration },
+    /// An upstream service such as the LLM provider failed or returned an error
+    Upstream(String),
+    /// An upstream service is overloaded or failing and shouldn't be called again until `retry_after`
+    Unavailable { message: String, retry_after: Duration },
+    /// An upstream service or task didn't answer in time
+    Timeout(String),
+    /// Any other failure while running a task
+    Internal(anyhow::Error),
+}
+
+impl ProcessError {
+    /// Stable machine-readable code sent as `error` in `ErrorResponse`
+    pub fn code(&self) -> &'static str {
+        match self {
+            ProcessError::InvalidRequest(_) => "invalid_request",
+            ProcessError::Unauthorized(_) => "unauthorized",
+            ProcessError::Forbidden(_) => "forbidden",
+            ProcessError::Validation(_) => "validation_failed",
+            ProcessError::UnknownTask(_) => "unknown_task",
+            ProcessError::NotFound(_) => "not_found",
+            ProcessError::Expired(_) => "expired",
+            ProcessError::AlreadyUsed(_) => "already_used",
+            ProcessError::PayloadTooLarge(_) => "payload_too_large",
+            ProcessError::RateLimited { .. } => "rate_limited",
+            ProcessError::QuotaExceeded { .. } => "quota_exceeded",
+            ProcessError::Upstream(_) => "upstream_failed",
+            ProcessError::Unavailable { .. } => "upstream_unavailable",
+            ProcessError::Timeout(_) => "timeout",
+            ProcessError::Internal(_) => "processing_failed",
+        }
+    }
+
+    /// HTTP status the error is reported with
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ProcessError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            ProcessError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ProcessError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ProcessError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ProcessError::UnknownTask(_) | ProcessError::NotFound(_) => StatusCode::NOT_FOUND,
+            ProcessError::Expired(_) => StatusCode::GONE,
+            ProcessError::AlreadyUsed(_) => StatusCode::CONFLICT,
+            ProcessError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ProcessError::RateLimited { .. } | ProcessError::QuotaExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ProcessError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            ProcessError::Unavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            ProcessError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ProcessError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// How long the caller should wait before retrying, sent as `Retry-After`
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ProcessError::RateLimited { retry_after, .. }
+            | ProcessError::QuotaExceeded { retry_after, .. }
+            | ProcessError::Unavailable { retry_after, .. } => Some(*retry_after),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessError::InvalidRequest(message) => write!(f, "{}", message),
+            ProcessError::Unauthorized(message) => write!(f, "{}", message),
+            ProcessError::Forbidden(message) => write!(f, "{}", message),
+            ProcessError::Validation(message) => write!(f, "{}", message),
+            ProcessError::UnknownTask(task) => write!(f, "Unsupported task: {}", task),
+            ProcessError::NotFound(message) => write!(f, "{}", message),
+            ProcessError::Expired(message) => write!(f, "{}", message),
+            ProcessError::AlreadyUsed(message) => write!(f, "{}", message),
+            ProcessError::PayloadTooLarge(message) => write!(f, "{}", message),
+            ProcessError::RateLimited { message, .. } => write!(f, "{}", message),
+            ProcessError::QuotaExceeded { message, .. } => write!(f, "{}", message),
+            ProcessError::Upstream(message) => write!(f, "Upstream request failed: {}", message),
+            ProcessError::Unavailable { message, .. } => write!(f, "Upstream unavailable: {}", message),
+            ProcessError::Timeout(message) => write!(f, "Timed out: {}", message),
+            ProcessError::Internal(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProcessError {}
+
+/// Recover a typed error raised inside a task, treating anything else as internal
+impl From<anyhow::Error> for ProcessError {
+    fn from(error: anyhow::Error) -> Self {
+        match error.downcast::<ProcessError>() {
+            Ok(error) => error,
+            Err(error) => ProcessError::Internal(error),
+        }
+    }
+}
+
+impl From<reqwest::Error> for ProcessError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            ProcessError::Timeout(error.to_string())
+        } else {
+            ProcessError::Upstream(error.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_error_survives_anyhow() {
+        let error = anyhow::Error::from(ProcessError::UnknownTask("missing".to_string()));
+        let error = ProcessError::from(error);
+
+        assert_eq!(error.code(), "unknown_task");
+        assert_eq!(error.status_code(), StatusCode::NOT_FOUND);
+        assert_eq!(error.to_string(), "Unsupported task: missing");
+    }
+
+    #[test]
+    fn test_untyped_error_is_internal() {
+        let error = ProcessError::from(anyhow::anyhow!("boom"));
+
+        assert_eq!(error.code(), "processing_failed");
+        assert_eq!(error.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,559 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::path::{Component, Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+use tracing::{info, warn};
+
+use crate::callbacks::{Callback, CallbackResult, CommandContext};
+use crate::config::ExecutorConfig;
+use crate::shell::{quote, ShellCommand};
+
+/// Arguments that would let an allowed program run other programs or write files
+const DENIED_ARGUMENTS: &[(&str, &[&str])] = &[
+    ("find", &["-exec", "-execdir", "-ok", "-okdir", "-delete", "-fprint", "-fprint0", "-fprintf", "-fls"]),
+    ("rg", &["--pre", "--pre-glob", "--hostname-bin"]),
+    ("git", &["--output", "--ext-diff", "--textconv"]),
+];
+
+/// Arguments that turn off `git diff`'s external diff and textconv drivers, which repository config can set
+const GIT_DIFF_SAFETY_ARGUMENTS: &[&str] = &["--no-ext-diff", "--no-textconv"];
+
+/// Characters that only mean something to a shell, which commands never run through
+const SHELL_METACHARACTERS: &[char] = &['|', '&', ';', '<', '>', '(', ')', '`', '$', '\n'];
+
+/// How a suggested command ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionStatus {
+    /// The command ran and exited; see `exit_code`
+    Completed,
+    /// The command was killed after running longer than the timeout
+    TimedOut,
+    /// The command isn't allowed and wasn't run
+    Rejected,
+    /// The command couldn't be started
+    Failed,
+}
+
+/// Outcome of one suggested command, returned in the executor's `CallbackResult.data`
+#[derive(Debug, Clone, Serialize)]
+pub struct Execution {
+    pub command: String,
+    pub status: ExecutionStatus,
+    /// Why the command was rejected or failed to start
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub stdout_truncated: bool,
+    pub stderr_truncated: bool,
+    pub duration_ms: u64,
+}
+
+impl Execution {
+    fn without_output(command: &str, status: ExecutionStatus, reason: Option<String>) -> Self {
+        Self {
+            command: command.to_string(),
+            status,
+            reason,
+            exit_code: None,
+            stdout: String::new(),
+            stderr: String::new(),
+            stdout_truncated: false,
+            stderr_truncated: false,
+            duration_ms: 0,
+        }
+    }
+}
+
+/// Runs allow-listed suggested commands inside a working directory, without a shell
+///
+/// Registered as the `executor` callback when `callbacks.executor.enabled` is set. It runs the
+/// first of a task's `suggested_commands` that passes validation and reports the others as
+/// rejected.
+pub struct SandboxExecutor {
+    root: PathBuf,
+    /// Each allowed command split into words, e.g. `["git", "diff"]`
+    allowed: Vec<Vec<String>>,
+    commands: Vec<String>,
+    timeout: Duration,
+    max_output_bytes: usize,
+}
+
+impl SandboxExecutor {
+    pub fn new(config: &ExecutorConfig) -> Result<Self> {
+        let root = config
+            .working_dir
+            .canonicalize()
+            .with_context(|| format!("Invalid executor working directory {}", config.working_dir.display()))?;
+        Ok(Self {
+            root,
+            allowed: config
+                .allowed_commands
+                .iter()
+                .map(|command| command.split_whitespace().map(str::to_string).collect())
+                .collect(),
+            commands: config.commands.clone(),
+            timeout: Duration::from_secs(config.timeout_secs),
+            max_output_bytes: config.max_output_bytes,
+        })
+    }
+
+    /// Split a command into its arguments and check it may run; `Err` holds the reason
+    pub fn validate(&self, command: &str) -> std::result::Result<Vec<String>, String> {
+        let args = split_command(command)?;
+        self.validate_args(&args)?;
+        Ok(args)
+    }
+
+    /// Check a command that's already split into arguments may run; `Err` holds the reason
+    pub fn validate_args(&self, args: &[String]) -> std::result::Result<(), String> {
+        let program = args.first().ok_or("Empty command")?;
+
+        if !self
+            .allowed
+            .iter()
+            .any(|allowed| args.len() >= allowed.len() && args[..allowed.len()] == allowed[..])
+        {
+            return Err(format!("'{}' is not an allowed command", program));
+        }
+
+        let denied = DENIED_ARGUMENTS
+            .iter()
+            .filter(|(denied_program, _)| denied_program == program)
+            .flat_map(|(_, flags)| flags.iter());
+        for flag in denied {
+            if let Some(arg) = args[1..]
+                .iter()
+                .find(|arg| *arg == flag || arg.starts_with(&format!("{}=", flag)))
+            {
+                return Err(format!("Argument '{}' is not allowed", arg));
+            }
+        }
+
+        for arg in &args[1..] {
+            let path = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with('-') => value,
+                _ if arg.starts_with('-') => continue,
+                _ => arg.as_str(),
+            };
+            if !self.is_confined(path) {
+                return Err(format!("Path '{}' is outside the working directory", path));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether a path argument stays inside the working directory, following symlinks that exist
+    fn is_confined(&self, path: &str) -> bool {
+        let path = Path::new(path);
+        let mut resolved = if path.is_absolute() { PathBuf::new() } else { self.root.clone() };
+        for component in path.components() {
+            match component {
+                Component::ParentDir => {
+                    resolved.pop();
+                }
+                Component::CurDir => {}
+                other => resolved.push(other),
+            }
+        }
+        let resolved = resolved.canonicalize().unwrap_or(resolved);
+        resolved.starts_with(&self.root)
+    }
+
+    /// Validate and run a command, capturing its output
+    pub async fn run(&self, command: &str) -> Execution {
+        match split_command(command) {
+            Ok(args) => self.run_validated(command, &args).await,
+            Err(reason) => Execution::without_output(command, ExecutionStatus::Rejected, Some(reason)),
+        }
+    }
+
+    /// Validate and run a command that's already split into arguments, so nothing is parsed again
+    pub async fn run_args(&self, args: &[String]) -> Execution {
+        let command = args.iter().map(|arg| quote(arg)).collect::<Vec<_>>().join(" ");
+        self.run_validated(&command, args).await
+    }
+
+    async fn run_validated(&self, command: &str, args: &[String]) -> Execution {
+        if let Err(reason) = self.validate_args(args) {
+            return Execution::without_output(command, ExecutionStatus::Rejected, Some(reason));
+        }
+        let args = disable_external_programs(args.to_vec());
+        info!("Executing suggested command: {:?}", args);
+
+        let mut process = Command::new(&args[0]);
+        process
+            .args(&args[1..])
+            .current_dir(&self.root)
+            .env_clear()
+            .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .env("GIT_PAGER", "cat")
+            .env("GIT_CONFIG_COUNT", "1")
+            .env("GIT_CONFIG_KEY_0", "core.fsmonitor")
+            .env("GIT_CONFIG_VALUE_0", "false")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let start_time = Instant::now();
+        let mut child = match process.spawn() {
+            Ok(child) => child,
+            Err(e) => return Execution::without_output(command, ExecutionStatus::Failed, Some(e.to_string())),
+        };
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+        let completed = tokio::time::timeout(self.timeout, async {
+            tokio::join!(
+                read_capped(stdout, self.max_output_bytes),
+                read_capped(stderr, self.max_output_bytes),
+                child.wait(),
+            )
+        })
+        .await;
+        let duration_ms = start_time.elapsed().as_millis() as u64;
+
+        match completed {
+            Ok(((stdout, stdout_truncated), (stderr, stderr_truncated), status)) => Execution {
+                command: command.to_string(),
+                status: ExecutionStatus::Completed,
+                reason: None,
+                exit_code: status.ok().and_then(|status| status.code()),
+                stdout: String::from_utf8_lossy(&stdout).into_owned(),
+                stderr: String::from_utf8_lossy(&stderr).into_owned(),
+                stdout_truncated,
+                stderr_truncated,
+                duration_ms,
+            },
+            Err(_) => {
+                warn!("Suggested command timed out after {:?}: {}", self.timeout, command);
+                if let Err(e) = child.kill().await {
+                    warn!("Failed to kill timed out command: {}", e);
+                }
+                Execution {
+                    duration_ms,
+                    ..Execution::without_output(
+                        command,
+                        ExecutionStatus::TimedOut,
+                        Some(format!("Killed after {} seconds", self.timeout.as_secs())),
+                    )
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Callback for SandboxExecutor {
+    fn name(&self) -> &str {
+        "executor"
+    }
+
+    fn supported_commands(&self) -> Option<Vec<String>> {
+        Some(self.commands.clone())
+    }
+
+    /// After the built-in callbacks, before webhooks
+    fn priority(&self) -> i32 {
+        10
+    }
+
+    /// The command's own timeout kills it first
+    fn timeout(&self) -> Option<Duration> {
+        Some(self.timeout + Duration::from_secs(5))
+    }
+
+    async fn handle(&self, context: &CommandContext) -> Result<CallbackResult> {
+        // The structured commands, so they're checked and run exactly like confirmed actions
+        let suggestions: Vec<ShellCommand> = context
+            .parsed_result
+            .get("commands")
+            .and_then(|commands| serde_json::from_value(commands.clone()).ok())
+            .ok_or_else(|| anyhow!("Task {} has no suggested commands", context.task))?;
+
+        let mut rejected = Vec::new();
+        for suggestion in &suggestions {
+            let execution = self.run_args(&suggestion.argv()).await;
+            let command = execution.command.clone();
+            if execution.status == ExecutionStatus::Rejected {
+                rejected.push(execution);
+                continue;
+            }
+
+            let success = execution.status == ExecutionStatus::Completed;
+            let message = match execution.exit_code {
+                Some(code) if success => format!("Ran `{}` (exit code {})", command, code),
+                _ => format!("Failed to run `{}`", command),
+            };
+            return Ok(CallbackResult {
+                success,
+                message,
+                data: Some(serde_json::json!({
+                    "action": "command_executed",
+                    "execution": execution,
+                    "rejected": rejected,
+                })),
+                execution_time_ms: 0,
+            });
+        }
+
+        Ok(CallbackResult {
+            success: false,
+            message: "None of the suggested commands is allowed".to_string(),
+            data: Some(serde_json::json!({
+                "action": "command_rejected",
+                "rejected": rejected,
+            })),
+            execution_time_ms: 0,
+        })
+    }
+}
+
+/// Split a command into arguments the way a POSIX shell would, rejecting anything that needs a shell
+pub fn split_command(command: &str) -> std::result::Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err("Unterminated single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => current.push(c),
+                            Some(c) => {
+                                current.push('\\');
+                                current.push(c);
+                            }
+                            None => return Err("Unterminated double quote".to_string()),
+                        },
+                        Some(c @ ('$' | '`')) => return Err(format!("Shell expansion '{}' is not allowed", c)),
+                        Some(c) => current.push(c),
+                        None => return Err("Unterminated double quote".to_string()),
+                    }
+                }
+            }
+            '\\' => {
+                in_arg = true;
+                current.push(chars.next().ok_or("Trailing backslash")?);
+            }
+            c if SHELL_METACHARACTERS.contains(&c) => {
+                return Err(format!("Shell operator '{}' is not allowed", c));
+            }
+            c if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            c => {
+                in_arg = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+/// Add the arguments that stop an allowed `git diff` from running programs named in the repository's config
+fn disable_external_programs(mut args: Vec<String>) -> Vec<String> {
+    if args.len() >= 2 && args[0] == "git" && args[1] == "diff" {
+        args.splice(2..2, GIT_DIFF_SAFETY_ARGUMENTS.iter().map(|arg| arg.to_string()));
+    }
+    args
+}
+
+/// Read up to `cap` bytes, then drain the rest so the process doesn't block on a full pipe
+async fn read_capped<R: AsyncRead + Unpin>(reader: Option<R>, cap: usize) -> (Vec<u8>, bool) {
+    let Some(reader) = reader else {
+        return (Vec::new(), false);
+    };
+    let mut output = Vec::new();
+    let mut limited = reader.take(cap as u64);
+    if let Err(e) = limited.read_to_end(&mut output).await {
+        warn!("Failed to read command output: {}", e);
+    }
+    let discarded = tokio::io::copy(&mut limited.into_inner(), &mut tokio::io::sink())
+        .await
+        .unwrap_or(0);
+    (output, discarded > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rustlm-executor-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src").join("main.rs"), "fn main() {}\n").unwrap();
+        dir
+    }
+
+    fn executor(dir: &Path, allowed: &[&str]) -> SandboxExecutor {
+        SandboxExecutor::new(&ExecutorConfig {
+            enabled: true,
+            working_dir: dir.to_path_buf(),
+            allowed_commands: allowed.iter().map(|c| c.to_string()).collect(),
+            timeout_secs: 1,
+            ..ExecutorConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_split_command() {
+        assert_eq!(
+            split_command(r#"find . -name 'my file.rs' -o -name "a\"b""#).unwrap(),
+            vec!["find", ".", "-name", "my file.rs", "-o", "-name", "a\"b"]
+        );
+        assert_eq!(split_command("rg ''").unwrap(), vec!["rg", ""]);
+        assert!(split_command("find . | xargs rm").is_err());
+        assert!(split_command("rg \"$(whoami)\"").is_err());
+        assert!(split_command("rg 'unterminated").is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_disallowed_commands() {
+        let dir = workspace();
+        let executor = executor(&dir, &["find", "git diff"]);
+
+        assert!(executor.validate("find . -name '*.rs'").is_ok());
+        assert!(executor.validate("git diff HEAD~1..HEAD").is_ok());
+        assert!(executor.validate("git checkout main").unwrap_err().contains("not an allowed command"));
+        assert!(executor.validate("locate main.rs").unwrap_err().contains("not an allowed command"));
+        assert!(executor.validate("find . -exec rm {} +").unwrap_err().contains("-exec"));
+        assert!(executor.validate("git diff --output=/tmp/x").unwrap_err().contains("--output"));
+        assert!(executor.validate("git diff --ext-diff").unwrap_err().contains("--ext-diff"));
+        assert!(executor.validate("find .. -name x").unwrap_err().contains("outside"));
+        assert!(executor.validate("find /etc -name passwd").unwrap_err().contains("outside"));
+        assert!(executor.validate("find src/../.. -name x").unwrap_err().contains("outside"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_git_diff_never_runs_external_programs() {
+        let args = |command: &str| disable_external_programs(split_command(command).unwrap());
+        assert_eq!(
+            args("git diff HEAD~1 -- src"),
+            vec!["git", "diff", "--no-ext-diff", "--no-textconv", "HEAD~1", "--", "src"]
+        );
+        assert_eq!(args("git log -p"), vec!["git", "log", "-p"]);
+        assert_eq!(args("rg diff"), vec!["rg", "diff"]);
+
+        let dir = workspace();
+        let executor = executor(&dir, &["rg"]);
+        assert!(executor.validate("rg --hostname-bin=/bin/sh x").unwrap_err().contains("--hostname-bin"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_captures_output() {
+        let dir = workspace();
+        let executor = executor(&dir, &["find"]);
+
+        let execution = executor.run("find . -name 'main.rs'").await;
+        assert_eq!(execution.status, ExecutionStatus::Completed);
+        assert_eq!(execution.exit_code, Some(0));
+        assert_eq!(execution.stdout.trim(), "./src/main.rs");
+        assert!(!execution.stdout_truncated);
+
+        let executor = SandboxExecutor {
+            max_output_bytes: 4,
+            ..executor
+        };
+        let execution = executor.run("find .").await;
+        assert_eq!(execution.stdout.len(), 4);
+        assert!(execution.stdout_truncated);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_args_checks_arguments_without_parsing_them() {
+        let dir = workspace();
+        std::fs::write(dir.join("src").join("a $b|c.rs"), "").unwrap();
+        let executor = executor(&dir, &["find"]);
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+        let execution = executor.run_args(&args(&["find", ".", "-name", "a $b|c.rs"])).await;
+        assert_eq!(execution.status, ExecutionStatus::Completed);
+        assert_eq!(execution.stdout.trim(), "./src/a $b|c.rs");
+        assert_eq!(execution.command, "find . -name 'a $b|c.rs'");
+
+        let execution = executor.run_args(&args(&["find", ".", "-delete"])).await;
+        assert_eq!(execution.status, ExecutionStatus::Rejected);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_kills_commands_after_timeout() {
+        let dir = workspace();
+        let executor = executor(&dir, &["sleep"]);
+
+        let start = Instant::now();
+        let execution = executor.run("sleep 10").await;
+        assert_eq!(execution.status, ExecutionStatus::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_callback_runs_first_allowed_suggestion() {
+        let dir = workspace();
+        std::fs::write(dir.join("src").join("it's a.rs"), "").unwrap();
+        let executor = executor(&dir, &["find"]);
+        let commands = [
+            ShellCommand::new("locate").arg("it's a.rs"),
+            ShellCommand::new("find").args([".", "-name", "it's a.rs"]),
+            ShellCommand::new("find").args([".", "-iname", "it's a.rs"]),
+        ];
+        let context = CommandContext {
+            command: "find_file".to_string(),
+            task: "find_file".to_string(),
+            input_text: "it's a.rs".to_string(),
+            parsed_result: serde_json::json!({
+                "commands": commands,
+                "suggested_commands": commands.iter().map(ShellCommand::render).collect::<Vec<_>>(),
+            }),
+            confidence: None,
+            timestamp: chrono::Utc::now(),
+            session_id: None,
+            previous: None,
+        };
+
+        let result = executor.handle(&context).await.unwrap();
+        assert!(result.success);
+        let data = result.data.unwrap();
+        assert_eq!(data["execution"]["command"], r"find . -name 'it'\''s a.rs'");
+        assert_eq!(data["execution"]["exit_code"], 0);
+        assert_eq!(data["execution"]["stdout"].as_str().unwrap().trim(), "./src/it's a.rs");
+        assert_eq!(data["rejected"][0]["status"], "rejected");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,333 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+
+use crate::config::GitConfig;
+use crate::results::{DiffStats, FileDiffStat, RefInfo, RefKind};
+
+/// A local Git repository inspected with the `git` program, without a shell
+#[derive(Debug, Clone)]
+pub struct GitRepository {
+    path: PathBuf,
+    timeout: Duration,
+    max_close_matches: usize,
+}
+
+/// Branch, remote branch and tag names of a repository
+#[derive(Debug, Default)]
+struct Refs {
+    branches: Vec<String>,
+    /// Remote branches as `origin/name`
+    remote_branches: Vec<String>,
+    tags: Vec<String>,
+}
+
+impl GitRepository {
+    pub fn new(config: &GitConfig) -> Result<Self> {
+        let path = config
+            .repository
+            .canonicalize()
+            .with_context(|| format!("Invalid git repository {}", config.repository.display()))?;
+        let status = std::process::Command::new("git")
+            .arg("-C")
+            .arg(&path)
+            .args(["rev-parse", "--git-dir"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .context("Failed to run git")?;
+        if !status.success() {
+            bail!("{} is not a git repository", path.display());
+        }
+
+        Ok(Self {
+            path,
+            timeout: Duration::from_secs(config.timeout_secs),
+            max_close_matches: config.max_close_matches,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Run `git` in the repository and return its output; callers pass only validated names
+    async fn git(&self, args: &[&str]) -> Result<String> {
+        let mut command = Command::new("git");
+        command
+            .arg("-C")
+            .arg(&self.path)
+            .args(["-c", "core.quotepath=off"])
+            .args(args)
+            .env_remove("GIT_DIR")
+            .env_remove("GIT_WORK_TREE")
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+
+        let output = tokio::time::timeout(self.timeout, command.output())
+            .await
+            .map_err(|_| anyhow!("git {} timed out after {} seconds", args[0], self.timeout.as_secs()))?
+            .context("Failed to run git")?;
+        if !output.status.success() {
+            bail!("git {} failed: {}", args[0], String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    async fn refs(&self) -> Result<Refs> {
+        let output = self
+            .git(&["for-each-ref", "--format=%(refname)", "refs/heads", "refs/remotes", "refs/tags"])
+            .await?;
+        let mut refs = Refs::default();
+        for name in output.lines() {
+            if let Some(branch) = name.strip_prefix("refs/heads/") {
+                refs.branches.push(branch.to_string());
+            } else if let Some(tag) = name.strip_prefix("refs/tags/") {
+                refs.tags.push(tag.to_string());
+            } else if let Some(remote) = name.strip_prefix("refs/remotes/") {
+                // `origin/HEAD` points at another remote branch
+                if !remote.ends_with("/HEAD") {
+                    refs.remote_branches.push(remote.to_string());
+                }
+            }
+        }
+        Ok(refs)
+    }
+
+    /// The commit a name or abbreviated hash points to, if any
+    async fn commit(&self, name: &str) -> Option<String> {
+        let output = self
+            .git(&["rev-parse", "--verify", "--quiet", "--end-of-options", &format!("{}^{{commit}}", name)])
+            .await
+            .ok()?;
+        Some(output.trim().to_string())
+    }
+
+    /// Whether `name` is a branch, remote branch, tag or commit, with close matches when it's none of them
+    pub async fn resolve(&self, name: &str) -> Result<RefInfo> {
+        let refs = self.refs().await?;
+        let remote = refs
+            .remote_branches
+            .iter()
+            .find(|remote| remote.split_once('/').is_some_and(|(_, branch)| branch == name));
+
+        let kind = if refs.branches.iter().any(|branch| branch == name) {
+            RefKind::Branch
+        } else if refs.tags.iter().any(|tag| tag == name) {
+            RefKind::Tag
+        } else if remote.is_some() || refs.remote_branches.iter().any(|remote| remote == name) {
+            RefKind::RemoteBranch
+        } else if self.commit(name).await.is_some() {
+            RefKind::Commit
+        } else {
+            RefKind::Unknown
+        };
+
+        let commit = match kind {
+            RefKind::Unknown => None,
+            _ => self.commit(remote.map_or(name, String::as_str)).await,
+        };
+        let close_matches = if kind == RefKind::Unknown {
+            let candidates = refs.branches.iter().chain(&refs.tags).chain(&refs.remote_branches);
+            close_matches(name, candidates, self.max_close_matches)
+        } else {
+            Vec::new()
+        };
+
+        Ok(RefInfo {
+            name: name.to_string(),
+            kind,
+            commit,
+            remote: remote.cloned(),
+            close_matches,
+        })
+    }
+
+    /// Files changed, insertions and deletions for `git diff` with the given commits and paths
+    pub async fn diff_stats(&self, commits: &[&str], paths: &[&str]) -> Result<DiffStats> {
+        let mut args = vec!["diff", "--numstat"];
+        args.extend(commits);
+        args.push("--");
+        args.extend(paths);
+        let output = self.git(&args).await?;
+
+        let mut stats = DiffStats::default();
+        for line in output.lines() {
+            let mut fields = line.splitn(3, '\t');
+            let (Some(insertions), Some(deletions), Some(path)) = (fields.next(), fields.next(), fields.next()) else {
+                continue;
+            };
+            // Binary files are reported as `-`
+            let file = FileDiffStat {
+                path: path.to_string(),
+                insertions: insertions.parse().ok(),
+                deletions: deletions.parse().ok(),
+            };
+            stats.insertions += file.insertions.unwrap_or(0);
+            stats.deletions += file.deletions.unwrap_or(0);
+            stats.files.push(file);
+        }
+        stats.files_changed = stats.files.len();
+        Ok(stats)
+    }
+
+    /// Split diff targets into commits and paths, as `git diff` would read them
+    pub async fn classify_diff_targets<'a>(&self, targets: &[&'a str]) -> (Vec<&'a str>, Vec<&'a str>) {
+        let mut commits = Vec::new();
+        let mut paths = Vec::new();
+        for &target in targets {
+            // Commits can only come first, and `git diff` compares at most two
+            if paths.is_empty() && commits.len() < 2 && self.commit(target).await.is_some() {
+                commits.push(target);
+            } else {
+                paths.push(target);
+            }
+        }
+        (commits, paths)
+    }
+}
+
+/// Names within a small edit distance of `name`, or containing it, closest first
+fn close_matches<'a>(name: &str, candidates: impl Iterator<Item = &'a String>, limit: usize) -> Vec<String> {
+    let name = name.to_lowercase();
+    let max_distance = (name.chars().count() / 3).max(2);
+    let mut scored: Vec<(usize, &String)> = candidates
+        .filter_map(|candidate| {
+            let lowercase = candidate.to_lowercase();
+            let distance = edit_distance(&name, &lowercase);
+            if distance <= max_distance {
+                Some((distance, candidate))
+            } else if lowercase.contains(&name) {
+                Some((max_distance + 1, candidate))
+            } else {
+                None
+            }
+        })
+        .collect();
+    scored.sort();
+    scored.dedup_by(|a, b| a.1 == b.1);
+    scored.into_iter().take(limit).map(|(_, candidate)| candidate.clone()).collect()
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn git(repo: &Path, args: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com", "-c", "commit.gpgsign=false"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    /// A repository with two commits on `main`, a `feature/login` branch, a `v1.0` tag and an `origin/release` remote branch
+    pub(crate) fn repository() -> PathBuf {
+        let repo = std::env::temp_dir().join(format!("rustlm-git-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&repo).unwrap();
+        git(&repo, &["init", "--quiet", "--initial-branch=main"]);
+        std::fs::write(repo.join("README.md"), "one\ntwo\n").unwrap();
+        git(&repo, &["add", "."]);
+        git(&repo, &["commit", "--quiet", "-m", "First"]);
+        git(&repo, &["tag", "v1.0"]);
+        std::fs::write(repo.join("README.md"), "one\n2\nthree\n").unwrap();
+        std::fs::write(repo.join("logo.png"), b"\x89PNG\0binary").unwrap();
+        git(&repo, &["add", "."]);
+        git(&repo, &["commit", "--quiet", "-m", "Second"]);
+        git(&repo, &["branch", "feature/login"]);
+        let head = git(&repo, &["rev-parse", "HEAD"]);
+        git(&repo, &["update-ref", "refs/remotes/origin/release", &head]);
+        repo
+    }
+
+    fn open(repo: &Path) -> GitRepository {
+        GitRepository::new(&GitConfig {
+            enabled: true,
+            repository: repo.to_path_buf(),
+            ..GitConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_resolve_refs() {
+        let path = repository();
+        let repo = open(&path);
+        let head = git(&path, &["rev-parse", "HEAD"]);
+
+        let branch = repo.resolve("feature/login").await.unwrap();
+        assert_eq!(branch.kind, RefKind::Branch);
+        assert_eq!(branch.commit.as_deref(), Some(head.as_str()));
+        assert_eq!(repo.resolve("v1.0").await.unwrap().kind, RefKind::Tag);
+
+        let remote = repo.resolve("release").await.unwrap();
+        assert_eq!(remote.kind, RefKind::RemoteBranch);
+        assert_eq!(remote.remote.as_deref(), Some("origin/release"));
+
+        let commit = repo.resolve(&head[..7]).await.unwrap();
+        assert_eq!(commit.kind, RefKind::Commit);
+        assert_eq!(commit.commit, Some(head));
+
+        let typo = repo.resolve("feature/logn").await.unwrap();
+        assert_eq!(typo.kind, RefKind::Unknown);
+        assert_eq!(typo.close_matches, vec!["feature/login"]);
+        assert!(typo.commit.is_none());
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_diff_stats() {
+        let path = repository();
+        let repo = open(&path);
+
+        let (commits, paths) = repo.classify_diff_targets(&["v1.0", "main", "README.md"]).await;
+        assert_eq!((commits.as_slice(), paths.as_slice()), (&["v1.0", "main"][..], &["README.md"][..]));
+
+        let stats = repo.diff_stats(&["v1.0", "main"], &[]).await.unwrap();
+        assert_eq!((stats.files_changed, stats.insertions, stats.deletions), (2, 2, 1));
+        let logo = stats.files.iter().find(|file| file.path == "logo.png").unwrap();
+        assert_eq!((logo.insertions, logo.deletions), (None, None));
+
+        // The working tree has no changes
+        let stats = repo.diff_stats(&[], &[]).await.unwrap();
+        assert_eq!(stats.files_changed, 0);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_close_matches() {
+        let names: Vec<String> = ["main", "master", "develop", "feature/main-menu"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(close_matches("mian", names.iter(), 5), vec!["main"]);
+        assert_eq!(close_matches("main", names.iter(), 5), vec!["main", "feature/main-menu"]);
+        assert_eq!(close_matches("main", names.iter(), 1), vec!["main"]);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert!(GitRepository::new(&GitConfig {
+            repository: std::env::temp_dir(),
+            ..GitConfig::default()
+        })
+        .is_err());
+    }
+}
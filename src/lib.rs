@@ -0,0 +1,22 @@
+pub mod actions;
+pub mod auth;
+pub mod callbacks;
+pub mod catalog;
+pub mod commands;
+pub mod config;
+pub mod download;
+pub mod error;
+pub mod executor;
+pub mod git;
+pub mod llm;
+pub mod metrics;
+pub mod nlp;
+pub mod platform;
+pub mod ratelimit;
+pub mod results;
+pub mod search;
+pub mod server;
+pub mod sessions;
+pub mod shell;
+pub mod tasks;
+pub mod udp_broadcast;
@@ -0,0 +1,901 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::error::ProcessError;
+use crate::metrics::metrics;
+
+/// API version used for Azure OpenAI chat completions
+pub const AZURE_OPENAI_API_VERSION: &str = "2024-02-15-preview";
+
+/// Base URL of the OpenAI API
+pub const OPENAI_ENDPOINT: &str = "https://api.openai.com/v1";
+
+/// Base URL of a local OpenAI-compatible server; Ollama's default
+pub const LOCAL_LLM_ENDPOINT: &str = "http://localhost:11434/v1";
+
+/// Placeholder key used when no API key is set
+pub const PLACEHOLDER_API_KEY: &str = "your-api-key-here";
+
+/// How long to wait for a chat completion (or, when streaming, for the first response)
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Characters of an error response body kept in the error message
+const MAX_ERROR_BODY_CHARS: usize = 500;
+
+static SHARED_CLIENT: LazyLock<Arc<LlmClient>> = LazyLock::new(|| Arc::new(LlmClient::new(LlmClientOptions::default())));
+
+/// Who a chat message is from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+}
+
+/// A message of a chat completion request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn system(content: &str) -> Self {
+        Self {
+            role: ChatRole::System,
+            content: content.to_string(),
+        }
+    }
+
+    pub fn user(content: &str) -> Self {
+        Self {
+            role: ChatRole::User,
+            content: content.to_string(),
+        }
+    }
+
+    pub fn assistant(content: &str) -> Self {
+        Self {
+            role: ChatRole::Assistant,
+            content: content.to_string(),
+        }
+    }
+}
+
+/// How an `LlmClient` connects, retries and stops calling a failing provider
+#[derive(Debug, Clone, PartialEq)]
+pub struct LlmClientOptions {
+    pub connect_timeout: Duration,
+    /// Retries of a request that got a 429 or 5xx response, timed out or couldn't connect
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled for each further one; a `Retry-After` header wins
+    pub retry_backoff: Duration,
+    /// Longest wait before a retry; asked to wait longer, the request fails instead
+    pub max_retry_delay: Duration,
+    /// Consecutive failed requests after which the provider isn't called for `circuit_reset`
+    pub circuit_failure_threshold: u32,
+    pub circuit_reset: Duration,
+}
+
+impl Default for LlmClientOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            max_retries: 2,
+            retry_backoff: Duration::from_millis(500),
+            max_retry_delay: Duration::from_secs(30),
+            circuit_failure_threshold: 5,
+            circuit_reset: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CircuitState {
+    /// Requests go through; `failures` counts consecutive failed ones
+    Closed { failures: u32 },
+    /// Requests fail fast until `until`, when a single trial request is let through
+    Open { until: Instant },
+}
+
+/// HTTP client for a provider's requests, retrying transient failures and failing fast while the
+/// provider is down
+#[derive(Debug)]
+pub struct LlmClient {
+    http: reqwest::Client,
+    options: LlmClientOptions,
+    circuit: Mutex<CircuitState>,
+}
+
+impl LlmClient {
+    pub fn new(options: LlmClientOptions) -> Self {
+        let http = reqwest::Client::builder()
+            .connect_timeout(options.connect_timeout)
+            .build()
+            .unwrap_or_default();
+        Self {
+            http,
+            options,
+            circuit: Mutex::new(CircuitState::Closed { failures: 0 }),
+        }
+    }
+
+    /// The client used by providers that weren't given their own
+    pub fn shared() -> Arc<Self> {
+        SHARED_CLIENT.clone()
+    }
+
+    /// Send the request built by `request`, retrying 429 and 5xx responses, timeouts and
+    /// connection failures
+    ///
+    /// `timeout` bounds each attempt until the response arrives. Other error responses and
+    /// request errors are returned as upstream errors right away.
+    async fn send(
+        &self,
+        provider: &str,
+        timeout: Duration,
+        request: impl Fn(&reqwest::Client) -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, ProcessError> {
+        self.allow(provider)?;
+
+        let mut attempt = 0;
+        loop {
+            let (error, retry_after) = match tokio::time::timeout(timeout, request(&self.http).send()).await {
+                Ok(Ok(response)) if response.status().is_success() => {
+                    self.record(provider, true);
+                    return Ok(response);
+                }
+                Ok(Ok(response)) => {
+                    let status = response.status();
+                    let retry_after = parse_retry_after(response.headers());
+                    let error = ProcessError::Upstream(response_error(provider, response).await);
+                    if status != reqwest::StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+                        // The provider is up but rejected the request, e.g. for a bad key
+                        self.record(provider, true);
+                        return Err(error);
+                    }
+                    (error, retry_after)
+                }
+                Ok(Err(e)) if e.is_timeout() || e.is_connect() => (ProcessError::from(e), None),
+                // Nothing reached the provider, e.g. an invalid URL, so it says nothing about its health
+                Ok(Err(e)) => return Err(ProcessError::from(e)),
+                Err(_) => (ProcessError::Timeout(format!("{} did not respond within {}s", provider, timeout.as_secs())), None),
+            };
+
+            let delay = retry_after.unwrap_or_else(|| self.backoff(attempt));
+            if attempt >= self.options.max_retries || delay > self.options.max_retry_delay {
+                self.record(provider, false);
+                return Err(match (error, retry_after) {
+                    (ProcessError::Upstream(message), Some(retry_after)) => {
+                        ProcessError::Unavailable { message, retry_after }
+                    }
+                    (error, _) => error,
+                });
+            }
+            attempt += 1;
+            warn!(
+                "Retrying {} request in {}ms ({} of {}): {}",
+                provider,
+                delay.as_millis(),
+                attempt,
+                self.options.max_retries,
+                error
+            );
+            metrics().observe_llm_retry(error.code());
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.options
+            .retry_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.options.max_retry_delay)
+    }
+
+    /// Fail fast while the circuit is open; once it's been open for `circuit_reset`, let one
+    /// trial request through and keep failing the others until it's answered
+    fn allow(&self, provider: &str) -> Result<(), ProcessError> {
+        let now = Instant::now();
+        let mut circuit = self.circuit.lock().unwrap_or_else(|e| e.into_inner());
+        match *circuit {
+            CircuitState::Closed { .. } => Ok(()),
+            CircuitState::Open { until } if until <= now => {
+                *circuit = CircuitState::Open {
+                    until: now + self.options.circuit_reset,
+                };
+                Ok(())
+            }
+            CircuitState::Open { until } => Err(ProcessError::Unavailable {
+                message: format!("{} is failing, not calling it until it recovers", provider),
+                retry_after: until - now,
+            }),
+        }
+    }
+
+    fn record(&self, provider: &str, success: bool) {
+        let mut circuit = self.circuit.lock().unwrap_or_else(|e| e.into_inner());
+        *circuit = match (*circuit, success) {
+            (_, true) => CircuitState::Closed { failures: 0 },
+            (CircuitState::Closed { failures }, false) if failures + 1 < self.options.circuit_failure_threshold => {
+                CircuitState::Closed { failures: failures + 1 }
+            }
+            _ => {
+                warn!(
+                    "{} failed {} times in a row, failing fast for {}s",
+                    provider,
+                    self.options.circuit_failure_threshold,
+                    self.options.circuit_reset.as_secs()
+                );
+                CircuitState::Open {
+                    until: Instant::now() + self.options.circuit_reset,
+                }
+            }
+        };
+    }
+}
+
+/// A chat completion API that `ask_ai` sends questions to
+///
+/// Implementations describe how to reach an OpenAI-style chat completions endpoint; the provided
+/// methods send the requests and parse the answers. Providers with another wire format override
+/// `chat_completion` and `stream_chat_completion`.
+#[async_trait]
+pub trait LlmProvider: fmt::Debug + Send + Sync {
+    /// Reported as the `source` of answers, e.g. `azure_openai`
+    fn name(&self) -> &str;
+
+    /// Whether the provider can be called, e.g. because an API key was set
+    fn is_configured(&self) -> bool;
+
+    fn chat_completions_url(&self) -> String;
+
+    /// Header authenticating requests, as name and value; `None` when no key is needed
+    fn auth_header(&self) -> Option<(&'static str, String)>;
+
+    /// Model named in the request body; Azure picks it by the deployment in the URL instead
+    fn model(&self) -> Option<&str> {
+        None
+    }
+
+    /// How long to wait for an answer; when streaming, for the first event and between events
+    fn timeout(&self) -> Duration;
+
+    /// Client the provided methods send requests with
+    fn client(&self) -> &LlmClient {
+        &SHARED_CLIENT
+    }
+
+    /// What to set up when the provider isn't configured
+    fn setup_instructions(&self) -> String;
+
+    /// Send a conversation ending in a question and wait for the full answer
+    async fn chat_completion(&self, messages: &[ChatMessage]) -> Result<String, ProcessError> {
+        let start_time = std::time::Instant::now();
+        let answer = request_completion(self, messages).await;
+        metrics().observe_llm_request("complete", start_time.elapsed(), answer.as_ref().err());
+        answer
+    }
+
+    /// Send a conversation using the streaming API, yielding content deltas as they arrive
+    async fn stream_chat_completion(
+        &self,
+        messages: &[ChatMessage],
+    ) -> Result<BoxStream<'static, Result<String>>, ProcessError> {
+        // The stream as a whole may take longer than the timeout, but each wait for it may not
+        let start_time = Instant::now();
+        let response = self
+            .client()
+            .send(self.name(), self.timeout(), |http| chat_request(http, self, messages, true))
+            .await;
+        metrics().observe_llm_request("stream", start_time.elapsed(), response.as_ref().err());
+        let response = response?;
+
+        let state = SseState {
+            bytes: response.bytes_stream().boxed(),
+            buffer: Vec::new(),
+            pending: VecDeque::new(),
+            finished: false,
+            provider: self.name().to_string(),
+            idle_timeout: self.timeout(),
+        };
+
+        Ok(stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(delta) = state.pending.pop_front() {
+                    return Some((delta, state));
+                }
+                if state.finished {
+                    return None;
+                }
+
+                let Ok(next) = tokio::time::timeout(state.idle_timeout, state.bytes.next()).await else {
+                    state.finished = true;
+                    let error = ProcessError::Timeout(format!("{} stopped sending the answer", state.provider));
+                    return Some((Err(error.into()), state));
+                };
+                match next {
+                    Some(Ok(chunk)) => {
+                        state.buffer.extend_from_slice(&chunk);
+                        state.drain_lines();
+                    }
+                    Some(Err(e)) => {
+                        state.finished = true;
+                        return Some((Err(ProcessError::from(e).into()), state));
+                    }
+                    None => {
+                        // Handle a final event that wasn't newline-terminated
+                        state.buffer.push(b'\n');
+                        state.drain_lines();
+                        state.finished = true;
+                    }
+                }
+            }
+        })
+        .boxed())
+    }
+}
+
+/// Connection settings for an Azure OpenAI deployment
+#[derive(Debug, Clone)]
+pub struct AzureOpenAiConfig {
+    pub endpoint: String,
+    pub api_key: String,
+    pub deployment: String,
+    pub timeout: Duration,
+    client: Arc<LlmClient>,
+}
+
+impl AzureOpenAiConfig {
+    pub fn new(endpoint: &str, api_key: &str, deployment: &str) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            deployment: deployment.to_string(),
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+            client: LlmClient::shared(),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send requests with `client` instead of the shared one, e.g. for its own circuit breaker
+    pub fn with_client(mut self, client: Arc<LlmClient>) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Example settings used until a real deployment is configured
+    pub fn placeholder() -> Self {
+        Self::new("https://your-resource.openai.azure.com", PLACEHOLDER_API_KEY, "gpt-35-turbo")
+    }
+
+    /// Read the configuration from the `AZURE_OPENAI_*` environment variables
+    pub fn from_env() -> Self {
+        let defaults = Self::placeholder();
+        let endpoint = std::env::var("AZURE_OPENAI_ENDPOINT").unwrap_or(defaults.endpoint);
+        let api_key = std::env::var("AZURE_OPENAI_API_KEY").unwrap_or(defaults.api_key);
+        let deployment = std::env::var("AZURE_OPENAI_DEPLOYMENT").unwrap_or(defaults.deployment);
+        Self::new(&endpoint, &api_key, &deployment)
+    }
+}
+
+impl LlmProvider for AzureOpenAiConfig {
+    fn name(&self) -> &str {
+        "azure_openai"
+    }
+
+    /// Whether a real API key has been provided
+    fn is_configured(&self) -> bool {
+        self.api_key != PLACEHOLDER_API_KEY
+    }
+
+    /// URL of the chat completions endpoint for this deployment
+    fn chat_completions_url(&self) -> String {
+        format!(
+            "{}/openai/deployments/{}/chat/completions?api-version={}",
+            self.endpoint, self.deployment, AZURE_OPENAI_API_VERSION
+        )
+    }
+
+    fn auth_header(&self) -> Option<(&'static str, String)> {
+        Some(("api-key", self.api_key.clone()))
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn client(&self) -> &LlmClient {
+        &self.client
+    }
+
+    fn setup_instructions(&self) -> String {
+        "To use Azure OpenAI, set these environment variables:\nexport AZURE_OPENAI_ENDPOINT=https://your-resource.openai.azure.com\nexport AZURE_OPENAI_API_KEY=your-api-key\nexport AZURE_OPENAI_DEPLOYMENT=gpt-35-turbo".to_string()
+    }
+}
+
+/// Connection settings for the OpenAI chat API, or a hosted service implementing it
+#[derive(Debug, Clone)]
+pub struct OpenAiConfig {
+    /// Base URL the `/chat/completions` path is appended to
+    pub endpoint: String,
+    pub api_key: String,
+    pub model: String,
+    pub timeout: Duration,
+    client: Arc<LlmClient>,
+}
+
+impl OpenAiConfig {
+    pub fn new(endpoint: &str, api_key: &str, model: &str) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+            client: LlmClient::shared(),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_client(mut self, client: Arc<LlmClient>) -> Self {
+        self.client = client;
+        self
+    }
+}
+
+impl LlmProvider for OpenAiConfig {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    fn is_configured(&self) -> bool {
+        !self.api_key.is_empty() && self.api_key != PLACEHOLDER_API_KEY
+    }
+
+    fn chat_completions_url(&self) -> String {
+        format!("{}/chat/completions", self.endpoint)
+    }
+
+    fn auth_header(&self) -> Option<(&'static str, String)> {
+        Some(("Authorization", format!("Bearer {}", self.api_key)))
+    }
+
+    fn model(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn client(&self) -> &LlmClient {
+        &self.client
+    }
+
+    fn setup_instructions(&self) -> String {
+        "To use the OpenAI API, set llm.api_key in the config file or export RUSTLM_LLM_API_KEY=your-api-key".to_string()
+    }
+}
+
+/// Connection settings for a local server implementing the OpenAI chat API, such as
+/// llama.cpp's `llama-server` or Ollama
+#[derive(Debug, Clone)]
+pub struct LocalLlmConfig {
+    /// Base URL the `/chat/completions` path is appended to
+    pub endpoint: String,
+    /// Model to ask for; servers that only serve one model may ignore it
+    pub model: Option<String>,
+    /// Sent as a bearer token when the server requires one
+    pub api_key: Option<String>,
+    pub timeout: Duration,
+    client: Arc<LlmClient>,
+}
+
+impl LocalLlmConfig {
+    pub fn new(endpoint: &str) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            model: None,
+            api_key: None,
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+            client: LlmClient::shared(),
+        }
+    }
+
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = Some(model.to_string());
+        self
+    }
+
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_client(mut self, client: Arc<LlmClient>) -> Self {
+        self.client = client;
+        self
+    }
+}
+
+impl LlmProvider for LocalLlmConfig {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    /// Local servers need no key, so they're always configured
+    fn is_configured(&self) -> bool {
+        true
+    }
+
+    fn chat_completions_url(&self) -> String {
+        format!("{}/chat/completions", self.endpoint)
+    }
+
+    fn auth_header(&self) -> Option<(&'static str, String)> {
+        let api_key = self.api_key.as_ref()?;
+        Some(("Authorization", format!("Bearer {}", api_key)))
+    }
+
+    fn model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn client(&self) -> &LlmClient {
+        &self.client
+    }
+
+    fn setup_instructions(&self) -> String {
+        format!("Start a server implementing the OpenAI chat API at {}", self.endpoint)
+    }
+}
+
+/// Body of a chat completion request for `messages`
+pub fn request_body(model: Option<&str>, messages: &[ChatMessage], stream: bool) -> serde_json::Value {
+    let mut body = serde_json::json!({
+        "messages": messages,
+        "max_tokens": 1000,
+        "temperature": 0.7
+    });
+    if let Some(model) = model {
+        body["model"] = serde_json::Value::String(model.to_string());
+    }
+    if stream {
+        body["stream"] = serde_json::Value::Bool(true);
+    }
+    body
+}
+
+fn chat_request<P: LlmProvider + ?Sized>(
+    http: &reqwest::Client,
+    provider: &P,
+    messages: &[ChatMessage],
+    stream: bool,
+) -> reqwest::RequestBuilder {
+    let mut request = http
+        .post(provider.chat_completions_url())
+        .header("Content-Type", "application/json")
+        .json(&request_body(provider.model(), messages, stream));
+    if let Some((name, value)) = provider.auth_header() {
+        request = request.header(name, value);
+    }
+    request
+}
+
+async fn request_completion<P: LlmProvider + ?Sized>(provider: &P, messages: &[ChatMessage]) -> Result<String, ProcessError> {
+    // The request timeout also covers reading the body
+    let timeout = provider.timeout();
+    let response = provider
+        .client()
+        .send(provider.name(), timeout, |http| chat_request(http, provider, messages, false).timeout(timeout))
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+
+    response["choices"][0]["message"]["content"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| {
+            ProcessError::Upstream(format!(
+                "{} returned no answer: {}",
+                provider.name(),
+                truncate_body(&response.to_string())
+            ))
+        })
+}
+
+/// Describe an error response, preferring the message of an OpenAI-style error body
+async fn response_error(provider: &str, response: reqwest::Response) -> String {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    let detail = serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|body| body["error"]["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| truncate_body(&body));
+    format!("{} returned {}: {}", provider, status, detail)
+}
+
+fn truncate_body(body: &str) -> String {
+    match body.char_indices().nth(MAX_ERROR_BODY_CHARS) {
+        Some((end, _)) => format!("{}...", &body[..end]),
+        None => body.to_string(),
+    }
+}
+
+/// Delay asked for by a `Retry-After` header, given in seconds or as an HTTP date
+fn parse_retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.with_timezone(&Utc) - Utc::now()).to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Incremental parser for the server-sent events of a streaming chat completion
+struct SseState {
+    bytes: BoxStream<'static, reqwest::Result<bytes::Bytes>>,
+    buffer: Vec<u8>,
+    pending: VecDeque<Result<String>>,
+    finished: bool,
+    provider: String,
+    /// Longest wait for the next chunk
+    idle_timeout: Duration,
+}
+
+impl SseState {
+    /// Parse every complete line in the buffer, keeping any partial line for the next chunk
+    fn drain_lines(&mut self) {
+        while let Some(position) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=position).collect();
+            if self.finished {
+                continue;
+            }
+            match parse_sse_line(String::from_utf8_lossy(&line).trim()) {
+                SseLine::Delta(content) => self.pending.push_back(Ok(content)),
+                SseLine::Done => self.finished = true,
+                SseLine::Error(message) => {
+                    self.pending.push_back(Err(ProcessError::Upstream(message).into()));
+                    self.finished = true;
+                }
+                SseLine::Ignored => {}
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum SseLine {
+    Delta(String),
+    Done,
+    Error(String),
+    Ignored,
+}
+
+/// Interpret a single line of a chat completion event stream
+fn parse_sse_line(line: &str) -> SseLine {
+    let Some(data) = line.strip_prefix("data:") else {
+        return SseLine::Ignored;
+    };
+    let data = data.trim();
+    if data == "[DONE]" {
+        return SseLine::Done;
+    }
+
+    match serde_json::from_str::<serde_json::Value>(data) {
+        Ok(event) => {
+            if let Some(message) = event["error"]["message"].as_str() {
+                return SseLine::Error(message.to_string());
+            }
+            match event["choices"][0]["delta"]["content"].as_str() {
+                Some(content) if !content.is_empty() => SseLine::Delta(content.to_string()),
+                _ => SseLine::Ignored,
+            }
+        }
+        Err(e) => SseLine::Error(format!("Invalid stream event: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::response::IntoResponse;
+    use axum::routing::post;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Serve `/chat/completions` answering with `responses` in turn, then with `Hello` forever;
+    /// returns the endpoint and the number of requests served
+    async fn spawn_flaky_server(responses: Vec<(StatusCode, HeaderMap)>) -> (String, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let app = axum::Router::new().route(
+            "/chat/completions",
+            post(move || {
+                let attempt = counter.fetch_add(1, Ordering::SeqCst);
+                let response = match responses.get(attempt) {
+                    Some((status, headers)) => (*status, headers.clone(), "try again").into_response(),
+                    None => axum::Json(serde_json::json!({ "choices": [{ "message": { "content": "Hello" } }] }))
+                        .into_response(),
+                };
+                async move { response }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (format!("http://{}", address), requests)
+    }
+
+    fn provider(endpoint: &str, options: LlmClientOptions) -> LocalLlmConfig {
+        LocalLlmConfig::new(endpoint).with_client(Arc::new(LlmClient::new(options)))
+    }
+
+    fn fast_retries() -> LlmClientOptions {
+        LlmClientOptions {
+            retry_backoff: Duration::from_millis(1),
+            ..LlmClientOptions::default()
+        }
+    }
+
+    fn retry_after(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", value.parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn test_transient_failures_are_retried() {
+        let responses = vec![
+            (StatusCode::TOO_MANY_REQUESTS, retry_after("0")),
+            (StatusCode::SERVICE_UNAVAILABLE, HeaderMap::new()),
+        ];
+        let (endpoint, requests) = spawn_flaky_server(responses).await;
+        let answer = provider(&endpoint, fast_retries()).chat_completion(&[ChatMessage::user("Hi")]).await;
+        assert_eq!(answer.unwrap(), "Hello");
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        // Client errors mean the provider is up, so they're neither retried nor counted as failures
+        let (endpoint, requests) = spawn_flaky_server(vec![(StatusCode::UNAUTHORIZED, HeaderMap::new())]).await;
+        let error = provider(&endpoint, fast_retries()).chat_completion(&[ChatMessage::user("Hi")]).await.unwrap_err();
+        assert_eq!(error.code(), "upstream_failed");
+        assert!(error.to_string().contains("local returned 401 Unauthorized: try again"));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_request_errors_are_not_retried() {
+        let options = LlmClientOptions {
+            retry_backoff: Duration::from_secs(10),
+            max_retry_delay: Duration::from_secs(10),
+            circuit_failure_threshold: 1,
+            ..LlmClientOptions::default()
+        };
+        let provider = provider("not a url", options);
+        let question = [ChatMessage::user("Hi")];
+
+        // Neither retried nor opening the circuit
+        let start = std::time::Instant::now();
+        for _ in 0..2 {
+            assert_eq!(provider.chat_completion(&question).await.unwrap_err().code(), "upstream_failed");
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_long_retry_after_is_passed_to_the_caller() {
+        let (endpoint, requests) = spawn_flaky_server(vec![(StatusCode::TOO_MANY_REQUESTS, retry_after("90"))]).await;
+        let error = provider(&endpoint, fast_retries()).chat_completion(&[ChatMessage::user("Hi")]).await.unwrap_err();
+        assert_eq!(error.code(), "upstream_unavailable");
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(90)));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let date = (Utc::now() + chrono::Duration::seconds(60)).to_rfc2822();
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(reqwest::header::RETRY_AFTER, date.parse().unwrap());
+        let delay = parse_retry_after(&headers).unwrap();
+        assert!(delay > Duration::from_secs(55) && delay <= Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_fails_fast() {
+        let failures = vec![(StatusCode::INTERNAL_SERVER_ERROR, HeaderMap::new()); 2];
+        let (endpoint, requests) = spawn_flaky_server(failures).await;
+        let options = LlmClientOptions {
+            max_retries: 0,
+            circuit_failure_threshold: 2,
+            circuit_reset: Duration::from_millis(200),
+            ..fast_retries()
+        };
+        let provider = provider(&endpoint, options);
+        let question = [ChatMessage::user("Hi")];
+
+        for _ in 0..2 {
+            assert_eq!(provider.chat_completion(&question).await.unwrap_err().code(), "upstream_failed");
+        }
+        let error = provider.chat_completion(&question).await.unwrap_err();
+        assert_eq!(error.code(), "upstream_unavailable");
+        assert!(error.retry_after().unwrap() <= Duration::from_millis(200));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        // After the reset time a trial request goes through, and its success closes the circuit
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(provider.chat_completion(&question).await.unwrap(), "Hello");
+        assert_eq!(provider.chat_completion(&question).await.unwrap(), "Hello");
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_parse_sse_line() {
+        assert_eq!(
+            parse_sse_line(r#"data: {"choices":[{"delta":{"content":"Hi"}}]}"#),
+            SseLine::Delta("Hi".to_string())
+        );
+        assert_eq!(parse_sse_line(r#"data: {"choices":[{"delta":{"role":"assistant"}}]}"#), SseLine::Ignored);
+        assert_eq!(parse_sse_line("data: [DONE]"), SseLine::Done);
+        assert_eq!(parse_sse_line(": keep-alive"), SseLine::Ignored);
+        assert_eq!(
+            parse_sse_line(r#"data: {"error":{"message":"quota exceeded"}}"#),
+            SseLine::Error("quota exceeded".to_string())
+        );
+    }
+
+    #[test]
+    fn test_config_url() {
+        let config = AzureOpenAiConfig::new("http://localhost:1234/", "key", "gpt");
+        assert!(config.is_configured());
+        assert_eq!(
+            config.chat_completions_url(),
+            "http://localhost:1234/openai/deployments/gpt/chat/completions?api-version=2024-02-15-preview"
+        );
+        assert_eq!(config.auth_header(), Some(("api-key", "key".to_string())));
+    }
+
+    #[test]
+    fn test_openai_compatible_providers() {
+        let openai = OpenAiConfig::new(OPENAI_ENDPOINT, PLACEHOLDER_API_KEY, "gpt-4o-mini");
+        assert!(!openai.is_configured());
+        assert_eq!(openai.chat_completions_url(), "https://api.openai.com/v1/chat/completions");
+        assert_eq!(openai.auth_header(), Some(("Authorization", format!("Bearer {}", PLACEHOLDER_API_KEY))));
+
+        let local = LocalLlmConfig::new("http://127.0.0.1:8080/v1/");
+        assert!(local.is_configured());
+        assert_eq!(local.chat_completions_url(), "http://127.0.0.1:8080/v1/chat/completions");
+        assert_eq!(local.auth_header(), None);
+
+        let messages = [ChatMessage::system("Be brief."), ChatMessage::user("Hi")];
+        let body = request_body(openai.model(), &messages, true);
+        assert_eq!(body["model"], "gpt-4o-mini");
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["messages"][0], serde_json::json!({ "role": "system", "content": "Be brief." }));
+        assert!(request_body(local.model(), &messages, false).get("model").is_none());
+    }
+}
@@ -0,0 +1,156 @@
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use crate::error::ProcessError;
+
+/// Content type of the Prometheus text exposition format
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Histogram buckets in seconds, from local tasks up to slow LLM answers
+const LATENCY_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// Process-wide metrics exposed on `/metrics`
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_errors: IntCounterVec,
+    request_duration: HistogramVec,
+    callbacks: IntCounterVec,
+    callback_duration: HistogramVec,
+    llm_requests: IntCounterVec,
+    llm_duration: HistogramVec,
+    llm_retries: IntCounterVec,
+    discovery_responses: IntCounter,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// The metrics shared by the whole server
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("rustlm".to_string()), None).expect("valid metrics prefix");
+        let histogram = |name: &str, help: &str, labels: &[&str]| {
+            let histogram = HistogramVec::new(
+                HistogramOpts::new(name, help).buckets(LATENCY_BUCKETS.to_vec()),
+                labels,
+            )
+            .expect("valid histogram");
+            registry.register(Box::new(histogram.clone())).expect("unique metric");
+            histogram
+        };
+        let counter = |name: &str, help: &str, labels: &[&str]| {
+            let counter = IntCounterVec::new(Opts::new(name, help), labels).expect("valid counter");
+            registry.register(Box::new(counter.clone())).expect("unique metric");
+            counter
+        };
+
+        let requests = counter("requests_total", "Processing requests by task", &["task"]);
+        let request_errors = counter("request_errors_total", "Failed processing requests by task and error code", &["task", "code"]);
+        let request_duration = histogram("request_duration_seconds", "Processing time by task", &["task"]);
+        let callbacks = counter("callbacks_total", "Callback executions by handler and outcome", &["handler", "outcome"]);
+        let callback_duration = histogram("callback_duration_seconds", "Callback execution time by handler", &["handler"]);
+        let llm_requests = counter("llm_requests_total", "LLM provider requests by outcome", &["outcome"]);
+        let llm_duration = histogram("llm_request_duration_seconds", "Time until the LLM provider answered, by `complete` or `stream` mode", &["mode"]);
+        let llm_retries = counter("llm_retries_total", "LLM provider requests retried, by the error code of the failed attempt", &["code"]);
+        let discovery_responses = IntCounter::new("discovery_responses_total", "UDP discovery requests answered")
+            .expect("valid counter");
+        registry
+            .register(Box::new(discovery_responses.clone()))
+            .expect("unique metric");
+
+        Self {
+            registry,
+            requests,
+            request_errors,
+            request_duration,
+            callbacks,
+            callback_duration,
+            llm_requests,
+            llm_duration,
+            llm_retries,
+            discovery_responses,
+        }
+    }
+
+    /// Count a processing request and its outcome; `task` should be a registered task name or `unknown`
+    pub fn observe_request(&self, task: &str, elapsed: Duration, error: Option<&ProcessError>) {
+        self.requests.with_label_values(&[task]).inc();
+        self.request_duration
+            .with_label_values(&[task])
+            .observe(elapsed.as_secs_f64());
+        if let Some(error) = error {
+            self.request_errors.with_label_values(&[task, error.code()]).inc();
+        }
+    }
+
+    pub fn observe_callback(&self, handler: &str, elapsed: Duration, success: bool) {
+        let outcome = if success { "success" } else { "failure" };
+        self.callbacks.with_label_values(&[handler, outcome]).inc();
+        self.callback_duration
+            .with_label_values(&[handler])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Count an LLM provider call; the outcome is `success` or the error code
+    pub fn observe_llm_request(&self, mode: &str, elapsed: Duration, error: Option<&ProcessError>) {
+        let outcome = error.map_or("success", |e| e.code());
+        self.llm_requests.with_label_values(&[outcome]).inc();
+        self.llm_duration
+            .with_label_values(&[mode])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn observe_llm_retry(&self, code: &str) {
+        self.llm_retries.with_label_values(&[code]).inc();
+    }
+
+    pub fn observe_discovery_response(&self) {
+        self.discovery_responses.inc();
+    }
+
+    /// Render every metric in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::warn!("Failed to encode metrics: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_prometheus_text() {
+        let metrics = Metrics::new();
+        metrics.observe_request("sentiment", Duration::from_millis(3), None);
+        metrics.observe_request(
+            "ask_ai",
+            Duration::from_secs(2),
+            Some(&ProcessError::Upstream("boom".to_string())),
+        );
+        metrics.observe_callback("nlp", Duration::from_millis(1), true);
+        metrics.observe_llm_request("complete", Duration::from_secs(2), Some(&ProcessError::Upstream("boom".to_string())));
+        metrics.observe_llm_retry("upstream_failed");
+        metrics.observe_discovery_response();
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE rustlm_requests_total counter"));
+        assert!(text.contains(r#"rustlm_requests_total{task="sentiment"} 1"#));
+        assert!(text.contains(r#"rustlm_request_errors_total{code="upstream_failed",task="ask_ai"} 1"#));
+        assert!(text.contains(r#"rustlm_request_duration_seconds_bucket{task="sentiment",le="0.005"} 1"#));
+        assert!(text.contains(r#"rustlm_callbacks_total{handler="nlp",outcome="success"} 1"#));
+        assert!(text.contains(r#"rustlm_llm_requests_total{outcome="upstream_failed"} 1"#));
+        assert!(text.contains(r#"rustlm_llm_retries_total{code="upstream_failed"} 1"#));
+        assert!(text.contains("rustlm_discovery_responses_total 1"));
+    }
+}
@@ -0,0 +1,238 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+use crate::shell::ShellCommand;
+
+/// Operating system commands are suggested for; Linux is split by distribution family
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", try_from = "String")]
+pub enum Platform {
+    /// Debian, Ubuntu and derivatives, using `apt`
+    Debian,
+    /// Fedora, RHEL, CentOS and derivatives, using `dnf`
+    Fedora,
+    /// Arch Linux and derivatives, using `pacman`
+    Arch,
+    /// Alpine Linux, using `apk`
+    Alpine,
+    /// openSUSE and SLES, using `zypper`
+    Suse,
+    /// A Linux distribution of unknown family
+    Linux,
+    #[serde(rename = "macos")]
+    MacOs,
+    Windows,
+}
+
+/// Names accepted for each platform, first the canonical one
+const PLATFORM_NAMES: &[(Platform, &[&str])] = &[
+    (Platform::Debian, &["debian", "ubuntu", "linuxmint", "pop", "raspbian", "kali"]),
+    (Platform::Fedora, &["fedora", "rhel", "centos", "rocky", "almalinux", "amzn", "ol"]),
+    (Platform::Arch, &["arch", "manjaro", "endeavouros"]),
+    (Platform::Alpine, &["alpine"]),
+    (Platform::Suse, &["suse", "opensuse", "opensuse-leap", "opensuse-tumbleweed", "sles"]),
+    (Platform::Linux, &["linux"]),
+    (Platform::MacOs, &["macos", "mac", "darwin", "osx"]),
+    (Platform::Windows, &["windows", "win"]),
+];
+
+static DETECTED: LazyLock<Platform> = LazyLock::new(|| {
+    let platform = Platform::detect();
+    tracing::info!("Suggesting commands for platform: {}", platform);
+    platform
+});
+
+impl Platform {
+    /// The platform the server runs on, detected once
+    pub fn detected() -> Self {
+        *DETECTED
+    }
+
+    fn detect() -> Self {
+        if cfg!(target_os = "macos") {
+            Platform::MacOs
+        } else if cfg!(target_os = "windows") {
+            Platform::Windows
+        } else {
+            std::fs::read_to_string("/etc/os-release")
+                .map(|contents| Self::from_os_release(&contents))
+                .unwrap_or(Platform::Linux)
+        }
+    }
+
+    /// Linux family from the `ID` and `ID_LIKE` fields of an os-release file
+    pub fn from_os_release(contents: &str) -> Self {
+        let field = |name: &str| {
+            contents
+                .lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix('='))
+                .map(|value| value.trim().trim_matches('"').trim_matches('\'').to_lowercase())
+                .unwrap_or_default()
+        };
+        let id = field("ID");
+        let id_like = field("ID_LIKE");
+
+        std::iter::once(id.as_str())
+            .chain(id_like.split_whitespace())
+            .filter_map(|name| name.parse::<Platform>().ok())
+            .find(|platform| !matches!(platform, Platform::MacOs | Platform::Windows))
+            .unwrap_or(Platform::Linux)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        PLATFORM_NAMES
+            .iter()
+            .find(|(platform, _)| platform == self)
+            .map_or("linux", |(_, names)| names[0])
+    }
+
+    /// The system package manager's install command, without the package
+    pub fn package_manager(&self) -> Option<ShellCommand> {
+        let command = match self {
+            Platform::Debian => ShellCommand::new("apt").arg("install"),
+            Platform::Fedora => ShellCommand::new("dnf").arg("install"),
+            Platform::Arch => ShellCommand::new("pacman").arg("-S"),
+            Platform::Alpine => ShellCommand::new("apk").arg("add"),
+            Platform::Suse => ShellCommand::new("zypper").arg("install"),
+            Platform::MacOs => ShellCommand::new("brew").arg("install"),
+            Platform::Windows => ShellCommand::new("winget").arg("install"),
+            Platform::Linux => return None,
+        };
+        Some(command)
+    }
+
+    /// Commands that open a file with its default application or an editor
+    pub fn file_openers(&self, path: &str) -> Vec<ShellCommand> {
+        let programs: &[&str] = match self {
+            Platform::MacOs => &["open", "code", "vim", "cat"],
+            Platform::Windows => &["explorer", "code", "notepad"],
+            _ => &["xdg-open", "code", "vim", "cat"],
+        };
+        programs
+            .iter()
+            .map(|program| ShellCommand::new(program).arg(path))
+            .collect()
+    }
+
+    /// Commands that open a URL in the default browser, then one that fetches it
+    pub fn url_openers(&self, url: &str) -> Vec<ShellCommand> {
+        let mut commands = match self {
+            Platform::MacOs => vec![
+                ShellCommand::new("open").arg(url),
+                ShellCommand::new("osascript")
+                    .arg("-e")
+                    .arg(format!("open location \"{}\"", url)),
+            ],
+            Platform::Windows => vec![ShellCommand::new("cmd").args(["/C", "start", ""]).arg(url)],
+            _ => vec![ShellCommand::new("xdg-open").arg(url)],
+        };
+        let python = if *self == Platform::Windows { "python" } else { "python3" };
+        commands.push(ShellCommand::new(python).args(["-m", "webbrowser"]).arg(url));
+        commands.push(ShellCommand::new("curl").arg("-sL").arg(url));
+        commands
+    }
+
+    /// Commands that launch an application by name
+    pub fn app_launchers(&self, app_name: &str) -> Vec<ShellCommand> {
+        match self {
+            Platform::MacOs => vec![
+                ShellCommand::new("open").arg("-a").arg(app_name),
+                ShellCommand::new("open").arg(format!("/Applications/{}.app", app_name)),
+                ShellCommand::new("osascript")
+                    .arg("-e")
+                    .arg(format!("tell application \"{}\" to activate", app_name)),
+            ],
+            Platform::Windows => vec![ShellCommand::new("cmd").args(["/C", "start", ""]).arg(app_name)],
+            _ => {
+                // Desktop entries and binaries are usually the lowercase name
+                let binary = app_name.to_lowercase().replace(' ', "-");
+                vec![
+                    ShellCommand::new("gtk-launch").arg(&binary),
+                    ShellCommand::new(&binary),
+                ]
+            }
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase();
+        PLATFORM_NAMES
+            .iter()
+            .find(|(_, names)| names.contains(&name.as_str()))
+            .map(|(platform, _)| *platform)
+            .ok_or_else(|| {
+                let expected: Vec<&str> = PLATFORM_NAMES.iter().map(|(_, names)| names[0]).collect();
+                anyhow::anyhow!("Unknown platform '{}', expected one of {}", s, expected.join(", "))
+            })
+    }
+}
+
+impl TryFrom<String> for Platform {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_platform_names() {
+        assert_eq!("Ubuntu".parse::<Platform>().unwrap(), Platform::Debian);
+        assert_eq!("darwin".parse::<Platform>().unwrap(), Platform::MacOs);
+        assert_eq!(Platform::MacOs.to_string(), "macos");
+        assert!("plan9".parse::<Platform>().unwrap_err().to_string().contains("expected one of debian"));
+
+        let platform: Platform = serde_json::from_value(serde_json::json!("rhel")).unwrap();
+        assert_eq!(platform, Platform::Fedora);
+        assert_eq!(serde_json::to_value(Platform::MacOs).unwrap(), "macos");
+    }
+
+    #[test]
+    fn test_linux_family_from_os_release() {
+        let rocky = "NAME=\"Rocky Linux\"\nID=\"rocky\"\nID_LIKE=\"rhel centos fedora\"\n";
+        assert_eq!(Platform::from_os_release(rocky), Platform::Fedora);
+
+        let pop = "ID=pop\nID_LIKE=\"ubuntu debian\"\n";
+        assert_eq!(Platform::from_os_release(pop), Platform::Debian);
+
+        let unknown = "ID=nixos\n";
+        assert_eq!(Platform::from_os_release(unknown), Platform::Linux);
+    }
+
+    #[test]
+    fn test_per_platform_commands() {
+        let render = |commands: Vec<ShellCommand>| commands.iter().map(ShellCommand::render).collect::<Vec<_>>();
+
+        assert_eq!(Platform::Debian.package_manager().unwrap().arg("ripgrep").render(), "apt install ripgrep");
+        assert_eq!(Platform::Arch.package_manager().unwrap().arg("ripgrep").render(), "pacman -S ripgrep");
+        assert!(Platform::Linux.package_manager().is_none());
+        assert_eq!(render(Platform::Fedora.file_openers("a.txt"))[0], "xdg-open a.txt");
+        assert_eq!(render(Platform::Windows.file_openers("a.txt"))[0], "explorer a.txt");
+        assert_eq!(render(Platform::Debian.app_launchers("Firefox")), vec!["gtk-launch firefox", "firefox"]);
+        assert_eq!(render(Platform::MacOs.app_launchers("Safari"))[0], "open -a Safari");
+
+        let url = "https://example.com/";
+        let programs = |platform: Platform| -> Vec<String> {
+            platform.url_openers(url).into_iter().map(|command| command.program).collect()
+        };
+        assert_eq!(programs(Platform::MacOs), vec!["open", "osascript", "python3", "curl"]);
+        assert_eq!(programs(Platform::Debian), vec!["xdg-open", "python3", "curl"]);
+        assert_eq!(programs(Platform::Windows), vec!["cmd", "python", "curl"]);
+    }
+}
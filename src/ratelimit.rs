@@ -0,0 +1,306 @@
+use chrono::{NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::{ClassLimitConfig, RateLimitConfig};
+use crate::error::ProcessError;
+use crate::tasks::{Task, TaskCategory};
+
+/// How often `check` looks for idle buckets to drop
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Cost class a task is rate limited under
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateClass {
+    /// Tasks that run locally and are cheap
+    Local,
+    /// Tasks that call an LLM provider
+    Llm,
+}
+
+impl RateClass {
+    pub fn of(task: &dyn Task) -> Self {
+        match task.category() {
+            TaskCategory::Llm => RateClass::Llm,
+            _ => RateClass::Local,
+        }
+    }
+}
+
+/// Token bucket and daily counter for one client and class
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    day: NaiveDate,
+    used_today: u64,
+}
+
+/// Usage of one client and class, reported by the admin endpoint
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QuotaUsage {
+    pub client: String,
+    pub class: RateClass,
+    pub used_today: u64,
+    pub daily_quota: Option<u64>,
+    pub remaining_today: Option<u64>,
+    /// Requests that can be made right now without waiting
+    pub available_now: u32,
+}
+
+/// Token-bucket rate limiter with daily quotas, keyed by client and `RateClass`
+pub struct RateLimiter {
+    enabled: bool,
+    local: ClassLimitConfig,
+    llm: ClassLimitConfig,
+    buckets: Mutex<HashMap<(String, RateClass), Bucket>>,
+    /// When `check` next drops idle buckets
+    next_sweep: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Limiter that allows every request
+    pub fn disabled() -> Self {
+        Self::from_config(&RateLimitConfig::default())
+    }
+
+    pub fn from_config(config: &RateLimitConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            local: config.local.clone(),
+            llm: config.llm.clone(),
+            buckets: Mutex::new(HashMap::new()),
+            next_sweep: Mutex::new(Instant::now() + SWEEP_INTERVAL),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn limits(&self, class: RateClass) -> &ClassLimitConfig {
+        match class {
+            RateClass::Local => &self.local,
+            RateClass::Llm => &self.llm,
+        }
+    }
+
+    /// Take one request from the client's allowance, or say how long to wait
+    pub fn check(&self, client: &str, class: RateClass) -> Result<(), ProcessError> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let limits = self.limits(class);
+        let now = Instant::now();
+        let today = Utc::now().date_naive();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        self.sweep(&mut buckets, now, today);
+        let bucket = buckets
+            .entry((client.to_string(), class))
+            .or_insert_with(|| Bucket {
+                tokens: limits.burst as f64,
+                refilled_at: now,
+                day: today,
+                used_today: 0,
+            });
+        refill(bucket, limits, now, today);
+
+        if let Some(quota) = limits.daily_quota {
+            if bucket.used_today >= quota {
+                return Err(ProcessError::QuotaExceeded {
+                    message: format!("Daily quota of {} requests reached for {:?} tasks", quota, class),
+                    retry_after: until_midnight(),
+                });
+            }
+        }
+
+        if bucket.tokens < 1.0 {
+            let per_second = limits.requests_per_minute as f64 / 60.0;
+            let wait = ((1.0 - bucket.tokens) / per_second).ceil().max(1.0);
+            return Err(ProcessError::RateLimited {
+                message: format!("Rate limit of {} requests per minute exceeded", limits.requests_per_minute),
+                retry_after: Duration::from_secs(wait as u64),
+            });
+        }
+
+        bucket.tokens -= 1.0;
+        bucket.used_today += 1;
+        Ok(())
+    }
+
+    /// Every `SWEEP_INTERVAL`, drop the buckets of clients that haven't been seen today and have
+    /// since refilled; they'd start over the same way if the client came back
+    fn sweep(&self, buckets: &mut HashMap<(String, RateClass), Bucket>, now: Instant, today: NaiveDate) {
+        let mut next_sweep = self.next_sweep.lock().unwrap_or_else(|e| e.into_inner());
+        if now < *next_sweep {
+            return;
+        }
+        *next_sweep = now + SWEEP_INTERVAL;
+
+        buckets.retain(|(_, class), bucket| {
+            let limits = self.limits(*class);
+            let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+            let full = bucket.tokens + elapsed * limits.requests_per_minute as f64 / 60.0 >= limits.burst as f64;
+            bucket.day == today || !full
+        });
+    }
+
+    /// Current usage of every client seen so far, sorted by client and class
+    pub fn usage(&self) -> Vec<QuotaUsage> {
+        let now = Instant::now();
+        let today = Utc::now().date_naive();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut usage: Vec<QuotaUsage> = buckets
+            .iter_mut()
+            .map(|((client, class), bucket)| {
+                let limits = self.limits(*class);
+                refill(bucket, limits, now, today);
+                QuotaUsage {
+                    client: client.clone(),
+                    class: *class,
+                    used_today: bucket.used_today,
+                    daily_quota: limits.daily_quota,
+                    remaining_today: limits.daily_quota.map(|q| q.saturating_sub(bucket.used_today)),
+                    available_now: bucket.tokens.floor() as u32,
+                }
+            })
+            .collect();
+        usage.sort_by(|a, b| (&a.client, a.class).cmp(&(&b.client, b.class)));
+        usage
+    }
+}
+
+fn refill(bucket: &mut Bucket, limits: &ClassLimitConfig, now: Instant, today: NaiveDate) {
+    let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+    let per_second = limits.requests_per_minute as f64 / 60.0;
+    bucket.tokens = (bucket.tokens + elapsed * per_second).min(limits.burst as f64);
+    bucket.refilled_at = now;
+
+    if bucket.day != today {
+        bucket.day = today;
+        bucket.used_today = 0;
+    }
+}
+
+/// Time until the daily quotas reset at midnight UTC
+fn until_midnight() -> Duration {
+    let now = Utc::now();
+    let midnight = (now.date_naive() + chrono::Duration::days(1))
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time")
+        .and_utc();
+    (midnight - now).to_std().unwrap_or(Duration::from_secs(1))
+}
+
+/// A client's handle on the limiter, carried with each request
+#[derive(Clone)]
+pub struct RateLimitHandle {
+    limiter: Arc<RateLimiter>,
+    client: String,
+}
+
+impl RateLimitHandle {
+    pub fn new(limiter: Arc<RateLimiter>, client: &str) -> Self {
+        Self {
+            limiter,
+            client: client.to_string(),
+        }
+    }
+
+    pub fn check(&self, task: &dyn Task) -> Result<(), ProcessError> {
+        self.limiter.check(&self.client, RateClass::of(task))
+    }
+}
+
+impl std::fmt::Debug for RateLimitHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RateLimitHandle").field("client", &self.client).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(requests_per_minute: u32, burst: u32, daily_quota: Option<u64>) -> RateLimiter {
+        let limits = ClassLimitConfig {
+            requests_per_minute,
+            burst,
+            daily_quota,
+        };
+        RateLimiter::from_config(&RateLimitConfig {
+            enabled: true,
+            local: limits.clone(),
+            llm: limits,
+        })
+    }
+
+    #[test]
+    fn test_burst_then_rate_limited() {
+        let limiter = limiter(60, 3, None);
+
+        for _ in 0..3 {
+            assert!(limiter.check("key:a", RateClass::Local).is_ok());
+        }
+        let error = limiter.check("key:a", RateClass::Local).unwrap_err();
+        assert_eq!(error.code(), "rate_limited");
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(1)));
+
+        // Other clients and classes have their own buckets
+        assert!(limiter.check("key:b", RateClass::Local).is_ok());
+        assert!(limiter.check("key:a", RateClass::Llm).is_ok());
+    }
+
+    #[test]
+    fn test_daily_quota() {
+        let limiter = limiter(6000, 100, Some(2));
+
+        assert!(limiter.check("key:a", RateClass::Llm).is_ok());
+        assert!(limiter.check("key:a", RateClass::Llm).is_ok());
+        let error = limiter.check("key:a", RateClass::Llm).unwrap_err();
+        assert_eq!(error.code(), "quota_exceeded");
+        assert!(error.retry_after().unwrap() <= Duration::from_secs(24 * 3600));
+
+        let usage = limiter.usage();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].used_today, 2);
+        assert_eq!(usage[0].remaining_today, Some(0));
+    }
+
+    #[test]
+    fn test_idle_buckets_are_dropped() {
+        let limiter = limiter(60, 3, Some(100));
+        assert!(limiter.check("key:a", RateClass::Local).is_ok());
+        assert!(limiter.check("key:b", RateClass::Local).is_ok());
+
+        // key:a was last seen yesterday and has refilled since; key:b is still refilling
+        {
+            let mut buckets = limiter.buckets.lock().unwrap();
+            let yesterday = Utc::now().date_naive() - chrono::Duration::days(1);
+            for (client, tokens) in [("key:a", 3.0), ("key:b", 0.0)] {
+                let bucket = buckets.get_mut(&(client.to_string(), RateClass::Local)).unwrap();
+                bucket.day = yesterday;
+                bucket.tokens = tokens;
+            }
+        }
+        *limiter.next_sweep.lock().unwrap() = Instant::now();
+
+        assert!(limiter.check("key:c", RateClass::Llm).is_ok());
+        let clients: Vec<String> = limiter.usage().into_iter().map(|usage| usage.client).collect();
+        assert_eq!(clients, vec!["key:b", "key:c"]);
+    }
+
+    #[test]
+    fn test_disabled_limiter_allows_everything() {
+        let limiter = RateLimiter::disabled();
+        for _ in 0..1000 {
+            assert!(limiter.check("ip:127.0.0.1", RateClass::Llm).is_ok());
+        }
+        assert!(limiter.usage().is_empty());
+    }
+}
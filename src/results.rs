@@ -0,0 +1,569 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::platform::Platform;
+use crate::shell::ShellCommand;
+
+/// Result of a processing task, serialized as the task's own JSON object
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum TaskResult {
+    Sentiment(SentimentResult),
+    Summary(SummaryResult),
+    Classification(ClassificationResult),
+    Keywords(KeywordsResult),
+    Translation(TranslationResult),
+    Answer(AnswerResult),
+    Command(Box<CommandSuggestion>),
+    Tools(ToolsResult),
+    Search(SearchSuggestion),
+    AskAi(AskAiResult),
+    NaturalLanguage(Box<NaturalLanguageResult>),
+    Usage(UsageError),
+    /// Result of a task registered outside this crate
+    Custom(serde_json::Value),
+}
+
+impl TaskResult {
+    /// Convert the result into a JSON value for callbacks and clients
+    pub fn to_value(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or(serde_json::Value::Null)
+    }
+
+    /// The suggested commands, including those of a task a natural language request ran
+    pub fn command_suggestion(&self) -> Option<&CommandSuggestion> {
+        match self {
+            TaskResult::Command(suggestion) => Some(suggestion),
+            TaskResult::NaturalLanguage(result) => result.result.as_ref()?.command_suggestion(),
+            _ => None,
+        }
+    }
+}
+
+/// Sentiment analysis result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SentimentResult {
+    pub sentiment: String,
+    pub positive_score: usize,
+    pub negative_score: usize,
+}
+
+/// Text summarization result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SummaryResult {
+    pub summary: String,
+}
+
+/// Text classification result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClassificationResult {
+    pub category: String,
+    pub confidence: f32,
+    pub keyword_matches: usize,
+}
+
+/// A single extracted keyword with its frequency
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Keyword {
+    pub word: String,
+    pub count: usize,
+}
+
+/// Keyword extraction result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeywordsResult {
+    pub keywords: Vec<Keyword>,
+}
+
+/// Translation result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranslationResult {
+    pub original: String,
+    pub translated: String,
+    pub source_language: String,
+    pub target_language: String,
+}
+
+/// Question answering result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnswerResult {
+    pub question: String,
+    pub answer: String,
+    #[serde(rename = "type")]
+    pub answer_type: String,
+}
+
+/// The subject a command suggestion was built for, serialized under its own key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandSubject {
+    Package(String),
+    Filename(String),
+    SearchTerm(String),
+    Source(String),
+    AppName(String),
+    FilePath(String),
+    Target(String),
+}
+
+/// Whether user input could be placed in suggested commands as given
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputStatus {
+    #[default]
+    Accepted,
+    /// The input was changed to make it safe, e.g. by removing control characters
+    Sanitized,
+    /// The input can't be used safely, so no commands are suggested
+    Rejected,
+}
+
+/// How the input was checked before building commands from it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputCheck {
+    pub status: InputStatus,
+    /// What was changed or why the input was rejected
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub issues: Vec<String>,
+}
+
+impl InputCheck {
+    pub fn sanitized(&mut self, issue: impl Into<String>) {
+        if self.status == InputStatus::Accepted {
+            self.status = InputStatus::Sanitized;
+        }
+        self.issues.push(issue.into());
+    }
+
+    pub fn reject(&mut self, issue: impl Into<String>) {
+        self.status = InputStatus::Rejected;
+        self.issues.push(issue.into());
+    }
+
+    pub fn is_rejected(&self) -> bool {
+        self.status == InputStatus::Rejected
+    }
+}
+
+/// Shell commands suggested for a system command task
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommandSuggestion {
+    pub command: String,
+    #[serde(flatten)]
+    pub subject: CommandSubject,
+    /// Each suggestion as a program and its arguments
+    pub commands: Vec<ShellCommand>,
+    /// `commands` quoted for the platform's shell: cmd.exe on Windows, a POSIX shell otherwise
+    pub suggested_commands: Vec<String>,
+    pub input: InputCheck,
+    /// Platform the commands were chosen for, when they depend on it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+    /// How an `install` package name was resolved through the package catalog
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<PackageResolution>,
+    /// What the server found itself, when workspace search is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<WorkspaceResults>,
+    /// What the configured Git repository says about the target, when Git integration is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<RepositoryInfo>,
+    /// The server-side download started for a `get_file_from` URL, when downloads are enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download: Option<DownloadStatus>,
+}
+
+impl CommandSuggestion {
+    /// Build a suggestion, dropping the commands when the input was rejected
+    pub fn new(command: &str, subject: CommandSubject, commands: Vec<ShellCommand>, input: InputCheck) -> Self {
+        let commands = if input.is_rejected() { Vec::new() } else { commands };
+        Self {
+            command: command.to_string(),
+            subject,
+            suggested_commands: commands.iter().map(ShellCommand::render).collect(),
+            commands,
+            input,
+            platform: None,
+            resolution: None,
+            workspace: None,
+            repository: None,
+            download: None,
+        }
+    }
+
+    /// Note the platform the commands were chosen for, and quote them for its shell
+    pub fn for_platform(mut self, platform: Platform) -> Self {
+        self.platform = Some(platform);
+        self.suggested_commands = self.commands.iter().map(|command| command.render_for(platform)).collect();
+        self
+    }
+
+    pub fn with_resolution(mut self, resolution: PackageResolution) -> Self {
+        self.resolution = Some(resolution);
+        self
+    }
+}
+
+/// Files or lines found by searching the configured workspace roots
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorkspaceResults {
+    /// Directories that were searched
+    pub roots: Vec<String>,
+    pub matches: Vec<WorkspaceMatch>,
+    /// Whether the search stopped at the result limit
+    pub truncated: bool,
+    pub files_searched: usize,
+    /// Files not searched because they were binary, too large or unreadable
+    pub files_skipped: usize,
+}
+
+/// A matching file, or a matching line with its surrounding lines
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkspaceMatch {
+    pub root: String,
+    /// Path from the root, separated by `/`
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_number: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub before: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+}
+
+impl WorkspaceMatch {
+    pub fn file(root: &Path, path: &str) -> Self {
+        Self {
+            root: root.display().to_string(),
+            path: path.to_string(),
+            line_number: None,
+            line: None,
+            before: Vec::new(),
+            after: Vec::new(),
+        }
+    }
+}
+
+/// A `checkout` or `diff` target looked up in the configured Git repository
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RepositoryInfo {
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub refs: Vec<RefInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<DiffStats>,
+    /// Why the repository couldn't be inspected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What a name refers to in a Git repository
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefKind {
+    Branch,
+    /// A branch that only exists on a remote; checking it out creates a tracking branch
+    RemoteBranch,
+    Tag,
+    Commit,
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RefInfo {
+    pub name: String,
+    pub kind: RefKind,
+    /// Full hash of the commit the name points to
+    pub commit: Option<String>,
+    /// The remote branch, e.g. `origin/release`, for a `remote_branch`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    /// Branches and tags with similar names, for an `unknown` name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub close_matches: Vec<String>,
+}
+
+/// Summary of `git diff --numstat`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DiffStats {
+    pub files_changed: usize,
+    pub insertions: u64,
+    pub deletions: u64,
+    pub files: Vec<FileDiffStat>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileDiffStat {
+    pub path: String,
+    /// Lines added; `None` for binary files
+    pub insertions: Option<u64>,
+    /// Lines removed; `None` for binary files
+    pub deletions: Option<u64>,
+}
+
+/// Where a server-side download is at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadState {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+}
+
+/// Progress of a `get_file_from` download into the server's download directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DownloadStatus {
+    pub id: Uuid,
+    pub url: String,
+    /// File name in the download directory
+    pub file: String,
+    pub state: DownloadState,
+    pub bytes_downloaded: u64,
+    /// Size of the file, when the server reported it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_bytes: Option<u64>,
+    /// Bytes kept from an earlier, interrupted download of the same file
+    pub resumed_from: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// Expected checksum as `<algorithm>:<hex>`, verified before the download completes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    /// Why the download failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Client that started the download, the only one who can look it up
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+}
+
+impl DownloadStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, DownloadState::Completed | DownloadState::Failed)
+    }
+}
+
+/// What the package catalog knows about a package to install
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageResolution {
+    /// Whether the name or an alias is in the catalog
+    pub known: bool,
+    /// Catalog name the input resolved to, e.g. `ripgrep` for `rg`
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Package managers of the suggested commands, most likely first
+    pub managers: Vec<String>,
+    pub note: Option<String>,
+}
+
+/// Tools listed by the `show_tools` task
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ToolListing {
+    All(BTreeMap<String, Vec<String>>),
+    Category(Vec<String>),
+    UnknownCategory {
+        error: String,
+        available_categories: Vec<String>,
+    },
+}
+
+/// Result of the `show_tools` task
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolsResult {
+    pub command: String,
+    pub category: String,
+    pub tools: ToolListing,
+}
+
+/// Result of the `google_search` task
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchSuggestion {
+    pub command: String,
+    pub query: String,
+    pub google_url: String,
+    pub commands: Vec<ShellCommand>,
+    /// `commands` quoted for the platform's shell: cmd.exe on Windows, a POSIX shell otherwise
+    pub suggested_commands: Vec<String>,
+    pub input: InputCheck,
+    /// Platform the commands were chosen for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+}
+
+impl SearchSuggestion {
+    pub fn new(query: String, google_url: String, commands: Vec<ShellCommand>, input: InputCheck) -> Self {
+        Self {
+            command: "google_search".to_string(),
+            query,
+            google_url,
+            suggested_commands: commands.iter().map(ShellCommand::render).collect(),
+            commands,
+            input,
+            platform: None,
+        }
+    }
+
+    /// Note the platform the commands were chosen for, and quote them for its shell
+    pub fn for_platform(mut self, platform: Platform) -> Self {
+        self.platform = Some(platform);
+        self.suggested_commands = self.commands.iter().map(|command| command.render_for(platform)).collect();
+        self
+    }
+}
+
+/// Result of the `ask_ai` task
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AskAiResult {
+    pub command: String,
+    pub question: String,
+    /// Either `completed` or `setup_required`
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub curl_example: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<String>,
+}
+
+impl AskAiResult {
+    /// Create an empty result for the given question with the given status
+    pub fn new(question: &str, status: &str) -> Self {
+        Self {
+            command: "ask_ai".to_string(),
+            question: question.to_string(),
+            status: status.to_string(),
+            answer: None,
+            source: None,
+            message: None,
+            curl_example: None,
+            error: None,
+            suggestion: None,
+        }
+    }
+}
+
+/// Result of the `natural_language` task
+#[derive(Debug, Clone, Serialize)]
+pub struct NaturalLanguageResult {
+    pub intent: String,
+    pub confidence: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extracted_text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<TaskResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub suggestions: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggested_task: Option<String>,
+    pub auto_executed: bool,
+}
+
+/// Returned by a task when its input is missing the required argument
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageError {
+    pub command: String,
+    pub error: String,
+    pub usage: String,
+}
+
+impl UsageError {
+    pub fn new(command: &str, error: &str, usage: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            error: error.to_string(),
+            usage: usage.to_string(),
+        }
+    }
+}
+
+macro_rules! impl_from_result {
+    ($($ty:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$ty> for TaskResult {
+                fn from(result: $ty) -> Self {
+                    TaskResult::$variant(result)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_result! {
+    SentimentResult => Sentiment,
+    SummaryResult => Summary,
+    ClassificationResult => Classification,
+    KeywordsResult => Keywords,
+    TranslationResult => Translation,
+    AnswerResult => Answer,
+    ToolsResult => Tools,
+    SearchSuggestion => Search,
+    AskAiResult => AskAi,
+    UsageError => Usage,
+}
+
+impl From<CommandSuggestion> for TaskResult {
+    fn from(result: CommandSuggestion) -> Self {
+        TaskResult::Command(Box::new(result))
+    }
+}
+
+impl From<NaturalLanguageResult> for TaskResult {
+    fn from(result: NaturalLanguageResult) -> Self {
+        TaskResult::NaturalLanguage(Box::new(result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_suggestion_serializes_subject_key() {
+        let result = TaskResult::from(CommandSuggestion::new(
+            "install",
+            CommandSubject::Package("rust".to_string()),
+            vec![ShellCommand::new("cargo").args(["install", "rust"])],
+            InputCheck::default(),
+        ));
+
+        let json = result.to_value();
+        assert_eq!(json["command"], "install");
+        assert_eq!(json["package"], "rust");
+        assert_eq!(json["commands"][0]["args"][1], "rust");
+        assert_eq!(json["suggested_commands"][0], "cargo install rust");
+        assert_eq!(json["input"]["status"], "accepted");
+    }
+
+    #[test]
+    fn test_special_characters_produce_valid_json() {
+        let result = TaskResult::from(CommandSuggestion::new(
+            "find_content",
+            CommandSubject::SearchTerm(r#"say "hi" \ bye"#.to_string()),
+            vec![],
+            InputCheck::default(),
+        ));
+
+        let serialized = serde_json::to_string(&result).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(parsed["search_term"], r#"say "hi" \ bye"#);
+    }
+}
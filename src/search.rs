@@ -0,0 +1,431 @@
+use anyhow::{Context, Result};
+use regex::{Regex, RegexBuilder};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::config::WorkspaceSearchConfig;
+use crate::results::{WorkspaceMatch, WorkspaceResults};
+
+/// Bytes read to decide whether a file is binary, as Git does
+const BINARY_CHECK_BYTES: usize = 8000;
+
+/// Searches allow-listed directories for files by name or by content
+#[derive(Debug, Clone)]
+pub struct WorkspaceSearch {
+    roots: Vec<PathBuf>,
+    max_depth: usize,
+    max_file_size: u64,
+    max_results: usize,
+    context_lines: usize,
+    respect_gitignore: bool,
+}
+
+impl WorkspaceSearch {
+    pub fn new(config: &WorkspaceSearchConfig) -> Result<Self> {
+        let roots = config
+            .roots
+            .iter()
+            .map(|root| {
+                root.canonicalize()
+                    .with_context(|| format!("Invalid search root {}", root.display()))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            roots,
+            max_depth: config.max_depth,
+            max_file_size: config.max_file_size,
+            max_results: config.max_results,
+            context_lines: config.context_lines,
+            respect_gitignore: config.respect_gitignore,
+        })
+    }
+
+    /// Files whose name matches `pattern`: a glob such as `*.rs`, or else a case-insensitive substring
+    pub fn find_files(&self, pattern: &str) -> WorkspaceResults {
+        let matcher = if pattern.contains(['*', '?', '[']) {
+            NameMatcher::Glob(glob_regex(pattern, true, "^", "$"))
+        } else {
+            NameMatcher::Substring(pattern.to_lowercase())
+        };
+
+        self.walk(|file, results| {
+            let name = file.relative.rsplit('/').next().unwrap_or_default();
+            if !matcher.is_match(name) {
+                return;
+            }
+            if results.matches.len() == self.max_results {
+                results.truncated = true;
+            } else {
+                results.matches.push(WorkspaceMatch::file(file.root, file.relative));
+            }
+        })
+    }
+
+    /// Lines containing `text`, ignoring case unless it has an uppercase letter
+    pub fn find_content(&self, text: &str) -> WorkspaceResults {
+        let pattern = RegexBuilder::new(&regex::escape(text))
+            .case_insensitive(!text.chars().any(char::is_uppercase))
+            .build()
+            .expect("escaped text is a valid regex");
+
+        self.walk(|file, results| {
+            let Some(contents) = self.read_text(file.path) else {
+                results.files_skipped += 1;
+                return;
+            };
+            let lines: Vec<&str> = contents.lines().collect();
+            for (index, line) in lines.iter().enumerate() {
+                if !pattern.is_match(line) {
+                    continue;
+                }
+                if results.matches.len() == self.max_results {
+                    results.truncated = true;
+                    return;
+                }
+                let start = index.saturating_sub(self.context_lines);
+                let end = (index + 1 + self.context_lines).min(lines.len());
+                results.matches.push(WorkspaceMatch {
+                    line_number: Some(index + 1),
+                    line: Some(line.to_string()),
+                    before: lines[start..index].iter().map(|l| l.to_string()).collect(),
+                    after: lines[index + 1..end].iter().map(|l| l.to_string()).collect(),
+                    ..WorkspaceMatch::file(file.root, file.relative)
+                });
+            }
+        })
+    }
+
+    /// The file's contents, or `None` when it's too large, binary or unreadable
+    fn read_text(&self, path: &Path) -> Option<String> {
+        let file = fs::File::open(path).ok()?;
+        if file.metadata().ok()?.len() > self.max_file_size {
+            return None;
+        }
+        let mut contents = Vec::new();
+        file.take(self.max_file_size).read_to_end(&mut contents).ok()?;
+        if contents[..contents.len().min(BINARY_CHECK_BYTES)].contains(&0) {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&contents).into_owned())
+    }
+
+    /// Visit every file under the roots that isn't ignored, until a visit marks the results truncated
+    fn walk<F: FnMut(&WalkedFile, &mut WorkspaceResults)>(&self, visit: F) -> WorkspaceResults {
+        let mut walk = Walk {
+            ignores: Vec::new(),
+            visit,
+            results: WorkspaceResults {
+                roots: self.roots.iter().map(|root| root.display().to_string()).collect(),
+                ..WorkspaceResults::default()
+            },
+        };
+        for root in &self.roots {
+            walk.ignores.clear();
+            self.walk_dir(&mut walk, root, root, "", 0);
+        }
+        walk.results
+    }
+
+    fn walk_dir<F: FnMut(&WalkedFile, &mut WorkspaceResults)>(
+        &self,
+        walk: &mut Walk<F>,
+        root: &Path,
+        dir: &Path,
+        relative: &str,
+        depth: usize,
+    ) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        let pushed = self.respect_gitignore
+            && match Gitignore::read(&dir.join(".gitignore"), relative) {
+                Some(gitignore) => {
+                    walk.ignores.push(gitignore);
+                    true
+                }
+                None => false,
+            };
+
+        // Sorted so results don't depend on the file system's order
+        let mut entries: Vec<_> = entries.filter_map(|entry| entry.ok()).collect();
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            if walk.results.truncated {
+                break;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // Symlinks could lead outside the root, so they're never followed
+            if name == ".git" || file_type.is_symlink() {
+                continue;
+            }
+            let path = if relative.is_empty() { name } else { format!("{}/{}", relative, name) };
+            if is_ignored(&walk.ignores, &path, file_type.is_dir()) {
+                continue;
+            }
+
+            if file_type.is_dir() {
+                if depth < self.max_depth {
+                    self.walk_dir(walk, root, &entry.path(), &path, depth + 1);
+                }
+            } else if file_type.is_file() {
+                walk.results.files_searched += 1;
+                let file = WalkedFile {
+                    root,
+                    path: &entry.path(),
+                    relative: &path,
+                };
+                (walk.visit)(&file, &mut walk.results);
+            }
+        }
+
+        if pushed {
+            walk.ignores.pop();
+        }
+    }
+}
+
+/// State carried through a walk of the roots
+struct Walk<F> {
+    /// `.gitignore` files of the directories being walked, outermost first
+    ignores: Vec<Gitignore>,
+    visit: F,
+    results: WorkspaceResults,
+}
+
+/// A file found while walking a root
+struct WalkedFile<'a> {
+    root: &'a Path,
+    path: &'a Path,
+    /// Path from the root, separated by `/`
+    relative: &'a str,
+}
+
+enum NameMatcher {
+    Glob(Regex),
+    Substring(String),
+}
+
+impl NameMatcher {
+    fn is_match(&self, name: &str) -> bool {
+        match self {
+            NameMatcher::Glob(pattern) => pattern.is_match(name),
+            NameMatcher::Substring(text) => name.to_lowercase().contains(text.as_str()),
+        }
+    }
+}
+
+/// Patterns from one `.gitignore` file
+struct Gitignore {
+    /// Directory of the file, relative to the root
+    base: String,
+    rules: Vec<GitignoreRule>,
+}
+
+struct GitignoreRule {
+    pattern: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl Gitignore {
+    fn read(path: &Path, base: &str) -> Option<Self> {
+        let contents = fs::read_to_string(path).ok()?;
+        Some(Self::parse(&contents, base))
+    }
+
+    fn parse(contents: &str, base: &str) -> Self {
+        let rules = contents
+            .lines()
+            .map(|line| line.trim_end())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (negated, line) = match line.strip_prefix('!') {
+                    Some(rest) => (true, rest),
+                    None => (false, line.strip_prefix('\\').unwrap_or(line)),
+                };
+                let (dir_only, line) = match line.strip_suffix('/') {
+                    Some(rest) => (true, rest),
+                    None => (false, line),
+                };
+                // A slash anywhere but the end anchors the pattern to the .gitignore's directory
+                let pattern = match line.strip_prefix('/') {
+                    Some(rest) => glob_regex(rest, false, "^", "$"),
+                    None if line.contains('/') => glob_regex(line, false, "^", "$"),
+                    None => glob_regex(line, false, "^(?:.*/)?", "$"),
+                };
+                GitignoreRule { pattern, negated, dir_only }
+            })
+            .collect();
+        Self {
+            base: base.to_string(),
+            rules,
+        }
+    }
+
+    /// Whether the last rule matching `path` ignores or re-includes it
+    fn matches(&self, path: &str, is_dir: bool) -> Option<bool> {
+        let path = if self.base.is_empty() { path } else { path.strip_prefix(&self.base)?.strip_prefix('/')? };
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.pattern.is_match(path))
+            .map(|rule| !rule.negated)
+    }
+}
+
+/// Deeper `.gitignore` files take precedence over the ones above them
+fn is_ignored(ignores: &[Gitignore], path: &str, is_dir: bool) -> bool {
+    ignores
+        .iter()
+        .rev()
+        .find_map(|gitignore| gitignore.matches(path, is_dir))
+        .unwrap_or(false)
+}
+
+/// Translate a glob into a regex where `*` and `?` stay within a path component and `**` crosses them
+fn glob_regex(glob: &str, case_insensitive: bool, prefix: &str, suffix: &str) -> Regex {
+    let mut pattern = String::from(prefix);
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    pattern.push_str("(?:.*/)?");
+                } else {
+                    pattern.push_str(".*");
+                }
+            }
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            '[' => {
+                let class: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                let class = class.strip_prefix('!').map_or(class.clone(), |rest| format!("^{}", rest));
+                pattern.push('[');
+                pattern.push_str(&class.replace('\\', "\\\\").replace('[', "\\["));
+                pattern.push(']');
+            }
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    pattern.push_str(&regex::escape(&escaped.to_string()));
+                }
+            }
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push_str(suffix);
+
+    RegexBuilder::new(&pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+        // An unclosed or empty class like `[` is matched literally instead
+        .unwrap_or_else(|_| Regex::new(&format!("{}{}{}", prefix, regex::escape(glob), suffix)).expect("escaped glob"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A workspace with a `.gitignore`, a nested one, a binary file and a large file
+    fn workspace() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("rustlm-search-{}", uuid::Uuid::new_v4()));
+        let files: &[(&str, &[u8])] = &[
+            (".gitignore", b"target/\n*.log\n!keep.log\n"),
+            ("src/main.rs", b"fn main() {\n    // TODO: parse flags\n    run();\n}\n"),
+            ("src/lib.rs", b"pub fn run() {}\n"),
+            ("src/generated/.gitignore", b"*.rs\n"),
+            ("src/generated/out.rs", b"// TODO: generated\n"),
+            ("target/debug/main.rs", b"// TODO: build output\n"),
+            ("debug.log", b"TODO in a log\n"),
+            ("keep.log", b"todo kept\n"),
+            ("image.png", b"\x89PNG\0\0TODO"),
+            ("docs/deep/er/notes.md", b"TODO: deep\n"),
+        ];
+        for (path, contents) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        fs::write(root.join("big.txt"), "TODO\n".repeat(1000)).unwrap();
+        root
+    }
+
+    fn search(root: &Path, configure: impl FnOnce(&mut WorkspaceSearchConfig)) -> WorkspaceSearch {
+        let mut config = WorkspaceSearchConfig {
+            enabled: true,
+            roots: vec![root.to_path_buf()],
+            max_file_size: 1024,
+            ..WorkspaceSearchConfig::default()
+        };
+        configure(&mut config);
+        WorkspaceSearch::new(&config).unwrap()
+    }
+
+    fn paths(results: &WorkspaceResults) -> Vec<&str> {
+        results.matches.iter().map(|m| m.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_find_files_respects_gitignore() {
+        let root = workspace();
+        let results = search(&root, |_| {}).find_files("*.rs");
+        assert_eq!(paths(&results), vec!["src/lib.rs", "src/main.rs"]);
+
+        let results = search(&root, |_| {}).find_files("LOG");
+        assert_eq!(paths(&results), vec!["keep.log"]);
+
+        let results = search(&root, |config| config.respect_gitignore = false).find_files("main.rs");
+        assert_eq!(paths(&results), vec!["src/main.rs", "target/debug/main.rs"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_find_content_with_line_numbers_and_context() {
+        let root = workspace();
+        let results = search(&root, |_| {}).find_content("todo");
+        assert_eq!(paths(&results), vec!["docs/deep/er/notes.md", "keep.log", "src/main.rs"]);
+
+        let found = &results.matches[2];
+        assert_eq!(found.line_number, Some(2));
+        assert_eq!(found.line.as_deref(), Some("    // TODO: parse flags"));
+        assert_eq!(found.before, vec!["fn main() {"]);
+        assert_eq!(found.after, vec!["    run();", "}"]);
+        // big.txt is over the size limit and image.png is binary
+        assert_eq!(results.files_skipped, 2);
+
+        // An uppercase letter makes the search case-sensitive
+        let results = search(&root, |_| {}).find_content("TODO: p");
+        assert_eq!(paths(&results), vec!["src/main.rs"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_depth_and_result_limits() {
+        let root = workspace();
+        let results = search(&root, |config| config.max_depth = 1).find_content("todo");
+        assert_eq!(paths(&results), vec!["keep.log", "src/main.rs"]);
+
+        let results = search(&root, |config| config.max_results = 1).find_content("todo");
+        assert_eq!(results.matches.len(), 1);
+        assert!(results.truncated);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_gitignore_patterns() {
+        let gitignore = Gitignore::parse("/build\ndocs/*.html\n**/cache/**\n[Tt]emp?\n", "");
+        assert_eq!(gitignore.matches("build", true), Some(true));
+        assert_eq!(gitignore.matches("src/build", true), None);
+        assert_eq!(gitignore.matches("docs/index.html", false), Some(true));
+        assert_eq!(gitignore.matches("docs/api/index.html", false), None);
+        assert_eq!(gitignore.matches("a/b/cache/x", false), Some(true));
+        assert_eq!(gitignore.matches("src/Temp1", false), Some(true));
+        assert_eq!(gitignore.matches("src/temp", false), None);
+    }
+}
@@ -0,0 +1,1982 @@
+use axum::{
+    extract::{rejection::JsonRejection, Path, State},
+    http::{header, StatusCode},
+    middleware,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+    routing::{get, post},
+    Extension, Router,
+};
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tower_http::cors::CorsLayer;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::actions::{ActionStore, ActionToken, AuditEntry};
+use crate::auth::{require_auth, Authenticator, Principal};
+use crate::error::ProcessError;
+use crate::metrics::{metrics, METRICS_CONTENT_TYPE};
+use crate::callbacks::{CallbackInfo, CallbackResult, CommandContext};
+use crate::download::Downloader;
+use crate::executor::Execution;
+use crate::nlp::{NlpProcessor, ProcessEvent, ProcessOutput};
+use crate::platform::Platform;
+use crate::ratelimit::{QuotaUsage, RateLimitHandle, RateLimiter};
+use crate::results::{DownloadStatus, TaskResult};
+use crate::sessions::{self, Session, SessionHandle, SessionStore, SessionSummary};
+use crate::shell::ShellCommand;
+use crate::tasks::ProcessOptions;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProcessRequest {
+    pub text: String,
+    pub task: String, // "sentiment", "summarize", "classify", etc.
+    /// Don't run callbacks; `callbacks` in the response is then empty
+    #[serde(default)]
+    pub skip_callbacks: bool,
+    /// Platform to suggest commands for, e.g. `debian` or `macos`; the server's own when unset
+    #[serde(default)]
+    pub platform: Option<Platform>,
+    /// Conversation `ask_ai` continues, started on first use; questions are sent on their own when unset
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// Replaces the configured system prompt for `ask_ai`, and for the rest of the session if one is given
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// Let `get_file_from` download the file on the server, when server-side downloads are enabled
+    #[serde(default)]
+    pub download: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProcessResponse {
+    pub id: Uuid,
+    pub input_text: String,
+    pub task: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    pub result: TaskResult,
+    pub confidence: Option<f32>,
+    /// Results of the callbacks run after the task, in execution order
+    pub callbacks: Vec<CallbackResult>,
+    /// A single-use token for each suggested command, when action tokens are enabled
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<ActionToken>,
+    pub processing_time_ms: u64,
+}
+
+impl ProcessResponse {
+    fn new(
+        id: Uuid,
+        input_text: String,
+        task: String,
+        session_id: Option<String>,
+        output: ProcessOutput,
+        processing_time: std::time::Duration,
+    ) -> Self {
+        Self {
+            id,
+            input_text,
+            task,
+            session_id,
+            result: output.result,
+            confidence: output.confidence,
+            callbacks: output.callbacks,
+            actions: Vec::new(),
+            processing_time_ms: processing_time.as_millis() as u64,
+        }
+    }
+
+    /// Issue action tokens for the suggested commands, bound to the caller
+    fn with_actions(mut self, actions: &ActionStore, principal: &Principal) -> Self {
+        if let Some(suggestion) = self.result.command_suggestion() {
+            self.actions = actions.issue(
+                self.id,
+                &suggestion.command,
+                &self.input_text,
+                &principal.client_id,
+                &suggestion.commands,
+                suggestion.platform,
+            );
+        }
+        self
+    }
+}
+
+/// Result of confirming an action token
+#[derive(Debug, Serialize)]
+pub struct ConfirmResponse {
+    pub token: String,
+    /// The `/process` request the command was suggested in
+    pub request_id: Uuid,
+    pub task: String,
+    pub command: ShellCommand,
+    pub confirmed_by: String,
+    pub confirmed_at: chrono::DateTime<chrono::Utc>,
+    /// Outcome of running the command, when confirmed commands are executed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execution: Option<Execution>,
+    /// Results of the `confirm_action` callbacks
+    pub callbacks: Vec<CallbackResult>,
+}
+
+/// Error body returned by every endpoint; `error` is a stable machine-readable code
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    pub message: String,
+}
+
+impl From<&ProcessError> for ErrorResponse {
+    fn from(error: &ProcessError) -> Self {
+        Self {
+            error: error.code().to_string(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for ProcessError {
+    fn into_response(self) -> Response {
+        let mut response = (self.status_code(), Json(ErrorResponse::from(&self))).into_response();
+        if let Some(retry_after) = self.retry_after() {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, retry_after.as_secs().max(1).into());
+        }
+        response
+    }
+}
+
+/// Unwrap a JSON body, reporting malformed bodies as `invalid_request`
+fn parse_body<T>(payload: Result<Json<T>, JsonRejection>) -> Result<T, ProcessError> {
+    payload
+        .map(|Json(body)| body)
+        .map_err(|rejection| ProcessError::InvalidRequest(rejection.body_text()))
+}
+
+/// Maximum number of items accepted by a single `/process/batch` request
+pub const MAX_BATCH_ITEMS: usize = 10_000;
+/// Number of batch items processed concurrently when the request doesn't say
+pub const DEFAULT_BATCH_PARALLELISM: usize = 8;
+/// Upper bound for the `parallelism` field of a batch request
+pub const MAX_BATCH_PARALLELISM: usize = 64;
+
+/// A single item of a batch request; `task` falls back to the batch-level task
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchItem {
+    pub text: String,
+    #[serde(default)]
+    pub task: Option<String>,
+}
+
+/// Batch request: either explicit `items`, or `texts` processed with one `task`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BatchRequest {
+    #[serde(default)]
+    pub items: Vec<BatchItem>,
+    #[serde(default)]
+    pub texts: Vec<String>,
+    #[serde(default)]
+    pub task: Option<String>,
+    #[serde(default)]
+    pub parallelism: Option<usize>,
+    /// Don't run callbacks for any item
+    #[serde(default)]
+    pub skip_callbacks: bool,
+    /// Platform to suggest commands for in every item
+    #[serde(default)]
+    pub platform: Option<Platform>,
+}
+
+/// Outcome of one batch item, reported at the item's input position
+#[derive(Debug, Serialize)]
+pub struct BatchItemResult {
+    pub index: usize,
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<ProcessResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponse>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchResponse {
+    pub id: Uuid,
+    pub results: Vec<BatchItemResult>,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub processing_time_ms: u64,
+}
+
+/// Rate limit usage returned by `/admin/quotas`
+#[derive(Debug, Serialize)]
+pub struct QuotasResponse {
+    pub enabled: bool,
+    pub quotas: Vec<QuotaUsage>,
+}
+
+#[derive(Clone)]
+pub struct AppState {
+    pub nlp_processor: Arc<NlpProcessor>,
+    pub authenticator: Arc<Authenticator>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub actions: Arc<ActionStore>,
+    pub sessions: Arc<SessionStore>,
+}
+
+impl AppState {
+    /// State for a server without authentication or rate limits
+    pub fn new(nlp_processor: Arc<NlpProcessor>) -> Self {
+        Self {
+            nlp_processor,
+            authenticator: Arc::new(Authenticator::disabled()),
+            rate_limiter: Arc::new(RateLimiter::disabled()),
+            actions: Arc::new(ActionStore::disabled()),
+            sessions: Arc::new(SessionStore::disabled()),
+        }
+    }
+
+    pub fn with_authenticator(mut self, authenticator: Authenticator) -> Self {
+        self.authenticator = Arc::new(authenticator);
+        self
+    }
+
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Arc::new(rate_limiter);
+        self
+    }
+
+    pub fn with_actions(mut self, actions: ActionStore) -> Self {
+        self.actions = Arc::new(actions);
+        self
+    }
+
+    pub fn with_sessions(mut self, sessions: SessionStore) -> Self {
+        self.sessions = Arc::new(sessions);
+        self
+    }
+}
+
+/// Build the HTTP router for the given application state
+pub fn create_app(state: AppState) -> Router {
+    // Everything but the health check requires credentials when auth is enabled
+    let protected = Router::new()
+        .route("/process", post(process_text))
+        .route("/process/batch", post(process_batch))
+        .route("/process/:task", post(process_text_with_task))
+        .route("/process/:task/stream", post(process_text_stream))
+        .route("/actions/:token/confirm", post(confirm_action))
+        .route("/downloads/:id", get(download_status))
+        .route("/sessions", get(list_sessions))
+        .route("/sessions/:id", get(get_session).delete(delete_session))
+        .route("/models", get(list_available_models))
+        .route("/admin/quotas", get(list_quotas))
+        .route("/admin/callbacks", get(list_callbacks))
+        .route("/admin/actions", get(list_actions))
+        .route("/admin/downloads", get(list_downloads))
+        .route("/admin/sessions", get(list_all_sessions))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(&state.authenticator),
+            require_auth,
+        ));
+
+    Router::new()
+        .route("/", get(health_check))
+        .route("/health", get(health_check))
+        .route("/metrics", get(render_metrics))
+        .merge(protected)
+        .layer(CorsLayer::permissive())
+        .with_state(state)
+}
+
+async fn health_check() -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "status": "healthy",
+        "service": "rustlm-server",
+        "version": "0.1.0"
+    }))
+}
+
+async fn render_metrics() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, METRICS_CONTENT_TYPE)], metrics().render())
+}
+
+/// Metrics label for a requested task, so arbitrary names don't create new series
+fn task_label(state: &AppState, task: &str) -> String {
+    state
+        .nlp_processor
+        .registry()
+        .get(task)
+        .map_or_else(|| "unknown".to_string(), |task| task.name().to_string())
+}
+
+/// Options for processing a request on behalf of `principal`
+fn process_options(state: &AppState, principal: &Principal, request: &ProcessRequest) -> Result<ProcessOptions, ProcessError> {
+    let session = match &request.session_id {
+        Some(id) => {
+            sessions::validate_session_id(id)?;
+            Some(SessionHandle::new(Arc::clone(&state.sessions), &principal.client_id, id))
+        }
+        None => None,
+    };
+    Ok(ProcessOptions {
+        skip_callbacks: request.skip_callbacks,
+        platform: request.platform,
+        access: principal.access.clone(),
+        rate_limit: state
+            .rate_limiter
+            .is_enabled()
+            .then(|| RateLimitHandle::new(Arc::clone(&state.rate_limiter), &principal.client_id)),
+        session,
+        system_prompt: request.system_prompt.clone(),
+        download: request.download,
+        client_id: Some(principal.client_id.clone()),
+    })
+}
+
+async fn process_text(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    payload: Result<Json<ProcessRequest>, JsonRejection>,
+) -> Result<Json<ProcessResponse>, ProcessError> {
+    run_process(&state, &principal, parse_body(payload)?).await.map(Json)
+}
+
+/// Process a single request, shared by `/process` and `/process/batch`
+async fn run_process(
+    state: &AppState,
+    principal: &Principal,
+    request: ProcessRequest,
+) -> Result<ProcessResponse, ProcessError> {
+    let start_time = std::time::Instant::now();
+    let request_id = Uuid::new_v4();
+
+    info!(
+        "Processing request {} from {} for task: {}, text length: {}",
+        request_id,
+        principal.name,
+        request.task,
+        request.text.len()
+    );
+
+    let outcome = async {
+        let options = process_options(state, principal, &request)?;
+        state.nlp_processor.process_with(&request.text, &request.task, &options).await
+    }
+    .await;
+    metrics().observe_request(&task_label(state, &request.task), start_time.elapsed(), outcome.as_ref().err());
+
+    match outcome {
+        Ok(output) => {
+            let processing_time = start_time.elapsed();
+
+            info!(
+                "Request {} completed in {}ms",
+                request_id,
+                processing_time.as_millis()
+            );
+
+            Ok(ProcessResponse::new(request_id, request.text, request.task, request.session_id, output, processing_time)
+                .with_actions(&state.actions, principal))
+        }
+        Err(e) => {
+            warn!("Processing failed for request {}: {}", request_id, e);
+            Err(e)
+        }
+    }
+}
+
+async fn process_batch(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    payload: Result<Json<BatchRequest>, JsonRejection>,
+) -> Result<Json<BatchResponse>, ProcessError> {
+    let batch = parse_body(payload)?;
+    let start_time = std::time::Instant::now();
+    let batch_id = Uuid::new_v4();
+
+    if batch.items.is_empty() && batch.texts.is_empty() {
+        return Err(ProcessError::InvalidRequest(
+            "Batch must contain 'items' or 'texts'".to_string(),
+        ));
+    }
+    if !batch.texts.is_empty() && batch.task.is_none() {
+        return Err(ProcessError::InvalidRequest(
+            "'task' is required when 'texts' is used".to_string(),
+        ));
+    }
+
+    let default_task = batch.task;
+    let skip_callbacks = batch.skip_callbacks;
+    let platform = batch.platform;
+    let items: Vec<BatchItem> = batch
+        .items
+        .into_iter()
+        .chain(batch.texts.into_iter().map(|text| BatchItem { text, task: None }))
+        .collect();
+
+    if items.len() > MAX_BATCH_ITEMS {
+        return Err(ProcessError::PayloadTooLarge(format!(
+            "Batch contains {} items, the maximum is {}",
+            items.len(),
+            MAX_BATCH_ITEMS
+        )));
+    }
+
+    let parallelism = batch
+        .parallelism
+        .unwrap_or(DEFAULT_BATCH_PARALLELISM)
+        .clamp(1, MAX_BATCH_PARALLELISM);
+
+    info!(
+        "Processing batch {} with {} items, parallelism: {}",
+        batch_id,
+        items.len(),
+        parallelism
+    );
+
+    // `buffered` runs up to `parallelism` items at once and yields them in input order
+    let results: Vec<BatchItemResult> = stream::iter(items.into_iter().enumerate())
+        .map(|(index, item)| {
+            let state = &state;
+            let principal = &principal;
+            let task = item.task.or_else(|| default_task.clone());
+            async move {
+                let outcome = match task {
+                    Some(task) => {
+                        let request = ProcessRequest {
+                            text: item.text,
+                            task,
+                            skip_callbacks,
+                            platform,
+                            session_id: None,
+                            system_prompt: None,
+                            download: false,
+                        };
+                        run_process(state, principal, request).await
+                    }
+                    None => Err(ProcessError::InvalidRequest(
+                        "Missing 'task' for batch item".to_string(),
+                    )),
+                };
+
+                match outcome {
+                    Ok(response) => BatchItemResult {
+                        index,
+                        status: "ok".to_string(),
+                        response: Some(response),
+                        error: None,
+                    },
+                    Err(error) => BatchItemResult {
+                        index,
+                        status: "error".to_string(),
+                        response: None,
+                        error: Some(ErrorResponse::from(&error)),
+                    },
+                }
+            }
+        })
+        .buffered(parallelism)
+        .collect()
+        .await;
+
+    let succeeded = results.iter().filter(|r| r.error.is_none()).count();
+    let failed = results.len() - succeeded;
+    let processing_time = start_time.elapsed();
+
+    info!(
+        "Batch {} completed in {}ms: {} succeeded, {} failed",
+        batch_id,
+        processing_time.as_millis(),
+        succeeded,
+        failed
+    );
+
+    Ok(Json(BatchResponse {
+        id: batch_id,
+        results,
+        succeeded,
+        failed,
+        processing_time_ms: processing_time.as_millis() as u64,
+    }))
+}
+
+/// An optional string field of a task-specific body
+fn optional_string(body: &serde_json::Value, field: &str) -> Result<Option<String>, ProcessError> {
+    match body.get(field) {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(value) => value
+            .as_str()
+            .map(|value| Some(value.to_string()))
+            .ok_or_else(|| ProcessError::InvalidRequest(format!("'{}' must be a string", field))),
+    }
+}
+
+/// An optional boolean field of a task-specific body, `false` when missing
+fn optional_bool(body: &serde_json::Value, field: &str) -> Result<bool, ProcessError> {
+    match body.get(field) {
+        None | Some(serde_json::Value::Null) => Ok(false),
+        Some(value) => value
+            .as_bool()
+            .ok_or_else(|| ProcessError::InvalidRequest(format!("'{}' must be a boolean", field))),
+    }
+}
+
+/// Build a request from a task-specific body, which needs `text` and may set `skip_callbacks`,
+/// `platform`, `session_id`, `system_prompt` and `download`
+fn request_from_body(
+    task: String,
+    payload: Result<Json<serde_json::Value>, JsonRejection>,
+) -> Result<ProcessRequest, ProcessError> {
+    let body = parse_body(payload)?;
+    let text = body
+        .get("text")
+        .and_then(|t| t.as_str())
+        .map(|t| t.to_string())
+        .ok_or_else(|| ProcessError::InvalidRequest("Missing 'text' field in request body".to_string()))?;
+    let skip_callbacks = optional_bool(&body, "skip_callbacks")?;
+    let platform = optional_string(&body, "platform")?
+        .map(|platform| platform.parse())
+        .transpose()
+        .map_err(|e: anyhow::Error| ProcessError::InvalidRequest(e.to_string()))?;
+
+    Ok(ProcessRequest {
+        text,
+        task,
+        skip_callbacks,
+        platform,
+        session_id: optional_string(&body, "session_id")?,
+        system_prompt: optional_string(&body, "system_prompt")?,
+        download: optional_bool(&body, "download")?,
+    })
+}
+
+async fn process_text_with_task(
+    Path(task): Path<String>,
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    payload: Result<Json<serde_json::Value>, JsonRejection>,
+) -> Result<Json<ProcessResponse>, ProcessError> {
+    let request = request_from_body(task, payload)?;
+    run_process(&state, &principal, request).await.map(Json)
+}
+
+/// Stream a task's output as server-sent events.
+///
+/// Emits `delta` events with `{"content": ...}` while the task runs, then a single
+/// `done` event carrying the `ProcessResponse`, or an `error` event carrying an `ErrorResponse`.
+async fn process_text_stream(
+    Path(task): Path<String>,
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    payload: Result<Json<serde_json::Value>, JsonRejection>,
+) -> Result<Sse<BoxStream<'static, Result<Event, Infallible>>>, ProcessError> {
+    let request = request_from_body(task, payload)?;
+    let start_time = std::time::Instant::now();
+    let request_id = Uuid::new_v4();
+
+    info!(
+        "Streaming request {} from {} for task: {}, text length: {}",
+        request_id,
+        principal.name,
+        request.task,
+        request.text.len()
+    );
+
+    let label = task_label(&state, &request.task);
+    let options = process_options(&state, &principal, &request)?;
+    let events = state
+        .nlp_processor
+        .process_stream(&request.text, &request.task, &options)
+        .await
+        .map_err(|e| {
+            warn!("Processing failed for request {}: {}", request_id, e);
+            metrics().observe_request(&label, start_time.elapsed(), Some(&e));
+            e
+        })?;
+
+    let events = events
+        .map(move |event| {
+            let event = match event {
+                Ok(ProcessEvent::Delta(content)) => {
+                    sse_event("delta", &serde_json::json!({ "content": content }))
+                }
+                Ok(ProcessEvent::Done(output)) => {
+                    let processing_time = start_time.elapsed();
+                    info!(
+                        "Request {} completed in {}ms",
+                        request_id,
+                        processing_time.as_millis()
+                    );
+                    metrics().observe_request(&label, processing_time, None);
+                    let response = ProcessResponse::new(
+                        request_id,
+                        request.text.clone(),
+                        request.task.clone(),
+                        request.session_id.clone(),
+                        *output,
+                        processing_time,
+                    )
+                    .with_actions(&state.actions, &principal);
+                    sse_event("done", &response)
+                }
+                Err(e) => {
+                    warn!("Streaming failed for request {}: {}", request_id, e);
+                    let error = ProcessError::from(e);
+                    metrics().observe_request(&label, start_time.elapsed(), Some(&error));
+                    sse_event("error", &ErrorResponse::from(&error))
+                }
+            };
+            Ok(event)
+        })
+        .boxed();
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+fn sse_event(name: &str, data: &impl Serialize) -> Event {
+    Event::default()
+        .event(name)
+        .json_data(data)
+        .unwrap_or_else(|_| Event::default().event("error"))
+}
+
+async fn list_available_models(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Json<serde_json::Value> {
+    // Only list the tasks the caller is allowed to run
+    let registry = state.nlp_processor.registry();
+    let allowed = |name: &str| {
+        registry
+            .get(name)
+            .is_some_and(|task| principal.access.allows(task.as_ref()))
+    };
+    let models: Vec<String> = state
+        .nlp_processor
+        .list_available_tasks()
+        .into_iter()
+        .filter(|name| allowed(name))
+        .collect();
+    let tasks: Vec<_> = state
+        .nlp_processor
+        .describe_tasks()
+        .into_iter()
+        .filter(|info| allowed(&info.name))
+        .collect();
+
+    Json(serde_json::json!({
+        "available_tasks": models,
+        "tasks": tasks,
+        "description": "List of available NLP processing tasks"
+    }))
+}
+
+/// Report every client's rate limit usage; only available to admin callers
+async fn list_quotas(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<QuotasResponse>, ProcessError> {
+    if !principal.admin {
+        return Err(ProcessError::Forbidden("Admin access required".to_string()));
+    }
+
+    Ok(Json(QuotasResponse {
+        enabled: state.rate_limiter.is_enabled(),
+        quotas: state.rate_limiter.usage(),
+    }))
+}
+
+/// Run a suggested command the caller confirmed, using up its action token
+///
+/// The command is executed when `actions.execute` is set, then handed to the callbacks
+/// registered for `confirm_action`.
+async fn confirm_action(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<ConfirmResponse>, ProcessError> {
+    // The caller may have lost access to the task since the command was suggested, or the task
+    // may be gone altogether
+    let registry = state.nlp_processor.registry();
+    let action = state.actions.confirm(&token, &principal, |task| {
+        registry.get(task).is_some_and(|task| principal.access.allows(task.as_ref()))
+    })?;
+
+    let execution = state.actions.execute(&action).await;
+    let confirmed_at = chrono::Utc::now();
+    let context = CommandContext {
+        command: "confirm_action".to_string(),
+        task: action.task.clone(),
+        input_text: action.input_text.clone(),
+        parsed_result: serde_json::json!({
+            "token": token,
+            "request_id": action.request_id,
+            "command": action.command,
+            "suggested_command": action.command.render(),
+            "confirmed_by": principal.name,
+            "execution": execution,
+        }),
+        confidence: None,
+        timestamp: confirmed_at,
+        session_id: None,
+        previous: None,
+    };
+    let callbacks = state
+        .nlp_processor
+        .callbacks()
+        .execute_callback(&context)
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Failed to run confirm_action callbacks: {}", e);
+            Vec::new()
+        });
+
+    Ok(Json(ConfirmResponse {
+        token,
+        request_id: action.request_id,
+        task: action.task,
+        command: action.command,
+        confirmed_by: principal.name,
+        confirmed_at,
+        execution,
+        callbacks,
+    }))
+}
+
+/// Progress of a download started by `get_file_from`
+async fn download_status(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<DownloadStatus>, ProcessError> {
+    if let Some(task) = state.nlp_processor.registry().get("get_file_from") {
+        if !principal.access.allows(task.as_ref()) {
+            return Err(ProcessError::Forbidden("Not allowed to run task: get_file_from".to_string()));
+        }
+    }
+
+    state
+        .nlp_processor
+        .downloader()
+        .zip(Uuid::parse_str(&id).ok())
+        .and_then(|(downloader, id)| downloader.status(id))
+        // Other clients' downloads are as unknown as missing ones
+        .filter(|status| status.client_id.as_deref().is_none_or(|client_id| client_id == principal.client_id))
+        .map(Json)
+        .ok_or_else(|| ProcessError::NotFound(format!("Unknown download {}", id)))
+}
+
+/// List the caller's conversation sessions, most recently used first
+async fn list_sessions(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Json<Vec<SessionSummary>> {
+    Json(state.sessions.list(Some(&principal.client_id)))
+}
+
+/// Fetch one of the caller's sessions with its history
+async fn get_session(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<Session>, ProcessError> {
+    state
+        .sessions
+        .get(&principal.client_id, &id)
+        .map(Json)
+        .ok_or_else(|| ProcessError::NotFound(format!("Unknown session {}", id)))
+}
+
+/// Forget one of the caller's sessions
+async fn delete_session(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<StatusCode, ProcessError> {
+    if state.sessions.delete(&principal.client_id, &id) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ProcessError::NotFound(format!("Unknown session {}", id)))
+    }
+}
+
+/// List every client's sessions, most recently used first; only available to admin callers
+async fn list_all_sessions(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<Vec<SessionSummary>>, ProcessError> {
+    if !principal.admin {
+        return Err(ProcessError::Forbidden("Admin access required".to_string()));
+    }
+
+    Ok(Json(state.sessions.list(None)))
+}
+
+/// List running and recently finished downloads, oldest first; only available to admin callers
+async fn list_downloads(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<Vec<DownloadStatus>>, ProcessError> {
+    if !principal.admin {
+        return Err(ProcessError::Forbidden("Admin access required".to_string()));
+    }
+
+    Ok(Json(state.nlp_processor.downloader().map(Downloader::list).unwrap_or_default()))
+}
+
+/// List confirmation attempts, oldest first; only available to admin callers
+async fn list_actions(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<Vec<AuditEntry>>, ProcessError> {
+    if !principal.admin {
+        return Err(ProcessError::Forbidden("Admin access required".to_string()));
+    }
+
+    Ok(Json(state.actions.audit_log()))
+}
+
+/// List the registered callbacks in the order they run; only available to admin callers
+async fn list_callbacks(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<Vec<CallbackInfo>>, ProcessError> {
+    if !principal.admin {
+        return Err(ProcessError::Forbidden("Admin access required".to_string()));
+    }
+
+    Ok(Json(state.nlp_processor.callbacks().list()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use axum::http::Request;
+    use tower::ServiceExt; // for `oneshot`
+    use crate::commands::AskAiTask;
+    use crate::llm::{AzureOpenAiConfig, LlmClient, LlmClientOptions};
+    use crate::tasks::TaskRegistry;
+
+    /// Start a mock Azure OpenAI server answering "Hello world", streamed or not, or failing for "fail"
+    ///
+    /// It also serves the OpenAI-style `/v1/chat/completions`, answering with the requested model.
+    async fn spawn_mock_azure() -> String {
+        async fn chat_completions(Json(body): Json<serde_json::Value>) -> Response {
+            let messages = body["messages"].as_array().cloned().unwrap_or_default();
+            let question = messages.last().map_or(serde_json::Value::Null, |message| message["content"].clone());
+            if question == "fail" {
+                (axum::http::StatusCode::INTERNAL_SERVER_ERROR, "deployment unavailable").into_response()
+            } else if question == "busy" {
+                let headers = [("retry-after", "120")];
+                (axum::http::StatusCode::TOO_MANY_REQUESTS, headers, "slow down").into_response()
+            } else if body["stream"] == true {
+                let events = concat!(
+                    "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\n",
+                    "data: {\"choices\":[{\"delta\":{\"content\":\"Hello\"}}]}\n\n",
+                    "data: {\"choices\":[{\"delta\":{\"content\":\" world\"}}]}\n\n",
+                    "data: [DONE]\n\n",
+                );
+                ([("content-type", "text/event-stream")], events).into_response()
+            } else if question == "history" {
+                // Answer with the conversation the question was sent with
+                let contents: Vec<&str> = messages.iter().filter_map(|message| message["content"].as_str()).collect();
+                Json(serde_json::json!({
+                    "choices": [{ "message": { "role": "assistant", "content": contents.join(" | ") } }]
+                }))
+                .into_response()
+            } else {
+                Json(serde_json::json!({
+                    "choices": [{ "message": { "role": "assistant", "content": "Hello world" } }]
+                }))
+                .into_response()
+            }
+        }
+
+        async fn openai_chat_completions(headers: axum::http::HeaderMap, Json(body): Json<serde_json::Value>) -> Response {
+            if headers.get("authorization").is_none_or(|value| value != "Bearer local-key") {
+                return (axum::http::StatusCode::UNAUTHORIZED, "missing bearer token").into_response();
+            }
+            let answer = format!("Hello from {}", body["model"].as_str().unwrap_or("no model"));
+            Json(serde_json::json!({
+                "choices": [{ "message": { "role": "assistant", "content": answer } }]
+            }))
+            .into_response()
+        }
+
+        let app = Router::new()
+            .route("/openai/deployments/:deployment/chat/completions", post(chat_completions))
+            .route("/v1/chat/completions", post(openai_chat_completions));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", address)
+    }
+
+    /// App whose `ask_ai` task talks to the mock Azure OpenAI server
+    async fn create_mock_ai_app() -> Router {
+        let endpoint = spawn_mock_azure().await;
+        let mut registry = TaskRegistry::with_builtin_tasks();
+        let client = LlmClient::new(LlmClientOptions {
+            retry_backoff: std::time::Duration::from_millis(1),
+            ..LlmClientOptions::default()
+        });
+        let provider = AzureOpenAiConfig::new(&endpoint, "test-key", "gpt").with_client(Arc::new(client));
+        registry.register(AskAiTask::with_provider(Arc::new(provider)));
+        let nlp_processor = Arc::new(NlpProcessor::with_registry(registry).await.unwrap());
+        create_app(AppState::new(nlp_processor))
+    }
+
+    /// App requiring auth, with an unrestricted key and a key limited to NLP tasks
+    async fn create_auth_app() -> Router {
+        use crate::config::{ApiKeyConfig, AuthConfig};
+
+        let authenticator = Authenticator::from_config(&AuthConfig {
+            enabled: true,
+            token_secret: None,
+            api_keys: vec![
+                ApiKeyConfig {
+                    name: "admin".to_string(),
+                    key: "admin-key".to_string(),
+                    allowed_tasks: None,
+                    admin: true,
+                },
+                ApiKeyConfig {
+                    name: "nlp-only".to_string(),
+                    key: "nlp-key".to_string(),
+                    allowed_tasks: Some(vec!["nlp".to_string()]),
+                    admin: false,
+                },
+            ],
+        });
+        let nlp_processor = Arc::new(NlpProcessor::new().await.unwrap());
+        create_app(AppState::new(nlp_processor).with_authenticator(authenticator))
+    }
+
+    /// `create_auth_app` with room for two local requests and one `ask_ai` request per key
+    async fn create_rate_limited_app() -> Router {
+        use crate::config::{ApiKeyConfig, AuthConfig, ClassLimitConfig, RateLimitConfig};
+
+        let authenticator = Authenticator::from_config(&AuthConfig {
+            enabled: true,
+            token_secret: None,
+            api_keys: vec![
+                ApiKeyConfig {
+                    name: "admin".to_string(),
+                    key: "admin-key".to_string(),
+                    allowed_tasks: None,
+                    admin: true,
+                },
+                ApiKeyConfig {
+                    name: "client".to_string(),
+                    key: "client-key".to_string(),
+                    allowed_tasks: None,
+                    admin: false,
+                },
+            ],
+        });
+        let rate_limiter = RateLimiter::from_config(&RateLimitConfig {
+            enabled: true,
+            local: ClassLimitConfig {
+                requests_per_minute: 1,
+                burst: 2,
+                daily_quota: None,
+            },
+            llm: ClassLimitConfig {
+                requests_per_minute: 60,
+                burst: 10,
+                daily_quota: Some(1),
+            },
+        });
+        let nlp_processor = Arc::new(NlpProcessor::new().await.unwrap());
+        create_app(
+            AppState::new(nlp_processor)
+                .with_authenticator(authenticator)
+                .with_rate_limiter(rate_limiter),
+        )
+    }
+
+    async fn post_with_key(app: Router, uri: &str, key: Option<&str>, body: serde_json::Value) -> (u16, serde_json::Value) {
+        let mut request = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("content-type", "application/json");
+        if let Some(key) = key {
+            request = request.header("x-api-key", key);
+        }
+        let response = app
+            .oneshot(request.body(Body::from(body.to_string())).unwrap())
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    /// Split a server-sent event body into (event, data) pairs
+    fn parse_sse(body: &str) -> Vec<(String, serde_json::Value)> {
+        body.split("\n\n")
+            .filter_map(|block| {
+                let event = block.lines().find_map(|l| l.strip_prefix("event: "))?;
+                let data = block.lines().find_map(|l| l.strip_prefix("data: "))?;
+                Some((event.to_string(), serde_json::from_str(data).unwrap()))
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_health_check() {
+        let nlp_processor = Arc::new(NlpProcessor::new().await.unwrap());
+        let state = AppState::new(nlp_processor);
+        let app = create_app(state);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/health")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["status"], "healthy");
+    }
+
+    #[tokio::test]
+    async fn test_process_sentiment() {
+        let nlp_processor = Arc::new(NlpProcessor::new().await.unwrap());
+        let state = AppState::new(nlp_processor);
+        let app = create_app(state);
+
+        let request_body = serde_json::json!({ "text": "I love Rust!", "task": "sentiment" });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/process")
+                    .header("content-type", "application/json")
+                    .body(Body::from(request_body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(json["task"], "sentiment");
+        assert!(json["result"].to_string().contains("positive"));
+    }
+
+    #[tokio::test]
+    async fn test_process_task_specific_endpoint() {
+        let nlp_processor = Arc::new(NlpProcessor::new().await.unwrap());
+        let state = AppState::new(nlp_processor);
+        let app = create_app(state);
+
+        let request_body = serde_json::json!({ "text": "This is terrible!" });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/process/sentiment")
+                    .header("content-type", "application/json")
+                    .body(Body::from(request_body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(json["task"], "sentiment");
+        assert!(json["result"].to_string().contains("negative"));
+    }
+
+    #[tokio::test]
+    async fn test_process_for_requested_platform() {
+        let nlp_processor = Arc::new(NlpProcessor::new().await.unwrap());
+        let app = create_app(AppState::new(nlp_processor));
+
+        let body = serde_json::json!({ "text": "ripgrep", "task": "install", "platform": "ubuntu" });
+        let (status, json) = post_with_key(app.clone(), "/process", None, body).await;
+        assert_eq!(status, 200);
+        assert_eq!(json["result"]["platform"], "debian");
+        assert_eq!(json["result"]["suggested_commands"][0], "apt install ripgrep");
+
+        let body = serde_json::json!({ "text": "Safari", "platform": "macos" });
+        let (status, json) = post_with_key(app.clone(), "/process/open_app", None, body).await;
+        assert_eq!(status, 200);
+        assert_eq!(json["result"]["suggested_commands"][0], "open -a Safari");
+
+        let body = serde_json::json!({ "text": "ripgrep", "platform": "beos" });
+        let (status, json) = post_with_key(app, "/process/install", None, body).await;
+        assert_eq!(status, 400);
+        assert!(json["message"].as_str().unwrap().contains("Unknown platform"));
+    }
+
+    #[tokio::test]
+    async fn test_process_returns_callbacks() {
+        let nlp_processor = Arc::new(NlpProcessor::new().await.unwrap());
+        let app = create_app(AppState::new(nlp_processor));
+
+        let body = serde_json::json!({ "text": "ripgrep", "task": "install" });
+        let (status, json) = post_with_key(app.clone(), "/process", None, body).await;
+        assert_eq!(status, 200);
+        let callbacks = json["callbacks"].as_array().unwrap();
+        assert!(callbacks.iter().any(|c| c["data"]["action"] == "install_ready"));
+
+        let body = serde_json::json!({ "text": "ripgrep", "skip_callbacks": true });
+        let (status, json) = post_with_key(app.clone(), "/process/install", None, body).await;
+        assert_eq!(status, 200);
+        assert_eq!(json["callbacks"], serde_json::json!([]));
+
+        let body = serde_json::json!({ "text": "ripgrep", "skip_callbacks": "yes" });
+        let (status, json) = post_with_key(app, "/process/install", None, body).await;
+        assert_eq!(status, 400);
+        assert_eq!(json["error"], "invalid_request");
+    }
+
+    #[tokio::test]
+    async fn test_process_result_is_json_object() {
+        let nlp_processor = Arc::new(NlpProcessor::new().await.unwrap());
+        let state = AppState::new(nlp_processor);
+        let app = create_app(state);
+
+        let request_body = serde_json::json!({ "text": r#"it's "quoted" \ text"#, "task": "find_content" });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/process")
+                    .header("content-type", "application/json")
+                    .body(Body::from(request_body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert!(json["result"].is_object());
+        assert_eq!(json["result"]["command"], "find_content");
+        assert_eq!(json["result"]["search_term"], r#"it's "quoted" \ text"#);
+    }
+
+    #[tokio::test]
+    async fn test_process_batch_items() {
+        let nlp_processor = Arc::new(NlpProcessor::new().await.unwrap());
+        let state = AppState::new(nlp_processor);
+        let app = create_app(state);
+
+        let request_body = serde_json::json!({
+            "items": [
+                { "text": "I love Rust!", "task": "sentiment" },
+                { "text": "Test text", "task": "invalid_task" },
+                { "text": "", "task": "sentiment" },
+                { "text": "ripgrep", "task": "install" }
+            ],
+            "parallelism": 2
+        });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/process/batch")
+                    .header("content-type", "application/json")
+                    .body(Body::from(request_body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(json["succeeded"], 2);
+        assert_eq!(json["failed"], 2);
+        let results = json["results"].as_array().unwrap();
+        let statuses: Vec<&str> = results.iter().map(|r| r["status"].as_str().unwrap()).collect();
+        assert_eq!(statuses, vec!["ok", "error", "error", "ok"]);
+        for (index, result) in results.iter().enumerate() {
+            assert_eq!(result["index"], index);
+        }
+        assert_eq!(results[0]["response"]["result"]["sentiment"], "positive");
+        assert_eq!(results[1]["error"]["error"], "unknown_task");
+        assert_eq!(results[2]["error"]["error"], "validation_failed");
+        assert_eq!(results[3]["response"]["result"]["package"], "ripgrep");
+    }
+
+    #[tokio::test]
+    async fn test_process_batch_texts_with_shared_task() {
+        let nlp_processor = Arc::new(NlpProcessor::new().await.unwrap());
+        let state = AppState::new(nlp_processor);
+        let app = create_app(state);
+
+        let texts: Vec<String> = (0..50)
+            .map(|i| if i % 2 == 0 { format!("great day {}", i) } else { format!("awful day {}", i) })
+            .collect();
+        let request_body = serde_json::json!({ "texts": texts, "task": "sentiment" });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/process/batch")
+                    .header("content-type", "application/json")
+                    .body(Body::from(request_body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(json["succeeded"], 50);
+        for (i, result) in json["results"].as_array().unwrap().iter().enumerate() {
+            assert_eq!(result["response"]["input_text"], texts[i]);
+            let expected = if i % 2 == 0 { "positive" } else { "negative" };
+            assert_eq!(result["response"]["result"]["sentiment"], expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_process_batch_requires_task_for_texts() {
+        let nlp_processor = Arc::new(NlpProcessor::new().await.unwrap());
+        let state = AppState::new(nlp_processor);
+        let app = create_app(state);
+
+        let request_body = serde_json::json!({ "texts": ["hello"] });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/process/batch")
+                    .header("content-type", "application/json")
+                    .body(Body::from(request_body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 400);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"], "invalid_request");
+    }
+
+    #[tokio::test]
+    async fn test_ask_ai_stream() {
+        let app = create_mock_ai_app().await;
+
+        let request_body = serde_json::json!({ "text": "Say hello" });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/process/ask_ai/stream")
+                    .header("content-type", "application/json")
+                    .body(Body::from(request_body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let events = parse_sse(std::str::from_utf8(&body).unwrap());
+
+        let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["delta", "delta", "done"]);
+        assert_eq!(events[0].1["content"], "Hello");
+        assert_eq!(events[1].1["content"], " world");
+
+        let done = &events[2].1;
+        assert_eq!(done["task"], "ask_ai");
+        assert_eq!(done["input_text"], "Say hello");
+        assert_eq!(done["result"]["status"], "completed");
+        assert_eq!(done["result"]["answer"], "Hello world");
+        assert!(done["id"].is_string());
+        assert!(done["processing_time_ms"].is_number());
+    }
+
+    #[tokio::test]
+    async fn test_ask_ai_without_streaming_uses_same_endpoint() {
+        let app = create_mock_ai_app().await;
+
+        let request_body = serde_json::json!({ "text": "Say hello" });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/process/ask_ai")
+                    .header("content-type", "application/json")
+                    .body(Body::from(request_body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["result"]["answer"], "Hello world");
+        assert_eq!(json["result"]["source"], "azure_openai");
+    }
+
+    #[tokio::test]
+    async fn test_stream_non_streaming_task_sends_single_done_event() {
+        let nlp_processor = Arc::new(NlpProcessor::new().await.unwrap());
+        let app = create_app(AppState::new(nlp_processor));
+
+        let request_body = serde_json::json!({ "text": "I love this!" });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/process/sentiment/stream")
+                    .header("content-type", "application/json")
+                    .body(Body::from(request_body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let events = parse_sse(std::str::from_utf8(&body).unwrap());
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].0, "done");
+        assert_eq!(events[0].1["result"]["sentiment"], "positive");
+    }
+
+    #[tokio::test]
+    async fn test_auth_requires_credentials() {
+        let app = create_auth_app().await;
+        let body = serde_json::json!({ "text": "I love Rust!", "task": "sentiment" });
+
+        let (status, json) = post_with_key(app.clone(), "/process", None, body.clone()).await;
+        assert_eq!(status, 401);
+        assert_eq!(json["error"], "unauthorized");
+
+        let (status, json) = post_with_key(app.clone(), "/process", Some("wrong-key"), body.clone()).await;
+        assert_eq!(status, 401);
+        assert_eq!(json["error"], "unauthorized");
+
+        let (status, _) = post_with_key(app.clone(), "/process", Some("nlp-key"), body).await;
+        assert_eq!(status, 200);
+
+        // The health check stays open
+        let response = app
+            .oneshot(Request::builder().uri("/health").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_auth_enforces_allowed_tasks() {
+        let app = create_auth_app().await;
+
+        let body = serde_json::json!({ "text": "ripgrep" });
+        let (status, json) = post_with_key(app.clone(), "/process/install", Some("nlp-key"), body.clone()).await;
+        assert_eq!(status, 403);
+        assert_eq!(json["error"], "forbidden");
+
+        let (status, _) = post_with_key(app.clone(), "/process/install", Some("admin-key"), body).await;
+        assert_eq!(status, 200);
+
+        // natural_language is an NLP task, but may not dispatch to a system task for this key
+        let body = serde_json::json!({ "text": "install ripgrep" });
+        let (status, json) = post_with_key(app, "/process/natural_language", Some("nlp-key"), body).await;
+        assert_eq!(status, 403);
+        assert_eq!(json["error"], "forbidden");
+    }
+
+    #[tokio::test]
+    async fn test_models_lists_only_allowed_tasks() {
+        let app = create_auth_app().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/models")
+                    .header("authorization", "Bearer nlp-key")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let tasks = json["available_tasks"].as_array().unwrap();
+        assert!(tasks.contains(&serde_json::json!("sentiment")));
+        assert!(!tasks.contains(&serde_json::json!("install")));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_returns_429_with_retry_after() {
+        let app = create_rate_limited_app().await;
+        let body = serde_json::json!({ "text": "I love Rust!" });
+
+        for _ in 0..2 {
+            let (status, _) = post_with_key(app.clone(), "/process/sentiment", Some("client-key"), body.clone()).await;
+            assert_eq!(status, 200);
+        }
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/process/sentiment")
+                    .header("content-type", "application/json")
+                    .header("x-api-key", "client-key")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 429);
+        assert_eq!(response.headers()["retry-after"], "60");
+
+        // ask_ai is limited separately, by its daily quota
+        let question = serde_json::json!({ "text": "What is Rust?" });
+        let (status, _) = post_with_key(app.clone(), "/process/ask_ai", Some("client-key"), question.clone()).await;
+        assert_eq!(status, 200);
+        let (status, json) = post_with_key(app.clone(), "/process/ask_ai", Some("client-key"), question).await;
+        assert_eq!(status, 429);
+        assert_eq!(json["error"], "quota_exceeded");
+
+        // Other keys have their own allowance
+        let (status, _) = post_with_key(app.clone(), "/process/sentiment", Some("admin-key"), body).await;
+        assert_eq!(status, 200);
+    }
+
+    #[tokio::test]
+    async fn test_admin_quotas() {
+        let app = create_rate_limited_app().await;
+        let body = serde_json::json!({ "text": "I love Rust!" });
+        post_with_key(app.clone(), "/process/sentiment", Some("client-key"), body).await;
+
+        let get_quotas = |key: &str| {
+            Request::builder()
+                .uri("/admin/quotas")
+                .header("x-api-key", key)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(get_quotas("client-key")).await.unwrap();
+        assert_eq!(response.status(), 403);
+
+        let response = app.oneshot(get_quotas("admin-key")).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["enabled"], true);
+        let quotas = json["quotas"].as_array().unwrap();
+        assert_eq!(quotas.len(), 1);
+        assert_eq!(quotas[0]["client"], "key:client");
+        assert_eq!(quotas[0]["class"], "local");
+        assert_eq!(quotas[0]["used_today"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_admin_endpoints_need_authentication() {
+        let app = create_app(AppState::new(Arc::new(NlpProcessor::new().await.unwrap())));
+        for uri in ["/admin/quotas", "/admin/callbacks", "/admin/actions", "/admin/downloads", "/admin/sessions"] {
+            let response = app.clone().oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap()).await.unwrap();
+            assert_eq!(response.status(), 403, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_admin_callbacks() {
+        let app = create_auth_app().await;
+        let list_callbacks = |key: &str| {
+            Request::builder()
+                .uri("/admin/callbacks")
+                .header("x-api-key", key)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(list_callbacks("nlp-key")).await.unwrap();
+        assert_eq!(response.status(), 403);
+
+        let response = app.oneshot(list_callbacks("admin-key")).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let names: Vec<&str> = json.as_array().unwrap().iter().map(|c| c["name"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["system", "nlp"]);
+    }
+
+    #[tokio::test]
+    async fn test_confirm_action_tokens() {
+        use crate::config::{ActionsConfig, ApiKeyConfig, AuthConfig, ExecutorConfig};
+
+        let authenticator = Authenticator::from_config(&AuthConfig {
+            enabled: true,
+            token_secret: None,
+            api_keys: vec![
+                ApiKeyConfig {
+                    name: "admin".to_string(),
+                    key: "admin-key".to_string(),
+                    allowed_tasks: None,
+                    admin: true,
+                },
+                ApiKeyConfig {
+                    name: "client".to_string(),
+                    key: "client-key".to_string(),
+                    allowed_tasks: None,
+                    admin: false,
+                },
+            ],
+        });
+        let actions = ActionStore::from_config(
+            &ActionsConfig {
+                enabled: true,
+                ..ActionsConfig::default()
+            },
+            &ExecutorConfig::default(),
+        )
+        .unwrap();
+        let nlp_processor = Arc::new(NlpProcessor::new().await.unwrap());
+        let app = create_app(
+            AppState::new(nlp_processor)
+                .with_authenticator(authenticator)
+                .with_actions(actions),
+        );
+
+        let body = serde_json::json!({ "text": "ripgrep", "task": "install", "platform": "macos" });
+        let (status, json) = post_with_key(app.clone(), "/process", Some("client-key"), body).await;
+        assert_eq!(status, 200);
+        let actions = json["actions"].as_array().unwrap();
+        assert_eq!(actions[0]["command"], "brew install ripgrep");
+        let token = actions[0]["token"].as_str().unwrap();
+        let confirm_uri = format!("/actions/{}/confirm", token);
+
+        // Tokens are bound to the client they were issued to
+        let (status, json) = post_with_key(app.clone(), &confirm_uri, Some("admin-key"), serde_json::json!({})).await;
+        assert_eq!(status, 403);
+        assert_eq!(json["error"], "forbidden");
+
+        let (status, json) = post_with_key(app.clone(), &confirm_uri, Some("client-key"), serde_json::json!({})).await;
+        assert_eq!(status, 200);
+        assert_eq!(json["task"], "install");
+        assert_eq!(json["command"]["program"], "brew");
+        assert_eq!(json["confirmed_by"], "client");
+        assert!(json.get("execution").is_none());
+
+        let (status, json) = post_with_key(app.clone(), &confirm_uri, Some("client-key"), serde_json::json!({})).await;
+        assert_eq!(status, 409);
+        assert_eq!(json["error"], "already_used");
+        let (status, _) = post_with_key(app.clone(), "/actions/unknown/confirm", Some("client-key"), serde_json::json!({})).await;
+        assert_eq!(status, 404);
+
+        // Commands suggested by the task a natural language request ran get tokens too
+        let body = serde_json::json!({ "text": "install ripgrep", "task": "natural_language", "platform": "macos" });
+        let (status, json) = post_with_key(app.clone(), "/process", Some("client-key"), body).await;
+        assert_eq!(status, 200);
+        assert_eq!(json["result"]["intent"], "install");
+        assert_eq!(json["actions"][0]["command"], "brew install ripgrep");
+
+        let list_actions = |key: &str| {
+            Request::builder()
+                .uri("/admin/actions")
+                .header("x-api-key", key)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app.clone().oneshot(list_actions("client-key")).await.unwrap();
+        assert_eq!(response.status(), 403);
+        let response = app.oneshot(list_actions("admin-key")).await.unwrap();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let outcomes: Vec<&str> = json.as_array().unwrap().iter().map(|e| e["outcome"].as_str().unwrap()).collect();
+        assert_eq!(outcomes, vec!["wrong_client", "confirmed", "replayed"]);
+    }
+
+    #[tokio::test]
+    async fn test_confirm_action_for_a_removed_task_is_forbidden() {
+        use crate::config::{ActionsConfig, ExecutorConfig};
+
+        let actions = ActionStore::from_config(
+            &ActionsConfig {
+                enabled: true,
+                ..ActionsConfig::default()
+            },
+            &ExecutorConfig::default(),
+        )
+        .unwrap();
+        let state = AppState::new(Arc::new(NlpProcessor::new().await.unwrap())).with_actions(actions);
+        let body = serde_json::json!({ "text": "ripgrep", "task": "install", "platform": "macos" });
+        let (_, json) = post_with_key(create_app(state.clone()), "/process", None, body).await;
+        let confirm_uri = format!("/actions/{}/confirm", json["actions"][0]["token"].as_str().unwrap());
+
+        // The same tokens, served by tasks that no longer include `install`
+        let mut registry = TaskRegistry::with_builtin_tasks();
+        assert!(registry.unregister("install"));
+        let state = AppState {
+            nlp_processor: Arc::new(NlpProcessor::with_registry(registry).await.unwrap()),
+            ..state
+        };
+        let (status, json) = post_with_key(create_app(state), &confirm_uri, None, serde_json::json!({})).await;
+        assert_eq!(status, 403);
+        assert_eq!(json["error"], "forbidden");
+    }
+
+    #[tokio::test]
+    async fn test_download_status() {
+        let mut config = crate::config::Config::default();
+        config.tasks.download.enabled = true;
+        config.tasks.download.directory = std::env::temp_dir().join(format!("rustlm-downloads-{}", Uuid::new_v4()));
+        let nlp_processor = Arc::new(NlpProcessor::from_config(&config).await.unwrap());
+        let app = create_app(AppState::new(nlp_processor));
+
+        // Suggesting commands doesn't download anything unless the caller asks for it
+        let body = serde_json::json!({ "text": "https://example.com/tool.tar.gz", "task": "get_file_from" });
+        let (status, json) = post_with_key(app.clone(), "/process", None, body).await;
+        assert_eq!(status, 200);
+        assert!(json["result"].get("download").is_none());
+
+        // Loopback addresses are refused, so the download fails, but its status can still be looked up
+        let body = serde_json::json!({ "text": "http://127.0.0.1:9/tool.tar.gz", "task": "get_file_from", "download": true });
+        let (status, json) = post_with_key(app.clone(), "/process", None, body).await;
+        assert_eq!(status, 200);
+        let download = &json["result"]["download"];
+        assert_eq!(download["file"], "tool.tar.gz");
+        assert_eq!(json["result"]["suggested_commands"][0], "curl -O http://127.0.0.1:9/tool.tar.gz");
+
+        let get = |uri: String| Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(get(format!("/downloads/{}", download["id"].as_str().unwrap()))).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["url"], "http://127.0.0.1:9/tool.tar.gz");
+
+        let response = app.oneshot(get("/downloads/not-a-download".to_string())).await.unwrap();
+        assert_eq!(response.status(), 404);
+        std::fs::remove_dir_all(&config.tasks.download.directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_status_is_private_to_its_client() {
+        use crate::config::{ApiKeyConfig, AuthConfig};
+
+        let mut config = crate::config::Config::default();
+        config.tasks.download.enabled = true;
+        config.tasks.download.directory = std::env::temp_dir().join(format!("rustlm-downloads-{}", Uuid::new_v4()));
+        let key = |name: &str, admin: bool| ApiKeyConfig {
+            name: name.to_string(),
+            key: format!("{}-key", name),
+            allowed_tasks: None,
+            admin,
+        };
+        let authenticator = Authenticator::from_config(&AuthConfig {
+            enabled: true,
+            token_secret: None,
+            api_keys: vec![key("alice", false), key("bob", false), key("admin", true)],
+        });
+        let nlp_processor = Arc::new(NlpProcessor::from_config(&config).await.unwrap());
+        let app = create_app(AppState::new(nlp_processor).with_authenticator(authenticator));
+
+        let body = serde_json::json!({ "text": "http://127.0.0.1:9/tool.tar.gz", "task": "get_file_from", "download": true });
+        let (status, json) = post_with_key(app.clone(), "/process", Some("alice-key"), body).await;
+        assert_eq!(status, 200);
+        let id = json["result"]["download"]["id"].as_str().unwrap().to_string();
+
+        let get = |uri: &str, key: &str| {
+            Request::builder().uri(uri).header("x-api-key", key).body(Body::empty()).unwrap()
+        };
+        let uri = format!("/downloads/{}", id);
+        let response = app.clone().oneshot(get(&uri, "alice-key")).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let response = app.clone().oneshot(get(&uri, "bob-key")).await.unwrap();
+        assert_eq!(response.status(), 404);
+
+        // Admins still see every download
+        let response = app.oneshot(get("/admin/downloads", "admin-key")).await.unwrap();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json[0]["id"], id.as_str());
+        assert_eq!(json[0]["client_id"], "key:alice");
+        std::fs::remove_dir_all(&config.tasks.download.directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ask_ai_sessions() {
+        use crate::config::SessionsConfig;
+
+        let endpoint = spawn_mock_azure().await;
+        let mut registry = TaskRegistry::with_builtin_tasks();
+        let provider = AzureOpenAiConfig::new(&endpoint, "test-key", "gpt");
+        registry.register(AskAiTask::with_provider(Arc::new(provider)).with_system_prompt("Be brief."));
+        let nlp_processor = Arc::new(NlpProcessor::with_registry(registry).await.unwrap());
+        let app = create_app(AppState::new(nlp_processor).with_sessions(SessionStore::from_config(&SessionsConfig::default())));
+
+        let (status, json) =
+            post_with_key(app.clone(), "/process/ask_ai", None, serde_json::json!({ "text": "Hi", "session_id": "chat-1" })).await;
+        assert_eq!(status, 200);
+        assert_eq!(json["session_id"], "chat-1");
+
+        // The second turn is sent with the first, under the request's system prompt
+        let body = serde_json::json!({
+            "text": "history",
+            "task": "ask_ai",
+            "session_id": "chat-1",
+            "system_prompt": "Talk like a pirate."
+        });
+        let (_, json) = post_with_key(app.clone(), "/process", None, body).await;
+        assert_eq!(json["result"]["answer"], "Talk like a pirate. | Hi | Hello world | history");
+
+        // Without a session the question is sent on its own, after the configured system prompt
+        let (_, json) = post_with_key(app.clone(), "/process/ask_ai", None, serde_json::json!({ "text": "history" })).await;
+        assert_eq!(json["result"]["answer"], "Be brief. | history");
+        assert!(json.get("session_id").is_none());
+
+        let body = serde_json::json!({ "text": "Hi", "session_id": "../chat" });
+        assert_eq!(post_with_key(app.clone(), "/process/ask_ai", None, body).await.0, 422);
+
+        let request = |method: &str, uri: &str| Request::builder().method(method).uri(uri).body(Body::empty()).unwrap();
+        let read = |response: Response| async move {
+            let status = response.status().as_u16();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap_or_default())
+        };
+
+        let (status, json) = read(app.clone().oneshot(request("GET", "/sessions")).await.unwrap()).await;
+        assert_eq!(status, 200);
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        assert_eq!(json[0]["messages"], 4);
+
+        let (_, json) = read(app.clone().oneshot(request("GET", "/sessions/chat-1")).await.unwrap()).await;
+        assert_eq!(json["system_prompt"], "Talk like a pirate.");
+        assert_eq!(json["messages"][3]["role"], "assistant");
+
+        let (status, _) = read(app.clone().oneshot(request("DELETE", "/sessions/chat-1")).await.unwrap()).await;
+        assert_eq!(status, 204);
+        let (status, json) = read(app.oneshot(request("GET", "/sessions/chat-1")).await.unwrap()).await;
+        assert_eq!(status, 404);
+        assert_eq!(json["error"], "not_found");
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint() {
+        let nlp_processor = Arc::new(NlpProcessor::new().await.unwrap());
+        let app = create_app(AppState::new(nlp_processor));
+
+        let body = serde_json::json!({ "text": "I love Rust!" });
+        post_with_key(app.clone(), "/process/sentiment", None, body.clone()).await;
+        post_with_key(app.clone(), "/process/no_such_task", None, body).await;
+
+        let response = app
+            .oneshot(Request::builder().uri("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/plain"));
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+
+        assert!(text.contains(r#"rustlm_requests_total{task="sentiment"}"#));
+        assert!(text.contains(r#"rustlm_request_errors_total{code="unknown_task",task="unknown"}"#));
+        assert!(text.contains(r#"rustlm_callbacks_total{handler="nlp",outcome="success"}"#));
+        assert!(!text.contains("no_such_task"));
+    }
+
+    #[tokio::test]
+    async fn test_list_available_models() {
+        let nlp_processor = Arc::new(NlpProcessor::new().await.unwrap());
+        let state = AppState::new(nlp_processor);
+        let app = create_app(state);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/models")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert!(json["available_tasks"].is_array());
+        let tasks = json["available_tasks"].as_array().unwrap();
+        assert!(tasks.contains(&serde_json::Value::String("sentiment".to_string())));
+
+        let described = json["tasks"].as_array().unwrap();
+        assert_eq!(described.len(), tasks.len());
+        assert!(described.iter().any(|t| t["name"] == "install" && t["category"] == "system"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_task() {
+        let nlp_processor = Arc::new(NlpProcessor::new().await.unwrap());
+        let state = AppState::new(nlp_processor);
+        let app = create_app(state);
+
+        let request_body = serde_json::json!({ "text": "Test text", "task": "invalid_task" });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/process")
+                    .header("content-type", "application/json")
+                    .body(Body::from(request_body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 404);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"], "unknown_task");
+    }
+
+    #[tokio::test]
+    async fn test_empty_text() {
+        let nlp_processor = Arc::new(NlpProcessor::new().await.unwrap());
+        let state = AppState::new(nlp_processor);
+        let app = create_app(state);
+
+        let request_body = serde_json::json!({ "text": "", "task": "sentiment" });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/process")
+                    .header("content-type", "application/json")
+                    .body(Body::from(request_body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 422);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"], "validation_failed");
+    }
+
+    #[tokio::test]
+    async fn test_malformed_body() {
+        let nlp_processor = Arc::new(NlpProcessor::new().await.unwrap());
+        let state = AppState::new(nlp_processor);
+        let app = create_app(state);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/process")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"text": "missing task"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 400);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"], "invalid_request");
+    }
+
+    #[tokio::test]
+    async fn test_ask_ai_local_provider() {
+        use crate::llm::LocalLlmConfig;
+
+        let endpoint = format!("{}/v1", spawn_mock_azure().await);
+        let ask = |provider: LocalLlmConfig| async move {
+            let mut registry = TaskRegistry::with_builtin_tasks();
+            registry.register(AskAiTask::with_provider(Arc::new(provider)));
+            let app = create_app(AppState::new(Arc::new(NlpProcessor::with_registry(registry).await.unwrap())));
+            post_with_key(app, "/process/ask_ai", None, serde_json::json!({ "text": "Hi" })).await
+        };
+
+        let (status, json) = ask(LocalLlmConfig::new(&endpoint).with_model("llama3.2").with_api_key("local-key")).await;
+        assert_eq!(status, 200);
+        assert_eq!(json["result"]["answer"], "Hello from llama3.2");
+        assert_eq!(json["result"]["source"], "local");
+
+        let (status, json) = ask(LocalLlmConfig::new(&endpoint)).await;
+        assert_eq!(status, 502);
+        assert!(json["message"].as_str().unwrap().contains("local returned 401"));
+    }
+
+    #[tokio::test]
+    async fn test_ask_ai_upstream_failure() {
+        let app = create_mock_ai_app().await;
+
+        let request_body = serde_json::json!({ "text": "fail" });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/process/ask_ai")
+                    .header("content-type", "application/json")
+                    .body(Body::from(request_body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 502);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"], "upstream_failed");
+        assert!(json["message"].as_str().unwrap().contains("500"));
+    }
+
+    #[tokio::test]
+    async fn test_ask_ai_provider_asks_to_retry_later() {
+        let app = create_mock_ai_app().await;
+
+        // The provider asks for a longer wait than the client retries after, so the caller is told to
+        let request = Request::builder()
+            .method("POST")
+            .uri("/process/ask_ai")
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::json!({ "text": "busy" }).to_string()))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), 503);
+        assert_eq!(response.headers()["retry-after"], "120");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"], "upstream_unavailable");
+        assert!(json["message"].as_str().unwrap().contains("slow down"));
+    }
+}
@@ -0,0 +1,345 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::config::SessionsConfig;
+use crate::error::ProcessError;
+use crate::llm::{ChatMessage, ChatRole};
+
+/// Longest session id accepted
+const MAX_SESSION_ID_LEN: usize = 128;
+
+/// Tokens counted for each message on top of its content, for the role and separators
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// A conversation with the LLM provider, continued across `ask_ai` requests
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    pub id: String,
+    /// Only this client can see or continue the session
+    pub client_id: String,
+    /// Replaces the configured system prompt for the rest of the session
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    /// Questions and answers, oldest first, truncated to the token budget
+    pub messages: Vec<ChatMessage>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A session without its messages, as listed by `/sessions`
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSummary {
+    pub id: String,
+    pub client_id: String,
+    pub messages: usize,
+    /// Estimated size of the history
+    pub tokens: usize,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<&Session> for SessionSummary {
+    fn from(session: &Session) -> Self {
+        Self {
+            id: session.id.clone(),
+            client_id: session.client_id.clone(),
+            messages: session.messages.len(),
+            tokens: session.messages.iter().map(estimate_tokens).sum(),
+            created_at: session.created_at,
+            updated_at: session.updated_at,
+        }
+    }
+}
+
+/// Rough size of a message in tokens, at about four characters per token
+pub fn estimate_tokens(message: &ChatMessage) -> usize {
+    message.content.chars().count().div_ceil(4) + MESSAGE_OVERHEAD_TOKENS
+}
+
+/// Check that a session id chosen by a client is usable
+pub fn validate_session_id(id: &str) -> Result<(), ProcessError> {
+    let valid_chars = id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if id.is_empty() || id.len() > MAX_SESSION_ID_LEN || !valid_chars {
+        return Err(ProcessError::Validation(format!(
+            "session_id must be 1 to {} letters, digits, '-', '_' or '.'",
+            MAX_SESSION_ID_LEN
+        )));
+    }
+    Ok(())
+}
+
+/// Drop the oldest messages until the rest fit in `budget` tokens; the last message is always kept
+fn truncate(messages: &mut Vec<ChatMessage>, budget: usize) {
+    let mut total: usize = messages.iter().map(estimate_tokens).sum();
+    let mut dropped = 0;
+    while total > budget && dropped + 1 < messages.len() {
+        total -= estimate_tokens(&messages[dropped]);
+        dropped += 1;
+    }
+    // An answer whose question was dropped would start the history mid-turn
+    if dropped > 0 && dropped + 1 < messages.len() && messages[dropped].role == ChatRole::Assistant {
+        dropped += 1;
+    }
+    messages.drain(..dropped);
+}
+
+/// Conversation history for `ask_ai`, kept in memory per client and session id
+pub struct SessionStore {
+    enabled: bool,
+    max_history_tokens: usize,
+    idle_timeout: chrono::Duration,
+    max_sessions: usize,
+    /// Sessions by client and session id, so clients can't reach each other's sessions
+    sessions: Mutex<HashMap<(String, String), Session>>,
+}
+
+impl SessionStore {
+    /// A store that keeps no history; questions are sent on their own
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            max_history_tokens: 0,
+            idle_timeout: chrono::Duration::zero(),
+            max_sessions: 0,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_config(config: &SessionsConfig) -> Self {
+        if !config.enabled {
+            return Self::disabled();
+        }
+        Self {
+            enabled: true,
+            max_history_tokens: config.max_history_tokens,
+            idle_timeout: chrono::Duration::seconds(config.idle_timeout_secs as i64),
+            max_sessions: config.max_sessions,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Messages to send for `question`: the system prompt, as much of the history as fits in the
+    /// token budget, then the question
+    ///
+    /// A system prompt given with the request wins over the session's, which wins over `default_prompt`.
+    pub fn messages(
+        &self,
+        client_id: &str,
+        id: &str,
+        question: &str,
+        request_prompt: Option<&str>,
+        default_prompt: Option<&str>,
+    ) -> Vec<ChatMessage> {
+        let session = self.get(client_id, id);
+        let system_prompt = request_prompt
+            .or(session.as_ref().and_then(|session| session.system_prompt.as_deref()))
+            .or(default_prompt)
+            .map(ChatMessage::system);
+
+        let mut messages = session.map(|session| session.messages).unwrap_or_default();
+        messages.push(ChatMessage::user(question));
+        if self.enabled {
+            let budget = self.max_history_tokens.saturating_sub(system_prompt.as_ref().map_or(0, estimate_tokens));
+            truncate(&mut messages, budget);
+        }
+        system_prompt.into_iter().chain(messages).collect()
+    }
+
+    /// Add a question and its answer to the session, starting it if it's new
+    pub fn record(&self, client_id: &str, id: &str, system_prompt: Option<&str>, question: &str, answer: &str) {
+        if !self.enabled {
+            return;
+        }
+        let now = Utc::now();
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        self.prune(&mut sessions, now);
+
+        let key = (client_id.to_string(), id.to_string());
+        if !sessions.contains_key(&key) && sessions.len() >= self.max_sessions {
+            let oldest = sessions.iter().min_by_key(|(_, session)| session.updated_at).map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                sessions.remove(&oldest);
+            }
+        }
+        let session = sessions.entry(key).or_insert_with(|| Session {
+            id: id.to_string(),
+            client_id: client_id.to_string(),
+            system_prompt: None,
+            messages: Vec::new(),
+            created_at: now,
+            updated_at: now,
+        });
+        if let Some(prompt) = system_prompt {
+            session.system_prompt = Some(prompt.to_string());
+        }
+        session.messages.push(ChatMessage::user(question));
+        session.messages.push(ChatMessage::assistant(answer));
+        truncate(&mut session.messages, self.max_history_tokens);
+        session.updated_at = now;
+    }
+
+    pub fn get(&self, client_id: &str, id: &str) -> Option<Session> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        self.prune(&mut sessions, Utc::now());
+        sessions.get(&(client_id.to_string(), id.to_string())).cloned()
+    }
+
+    /// Sessions of `client_id`, or of every client when `None`, most recently used first
+    pub fn list(&self, client_id: Option<&str>) -> Vec<SessionSummary> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        self.prune(&mut sessions, Utc::now());
+        let mut summaries: Vec<SessionSummary> = sessions
+            .values()
+            .filter(|session| client_id.is_none_or(|client_id| session.client_id == client_id))
+            .map(SessionSummary::from)
+            .collect();
+        summaries.sort_by_key(|summary| std::cmp::Reverse(summary.updated_at));
+        summaries
+    }
+
+    /// Forget a session, returning whether it existed
+    pub fn delete(&self, client_id: &str, id: &str) -> bool {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions.remove(&(client_id.to_string(), id.to_string())).is_some()
+    }
+
+    fn prune(&self, sessions: &mut HashMap<(String, String), Session>, now: DateTime<Utc>) {
+        let cutoff = now - self.idle_timeout;
+        sessions.retain(|_, session| session.updated_at > cutoff);
+    }
+}
+
+/// A client's session, carried with requests that give a `session_id`
+#[derive(Clone)]
+pub struct SessionHandle {
+    store: Arc<SessionStore>,
+    client_id: String,
+    id: String,
+}
+
+impl SessionHandle {
+    pub fn new(store: Arc<SessionStore>, client_id: &str, id: &str) -> Self {
+        Self {
+            store,
+            client_id: client_id.to_string(),
+            id: id.to_string(),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Messages to send for `question`, see [`SessionStore::messages`]
+    pub fn messages(&self, question: &str, request_prompt: Option<&str>, default_prompt: Option<&str>) -> Vec<ChatMessage> {
+        self.store.messages(&self.client_id, &self.id, question, request_prompt, default_prompt)
+    }
+
+    pub fn record(&self, system_prompt: Option<&str>, question: &str, answer: &str) {
+        self.store.record(&self.client_id, &self.id, system_prompt, question, answer);
+    }
+}
+
+impl std::fmt::Debug for SessionHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionHandle").field("client_id", &self.client_id).field("id", &self.id).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(max_history_tokens: usize) -> SessionStore {
+        SessionStore::from_config(&SessionsConfig {
+            max_history_tokens,
+            ..SessionsConfig::default()
+        })
+    }
+
+    fn contents(messages: &[ChatMessage]) -> Vec<&str> {
+        messages.iter().map(|message| message.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_history_is_truncated_to_the_token_budget() {
+        // Each of these messages is estimated at 6 tokens
+        let store = store(30);
+        store.record("key:app", "chat", None, "query-01", "reply-01");
+        store.record("key:app", "chat", None, "query-02", "reply-02");
+        assert_eq!(store.get("key:app", "chat").unwrap().messages.len(), 4);
+
+        // The oldest turn no longer fits alongside the new one
+        store.record("key:app", "chat", None, "query-03", "reply-03");
+        let session = store.get("key:app", "chat").unwrap();
+        assert_eq!(contents(&session.messages), vec!["query-02", "reply-02", "query-03", "reply-03"]);
+
+        // The system prompt and question come out of the same budget
+        let messages = store.messages("key:app", "chat", "query-04", Some("be brief"), None);
+        assert_eq!(contents(&messages), vec!["be brief", "query-03", "reply-03", "query-04"]);
+        assert_eq!(messages[0].role, ChatRole::System);
+
+        // A question is sent even when it alone is over budget
+        let long = "x".repeat(400);
+        assert_eq!(contents(&store.messages("key:app", "chat", &long, None, None)), vec![long.as_str()]);
+    }
+
+    #[test]
+    fn test_system_prompt_precedence() {
+        let store = store(4000);
+        let messages = store.messages("key:app", "chat", "hi", None, Some("default"));
+        assert_eq!(contents(&messages), vec!["default", "hi"]);
+
+        store.record("key:app", "chat", Some("pirate"), "hi", "ahoy");
+        let messages = store.messages("key:app", "chat", "again", None, Some("default"));
+        assert_eq!(contents(&messages), vec!["pirate", "hi", "ahoy", "again"]);
+        let messages = store.messages("key:app", "chat", "again", Some("robot"), Some("default"));
+        assert_eq!(messages[0].content, "robot");
+    }
+
+    #[test]
+    fn test_sessions_are_scoped_to_the_client() {
+        let store = store(4000);
+        store.record("key:app", "chat", None, "secret question", "secret answer");
+
+        assert!(store.get("key:other", "chat").is_none());
+        assert_eq!(store.messages("key:other", "chat", "hi", None, None).len(), 1);
+        assert!(store.list(Some("key:other")).is_empty());
+        assert!(!store.delete("key:other", "chat"));
+
+        let summaries = store.list(None);
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].messages, 2);
+        assert!(store.delete("key:app", "chat"));
+        assert!(store.get("key:app", "chat").is_none());
+    }
+
+    #[test]
+    fn test_session_limits() {
+        let store = SessionStore::from_config(&SessionsConfig {
+            max_sessions: 2,
+            ..SessionsConfig::default()
+        });
+        for id in ["one", "two", "three"] {
+            store.record("key:app", id, None, "q", "a");
+        }
+        let ids: Vec<String> = store.list(None).into_iter().map(|summary| summary.id).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(!ids.contains(&"one".to_string()));
+
+        let disabled = SessionStore::disabled();
+        disabled.record("key:app", "chat", None, "q", "a");
+        assert!(disabled.get("key:app", "chat").is_none());
+
+        assert!(validate_session_id("chat-1_a.b").is_ok());
+        assert_eq!(validate_session_id("../etc").unwrap_err().code(), "validation_failed");
+        assert!(validate_session_id("").is_err());
+        assert!(validate_session_id(&"a".repeat(129)).is_err());
+    }
+}
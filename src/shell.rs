@@ -0,0 +1,270 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+use crate::platform::Platform;
+use crate::results::InputCheck;
+
+/// A suggested command as a program and its arguments, never interpreted by a shell
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShellCommand {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl ShellCommand {
+    pub fn new(program: &str) -> Self {
+        Self {
+            program: program.to_string(),
+            args: Vec::new(),
+        }
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Add a positional argument, after `--` if it would otherwise be read as an option
+    pub fn operand(self, operand: &str) -> Self {
+        if operand.starts_with('-') {
+            self.arg("--").arg(operand)
+        } else {
+            self.arg(operand)
+        }
+    }
+
+    /// The program followed by its arguments, as they'd be passed to `exec`
+    pub fn argv(&self) -> Vec<String> {
+        std::iter::once(&self.program).chain(&self.args).cloned().collect()
+    }
+
+    /// The command as it would be typed into a POSIX shell
+    pub fn render(&self) -> String {
+        self.render_with(quote)
+    }
+
+    /// The command as it would be typed into `platform`'s shell: cmd.exe on Windows, a POSIX
+    /// shell everywhere else
+    pub fn render_for(&self, platform: Platform) -> String {
+        match platform {
+            Platform::Windows => self.render_with(quote_cmd),
+            _ => self.render(),
+        }
+    }
+
+    fn render_with(&self, quote: impl Fn(&str) -> Cow<'_, str>) -> String {
+        std::iter::once(self.program.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .map(quote)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Quote a word for a POSIX shell, leaving it bare when that's already safe
+pub fn quote(word: &str) -> Cow<'_, str> {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !word.is_empty() && word.chars().all(is_safe) {
+        Cow::Borrowed(word)
+    } else {
+        Cow::Owned(format!("'{}'", word.replace('\'', r#"'\''"#)))
+    }
+}
+
+/// Remove control characters such as newlines, noting on `input` when any were found
+pub fn strip_control_characters(text: &str, input: &mut InputCheck) -> String {
+    if !text.chars().any(char::is_control) {
+        return text.to_string();
+    }
+    input.sanitized("Removed control characters");
+    text.chars().filter(|c| !c.is_control()).collect()
+}
+
+/// Make a path safe to pass where an option could be expected, e.g. `-rf` becomes `./-rf`
+pub fn option_safe_path(path: &str, input: &mut InputCheck) -> String {
+    if path.starts_with('-') {
+        input.sanitized(format!("Prefixed '{}' with ./ so it isn't read as an option", path));
+        format!("./{}", path)
+    } else {
+        path.to_string()
+    }
+}
+
+/// Check a branch, tag or commit name against Git's ref name rules
+pub fn validate_git_ref(name: &str) -> Result<(), String> {
+    let invalid = |reason: &str| Err(format!("'{}' is not a valid Git ref: {}", name, reason));
+
+    if name.is_empty() || name.len() > 255 {
+        return invalid("must be 1 to 255 characters");
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| c.is_control() || c.is_whitespace() || "~^:?*[\\".contains(*c))
+    {
+        return invalid(&format!("contains '{}'", c.escape_default()));
+    }
+    if name.starts_with('-') {
+        return invalid("starts with '-'");
+    }
+    if name.contains("..") || name.contains("@{") || name.contains("//") || name == "@" {
+        return invalid("contains '..', '@{' or '//'");
+    }
+    if name.split('/').any(|part| part.starts_with('.')) {
+        return invalid("a component starts with '.'");
+    }
+    if name.ends_with('/') || name.ends_with('.') || name.ends_with(".lock") {
+        return invalid("ends with '/', '.' or '.lock'");
+    }
+    Ok(())
+}
+
+/// Check a package name is one package managers accept, e.g. `ripgrep` or `@types/node`
+pub fn validate_package_name(name: &str) -> Result<(), String> {
+    if name.len() > 214 {
+        return Err("Package name is longer than 214 characters".to_string());
+    }
+    if name.starts_with('-') {
+        return Err(format!("Package name '{}' starts with '-'", name));
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || "@/._+-".contains(c)) {
+        return Err(format!(
+            "Package name '{}' may only contain letters, digits and @ / . _ + -",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Parse an http(s) URL with a host
+pub fn validate_url(url: &str) -> Result<reqwest::Url, String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("'{}' is not a valid URL: {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("URL scheme '{}' is not supported, use http or https", parsed.scheme()));
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        return Err(format!("URL '{}' has no host", url));
+    }
+    Ok(parsed)
+}
+
+/// Quote a word for cmd.exe, leaving it bare when that's already safe
+///
+/// Inside double quotes cmd.exe takes `&`, `|`, `<`, `>`, `^` and parentheses literally. A double
+/// quote in the word is written outside the quotes as `\^"`, so cmd.exe doesn't see it end the
+/// quoted part and the program still reads it as a literal quote, and backslashes before a quote
+/// are doubled. cmd.exe still expands `%NAME%` when `NAME` is a defined variable.
+pub fn quote_cmd(word: &str) -> Cow<'_, str> {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_@+=:,./\\-".contains(c);
+    if !word.is_empty() && word.chars().all(is_safe) {
+        return Cow::Borrowed(word);
+    }
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in word.chars() {
+        if c == '"' {
+            quoted.push_str(&"\\".repeat(backslashes));
+            quoted.push_str(r#""\^"""#);
+        } else {
+            quoted.push(c);
+        }
+        backslashes = if c == '\\' { backslashes + 1 } else { 0 };
+    }
+    quoted.push_str(&"\\".repeat(backslashes));
+    quoted.push('"');
+    Cow::Owned(quoted)
+}
+
+/// Check an application name can be used in a path, in AppleScript and with `cmd /C start`
+pub fn validate_app_name(name: &str) -> Result<(), String> {
+    if name.starts_with('-') {
+        return Err(format!("Application name '{}' starts with '-'", name));
+    }
+    if let Some(c) = name.chars().find(|c| "\"\\/&|<>^%".contains(*c)) {
+        return Err(format!("Application name '{}' may not contain '{}'", name, c));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::split_command;
+    use crate::results::InputStatus;
+
+    #[test]
+    fn test_quote_round_trips_through_a_shell_parser() {
+        let command = ShellCommand::new("grep").args(["-r", "-e", "it's $(rm -rf ~); `x`", "."]);
+        let rendered = command.render();
+        assert_eq!(rendered, r#"grep -r -e 'it'\''s $(rm -rf ~); `x`' ."#);
+
+        // Single quotes turn off every expansion, so only the quote itself needs care
+        let words = split_command(&rendered).unwrap();
+        assert_eq!(words[0], command.program);
+        assert_eq!(words[1..], command.args[..]);
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("src/main.rs"), "src/main.rs");
+    }
+
+    #[test]
+    fn test_windows_rendering_quotes_for_cmd() {
+        let render = |command: ShellCommand| command.render_for(Platform::Windows);
+        assert_eq!(
+            render(ShellCommand::new("cmd").args(["/C", "start", "", "Visual Studio Code"])),
+            r#"cmd /C start "" "Visual Studio Code""#
+        );
+        assert_eq!(render(ShellCommand::new("explorer").arg("a b.txt")), r#"explorer "a b.txt""#);
+        // `&` would start a second command if it weren't quoted
+        assert_eq!(render(ShellCommand::new("explorer").arg("a&calc.txt")), r#"explorer "a&calc.txt""#);
+        assert_eq!(render(ShellCommand::new("explorer").arg(r"C:\My Files\")), r#"explorer "C:\My Files\\""#);
+        assert_eq!(quote_cmd(r#"say "hi" & go"#), r#""say "\^""hi"\^"" & go""#);
+        assert_eq!(quote_cmd(r"C:\Windows\notepad.exe"), r"C:\Windows\notepad.exe");
+        assert_eq!(ShellCommand::new("explorer").arg("a b.txt").render_for(Platform::MacOs), "explorer 'a b.txt'");
+    }
+
+    #[test]
+    fn test_operand_after_double_dash() {
+        assert_eq!(ShellCommand::new("fd").operand("-rf").render(), "fd -- -rf");
+        assert_eq!(ShellCommand::new("fd").operand("main.rs").render(), "fd main.rs");
+    }
+
+    #[test]
+    fn test_validate_git_ref() {
+        assert!(validate_git_ref("main").is_ok());
+        assert!(validate_git_ref("feature/login-form").is_ok());
+        assert!(validate_git_ref("a1b2c3d").is_ok());
+        for name in ["", "-f", "a..b", "has space", "tag^", "branch.lock", ".hidden", "a//b", "@"] {
+            assert!(validate_git_ref(name).is_err(), "{} should be rejected", name);
+        }
+    }
+
+    #[test]
+    fn test_sanitizing_marks_input() {
+        let mut input = InputCheck::default();
+        assert_eq!(strip_control_characters("main.rs", &mut input), "main.rs");
+        assert_eq!(input.status, InputStatus::Accepted);
+
+        assert_eq!(strip_control_characters("main\n.rs", &mut input), "main.rs");
+        assert_eq!(option_safe_path("-rf", &mut input), "./-rf");
+        assert_eq!(input.status, InputStatus::Sanitized);
+        assert_eq!(input.issues.len(), 2);
+    }
+
+    #[test]
+    fn test_validate_url_and_package() {
+        assert!(validate_url("https://example.com/file.tar.gz").is_ok());
+        assert!(validate_url("file:///etc/passwd").is_err());
+        assert!(validate_url("not a url").is_err());
+        assert!(validate_package_name("@types/node").is_ok());
+        assert!(validate_package_name("rust; rm -rf /").is_err());
+        assert!(validate_package_name("--global").is_err());
+    }
+}
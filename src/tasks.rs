@@ -0,0 +1,413 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::auth::TaskAccess;
+use crate::error::ProcessError;
+use crate::platform::Platform;
+use crate::ratelimit::{RateClass, RateLimitHandle};
+use crate::results::TaskResult;
+use crate::sessions::SessionHandle;
+
+/// Output produced by a single task run
+#[derive(Debug, Clone)]
+pub struct TaskOutput {
+    pub result: TaskResult,
+    pub confidence: Option<f32>,
+}
+
+impl TaskOutput {
+    pub fn new(result: impl Into<TaskResult>, confidence: Option<f32>) -> Self {
+        Self {
+            result: result.into(),
+            confidence,
+        }
+    }
+}
+
+/// Event emitted by a streaming task run
+#[derive(Debug, Clone)]
+pub enum TaskStreamEvent {
+    /// A chunk of output text
+    Delta(String),
+    /// The final output; always the last event of a successful stream
+    Done(TaskOutput),
+}
+
+/// Stream of events produced by `Task::run_stream`
+pub type TaskStream = BoxStream<'static, Result<TaskStreamEvent>>;
+
+/// Broad grouping of tasks, used when listing them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskCategory {
+    /// Text analysis tasks that run locally
+    Nlp,
+    /// Tasks that suggest or prepare system commands
+    System,
+    /// Tasks that call an LLM provider
+    Llm,
+}
+
+impl TaskCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskCategory::Nlp => "nlp",
+            TaskCategory::System => "system",
+            TaskCategory::Llm => "llm",
+        }
+    }
+}
+
+/// A processing task that can be registered with the `TaskRegistry`
+#[async_trait]
+pub trait Task: Send + Sync {
+    /// Name used to select the task in requests
+    fn name(&self) -> &str;
+
+    /// Human-readable description shown by `/models`
+    fn description(&self) -> &str;
+
+    fn category(&self) -> TaskCategory {
+        TaskCategory::Nlp
+    }
+
+    /// JSON schema describing the expected input
+    fn input_schema(&self) -> serde_json::Value {
+        text_input_schema("Text to process")
+    }
+
+    /// Run the task on the given text
+    async fn run(&self, text: &str, context: &TaskContext<'_>) -> Result<TaskOutput>;
+
+    /// Run the task incrementally; `None` means the task doesn't stream and `run` is used instead
+    async fn run_stream(&self, _text: &str, _context: &TaskContext<'_>) -> Result<Option<TaskStream>> {
+        Ok(None)
+    }
+}
+
+/// Build the input schema for a task that takes a single text argument
+pub fn text_input_schema(description: &str) -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "text": { "type": "string", "description": description }
+        },
+        "required": ["text"]
+    })
+}
+
+/// Per-request options: who the caller is and how the request should be processed
+#[derive(Debug, Clone)]
+pub struct ProcessOptions {
+    /// Tasks the caller may run, including tasks dispatched by `natural_language`
+    pub access: TaskAccess,
+    /// Rate limit the caller's task runs are counted against, if any
+    pub rate_limit: Option<RateLimitHandle>,
+    /// Don't run callbacks after the task
+    pub skip_callbacks: bool,
+    /// Platform to suggest commands for; the server's own when unset
+    pub platform: Option<Platform>,
+    /// Conversation `ask_ai` continues, if any
+    pub session: Option<SessionHandle>,
+    /// Replaces the configured system prompt for `ask_ai`
+    pub system_prompt: Option<String>,
+    /// The caller asked `get_file_from` to download the file on the server
+    pub download: bool,
+    /// Client the request is made for, who alone can look up the downloads it starts
+    pub client_id: Option<String>,
+}
+
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProcessOptions {
+    /// Options allowing every task, without rate limits
+    pub const fn new() -> Self {
+        Self {
+            access: TaskAccess::All,
+            rate_limit: None,
+            skip_callbacks: false,
+            platform: None,
+            session: None,
+            system_prompt: None,
+            download: false,
+            client_id: None,
+        }
+    }
+
+    /// Check that the caller may run `task`
+    pub fn check_access(&self, task: &dyn Task) -> Result<(), ProcessError> {
+        if !self.access.allows(task) {
+            return Err(ProcessError::Forbidden(format!("Not allowed to run task: {}", task.name())));
+        }
+        Ok(())
+    }
+
+    /// Check that the caller may run `task` now, counting the run against its rate limit
+    pub fn authorize(&self, task: &dyn Task) -> Result<(), ProcessError> {
+        self.check_access(task)?;
+        if let Some(rate_limit) = &self.rate_limit {
+            rate_limit.check(task)?;
+        }
+        Ok(())
+    }
+}
+
+static DEFAULT_OPTIONS: ProcessOptions = ProcessOptions::new();
+
+/// Context passed to a running task
+pub struct TaskContext<'a> {
+    registry: &'a TaskRegistry,
+    options: &'a ProcessOptions,
+    /// Rate class the request was already charged under, see `charged_for`
+    charged: Option<RateClass>,
+}
+
+impl<'a> TaskContext<'a> {
+    pub fn new(registry: &'a TaskRegistry) -> Self {
+        Self::with_options(registry, &DEFAULT_OPTIONS)
+    }
+
+    /// Context for the caller described by `options`
+    pub fn with_options(registry: &'a TaskRegistry, options: &'a ProcessOptions) -> Self {
+        Self {
+            registry,
+            options,
+            charged: None,
+        }
+    }
+
+    /// Note that the request was charged for running `task`, so tasks it dispatches to in the
+    /// same rate class aren't charged again
+    pub fn charged_for(mut self, task: &dyn Task) -> Self {
+        self.charged = Some(RateClass::of(task));
+        self
+    }
+
+    pub fn registry(&self) -> &TaskRegistry {
+        self.registry
+    }
+
+    pub fn access(&self) -> &TaskAccess {
+        &self.options.access
+    }
+
+    /// Platform to suggest commands for, from the request or detected on the server
+    pub fn platform(&self) -> Platform {
+        self.options.platform.unwrap_or_else(Platform::detected)
+    }
+
+    pub fn session(&self) -> Option<&SessionHandle> {
+        self.options.session.as_ref()
+    }
+
+    /// System prompt given with the request
+    pub fn system_prompt(&self) -> Option<&str> {
+        self.options.system_prompt.as_deref()
+    }
+
+    /// Whether the caller opted in to server-side downloads
+    pub fn download_requested(&self) -> bool {
+        self.options.download
+    }
+
+    pub fn client_id(&self) -> Option<&str> {
+        self.options.client_id.as_deref()
+    }
+
+    /// Run another registered task, e.g. one selected from a parsed intent
+    pub async fn run_task(&self, name: &str, text: &str) -> Result<TaskOutput> {
+        let task = self
+            .registry
+            .get(name)
+            .ok_or_else(|| ProcessError::UnknownTask(name.to_string()))?;
+        if self.charged == Some(RateClass::of(task.as_ref())) {
+            self.options.check_access(task.as_ref())?;
+        } else {
+            self.options.authorize(task.as_ref())?;
+        }
+        task.run(text, self).await
+    }
+}
+
+/// Description of a registered task
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskInfo {
+    pub name: String,
+    pub description: String,
+    pub category: TaskCategory,
+    pub input_schema: serde_json::Value,
+}
+
+/// Registry of the tasks available to the processor, in registration order
+#[derive(Clone, Default)]
+pub struct TaskRegistry {
+    tasks: Vec<Arc<dyn Task>>,
+}
+
+impl TaskRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry with all built-in tasks
+    pub fn with_builtin_tasks() -> Self {
+        let mut registry = Self::new();
+        crate::nlp::register_builtin_tasks(&mut registry);
+        crate::commands::register_builtin_tasks(&mut registry);
+        registry.register(crate::nlp::NaturalLanguageTask);
+        registry
+    }
+
+    /// Register a task, replacing any task with the same name
+    pub fn register(&mut self, task: impl Task + 'static) {
+        self.register_arc(Arc::new(task));
+    }
+
+    /// Register a shared task, replacing any task with the same name
+    pub fn register_arc(&mut self, task: Arc<dyn Task>) {
+        match self.tasks.iter().position(|t| t.name() == task.name()) {
+            Some(index) => self.tasks[index] = task,
+            None => self.tasks.push(task),
+        }
+    }
+
+    /// Remove a task by name, returning whether it was registered
+    pub fn unregister(&mut self, name: &str) -> bool {
+        let before = self.tasks.len();
+        self.tasks.retain(|t| t.name() != name);
+        self.tasks.len() != before
+    }
+
+    /// Look up a task by name (case-insensitive)
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Task>> {
+        self.tasks
+            .iter()
+            .find(|t| t.name().eq_ignore_ascii_case(name))
+            .cloned()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Names of all registered tasks
+    pub fn names(&self) -> Vec<String> {
+        self.tasks.iter().map(|t| t.name().to_string()).collect()
+    }
+
+    /// Descriptions of all registered tasks
+    pub fn describe(&self) -> Vec<TaskInfo> {
+        self.tasks
+            .iter()
+            .map(|t| TaskInfo {
+                name: t.name().to_string(),
+                description: t.description().to_string(),
+                category: t.category(),
+                input_schema: t.input_schema(),
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct ShoutTask;
+
+    #[async_trait]
+    impl Task for ShoutTask {
+        fn name(&self) -> &str {
+            "shout"
+        }
+
+        fn description(&self) -> &str {
+            "Upper-case the input text"
+        }
+
+        async fn run(&self, text: &str, _context: &TaskContext<'_>) -> Result<TaskOutput> {
+            Ok(TaskOutput::new(
+                TaskResult::Custom(serde_json::json!({ "shouted": text.to_uppercase() })),
+                Some(1.0),
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_custom_task() {
+        let mut registry = TaskRegistry::new();
+        registry.register(ShoutTask);
+
+        assert_eq!(registry.names(), vec!["shout".to_string()]);
+        assert!(registry.contains("SHOUT"));
+
+        let context = TaskContext::new(&registry);
+        let output = context.run_task("shout", "hello").await.unwrap();
+        assert_eq!(output.result.to_value()["shouted"], "HELLO");
+    }
+
+    #[tokio::test]
+    async fn test_register_replaces_task_with_same_name() {
+        let mut registry = TaskRegistry::with_builtin_tasks();
+        let count = registry.len();
+
+        registry.register(ShoutTask);
+        assert_eq!(registry.len(), count + 1);
+        registry.register(ShoutTask);
+        assert_eq!(registry.len(), count + 1);
+
+        assert!(registry.unregister("shout"));
+        assert!(!registry.unregister("shout"));
+        assert_eq!(registry.len(), count);
+    }
+
+    #[tokio::test]
+    async fn test_run_unknown_task() {
+        let registry = TaskRegistry::new();
+        let context = TaskContext::new(&registry);
+
+        let result = context.run_task("missing", "text").await;
+        assert!(result.unwrap_err().to_string().contains("Unsupported task"));
+    }
+
+    #[tokio::test]
+    async fn test_run_task_checks_access() {
+        let registry = TaskRegistry::with_builtin_tasks();
+        let options = ProcessOptions {
+            access: TaskAccess::Only(vec!["nlp".to_string()]),
+            ..ProcessOptions::default()
+        };
+        let context = TaskContext::with_options(&registry, &options);
+
+        assert!(context.run_task("sentiment", "great").await.is_ok());
+        let error = ProcessError::from(context.run_task("install", "rust").await.unwrap_err());
+        assert_eq!(error.code(), "forbidden");
+    }
+
+    #[test]
+    fn test_describe_builtin_tasks() {
+        let registry = TaskRegistry::with_builtin_tasks();
+        let info = registry.describe();
+
+        let install = info.iter().find(|t| t.name == "install").unwrap();
+        assert_eq!(install.category, TaskCategory::System);
+        assert!(!install.description.is_empty());
+        assert_eq!(install.input_schema["required"][0], "text");
+    }
+}
@@ -2,6 +2,8 @@ use network_interface::{NetworkInterface, NetworkInterfaceConfig};
 use std::net::{UdpSocket, SocketAddrV4, Ipv4Addr};
 use std::time::Duration;
 
+use crate::metrics::metrics;
+
 /// Get the local IP address (first non-loopback interface)
 fn get_local_ip() -> Option<String> {
     let network_interfaces = NetworkInterface::show().ok()?;
@@ -20,35 +22,44 @@ fn get_local_ip() -> Option<String> {
     None
 }
 
-#[derive(Debug, Clone)]
+/// UDP port used for announcements and discovery requests unless configured otherwise
+pub const DEFAULT_DISCOVERY_PORT: u16 = 8888;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum AnnouncementMode {
     Periodic(u64),  // Announce every N seconds
     OnRequest,      // Only respond to discovery requests
     Limited(u64, u32), // Announce every N seconds for M times
 }
 
-/// Start UDP discovery service with configurable announcement mode
-pub fn start_discovery_service(port: u16, service_name: &str, shared_key: &str, mode: AnnouncementMode) {
+/// Start UDP discovery service with configurable announcement mode, using `discovery_port` for UDP traffic
+pub fn start_discovery_service(
+    port: u16,
+    service_name: &str,
+    shared_key: &str,
+    mode: AnnouncementMode,
+    discovery_port: u16,
+) {
     match mode {
         AnnouncementMode::Periodic(interval) => {
-            announce_server_periodic(port, service_name, shared_key, interval);
+            announce_server_periodic(port, service_name, shared_key, interval, discovery_port);
         }
         AnnouncementMode::OnRequest => {
-            respond_to_discovery_requests(port, service_name, shared_key);
+            respond_to_discovery_requests(port, service_name, shared_key, discovery_port);
         }
         AnnouncementMode::Limited(interval, count) => {
-            announce_server_limited(port, service_name, shared_key, interval, count);
+            announce_server_limited(port, service_name, shared_key, interval, count, discovery_port);
         }
     }
 }
 
 /// Announce server availability with a shared key via UDP broadcast (original function).
 pub fn announce_server(port: u16, service_name: &str, shared_key: &str) {
-    announce_server_periodic(port, service_name, shared_key, 30);
+    announce_server_periodic(port, service_name, shared_key, 30, DEFAULT_DISCOVERY_PORT);
 }
 
 /// Announce server periodically
-fn announce_server_periodic(port: u16, service_name: &str, shared_key: &str, interval_secs: u64) {
+fn announce_server_periodic(port: u16, service_name: &str, shared_key: &str, interval_secs: u64, discovery_port: u16) {
     // Get the actual local IP address
     let local_ip = get_local_ip().unwrap_or_else(|| "127.0.0.1".to_string());
     println!("Using local IP: {}", local_ip);
@@ -67,7 +78,7 @@ fn announce_server_periodic(port: u16, service_name: &str, shared_key: &str, int
 
     loop {
         // Send the announcement
-        match socket.send_to(announcement.as_bytes(), SocketAddrV4::new(broadcast_address, 8888)) {
+        match socket.send_to(announcement.as_bytes(), SocketAddrV4::new(broadcast_address, discovery_port)) {
             Ok(_) => println!("Announced server at {}:{} with key", local_ip, port),
             Err(e) => println!("Failed to send broadcast: {}", e),
         }
@@ -78,7 +89,14 @@ fn announce_server_periodic(port: u16, service_name: &str, shared_key: &str, int
 }
 
 /// Announce server for a limited number of times
-fn announce_server_limited(port: u16, service_name: &str, shared_key: &str, interval_secs: u64, max_count: u32) {
+fn announce_server_limited(
+    port: u16,
+    service_name: &str,
+    shared_key: &str,
+    interval_secs: u64,
+    max_count: u32,
+    discovery_port: u16,
+) {
     let local_ip = get_local_ip().unwrap_or_else(|| "127.0.0.1".to_string());
     println!("Using local IP: {} (will announce {} times)", local_ip, max_count);
     
@@ -93,7 +111,7 @@ fn announce_server_limited(port: u16, service_name: &str, shared_key: &str, inte
     );
 
     for i in 1..=max_count {
-        match socket.send_to(announcement.as_bytes(), SocketAddrV4::new(broadcast_address, 8888)) {
+        match socket.send_to(announcement.as_bytes(), SocketAddrV4::new(broadcast_address, discovery_port)) {
             Ok(_) => println!("Announced server at {}:{} with key ({}/{})", local_ip, port, i, max_count),
             Err(e) => println!("Failed to send broadcast: {}", e),
         }
@@ -106,12 +124,12 @@ fn announce_server_limited(port: u16, service_name: &str, shared_key: &str, inte
 }
 
 /// Respond to discovery requests only
-fn respond_to_discovery_requests(port: u16, service_name: &str, shared_key: &str) {
+fn respond_to_discovery_requests(port: u16, service_name: &str, shared_key: &str, discovery_port: u16) {
     let local_ip = get_local_ip().unwrap_or_else(|| "127.0.0.1".to_string());
     println!("Using local IP: {} (respond-only mode)", local_ip);
     
-    let socket = UdpSocket::bind((Ipv4Addr::new(0, 0, 0, 0), 8888)).expect("Could not bind discovery socket");
-    println!("Listening for discovery requests on port 8888...");
+    let socket = UdpSocket::bind((Ipv4Addr::new(0, 0, 0, 0), discovery_port)).expect("Could not bind discovery socket");
+    println!("Listening for discovery requests on port {}...", discovery_port);
     
     let response = format!(
         "{{\n  \"service\": \"{}\",\n  \"ip\": \"{}\",\n  \"port\": {},\n  \"key\": \"{}\"\n}}",
@@ -129,7 +147,10 @@ fn respond_to_discovery_requests(port: u16, service_name: &str, shared_key: &str
                 // Check if it's a discovery request
                 if request.contains("DISCOVER") || request.contains("discover") {
                     match socket.send_to(response.as_bytes(), src) {
-                        Ok(_) => println!("Sent response to {}", src),
+                        Ok(_) => {
+                            metrics().observe_discovery_response();
+                            println!("Sent response to {}", src);
+                        }
                         Err(e) => println!("Failed to send response to {}: {}", src, e),
                     }
                 } else {